@@ -0,0 +1,665 @@
+import os
+from collections.abc import Callable, Mapping, Sequence
+
+class NameData:
+    collection: int | None
+    tl_country: int
+    year: int | None
+    authors: list[int]
+    citation_groups: list[int]
+    citation_group: int | None
+    name_id: int
+    def __new__(
+        cls,
+        collection: int | None,
+        tl_country: int,
+        year: int | None,
+        authors: Sequence[int],
+        citation_group: int | Sequence[int],
+        name_id: int,
+    ) -> NameData: ...
+    @staticmethod
+    def query(
+        tl_country: int,
+        year: int | None,
+        authors: Sequence[int],
+        citation_group: int | Sequence[int],
+        name_id: int,
+    ) -> NameData: ...
+
+class Aggregation:
+    Sum: Aggregation
+    MeanWithSupport: Aggregation
+
+class Combiner:
+    Multiplicative: Combiner
+    LogAdditive: Combiner
+    MaxFactor: Combiner
+
+class AuthorOverlapDenominator:
+    Union: AuthorOverlapDenominator
+    Query: AuthorOverlapDenominator
+
+class ScoreMode:
+    Boost: ScoreMode
+    Distance: ScoreMode
+
+class TieBreak:
+    LowestId: TieBreak
+    BestMatch: TieBreak
+
+class Params:
+    country_boost: float
+    cg_boost: float
+    author_boost: float
+    year_factor: float
+    year_boost: float
+    score_cutoff: float
+    probability_cutoff: float
+    author_year_interaction: float
+    length_mismatch_penalty: float
+    below_cutoff_factor: float
+    unknown_bucket_score: float | None
+    normalize_with_unknown: bool
+    aggregation: Aggregation
+    min_support: int
+    ordered_author_overlap: bool
+    min_unknown_prob: float
+    reject_collection: int | None
+    combiner: Combiner
+    year_curve: Sequence[float] | None
+    min_margin: float
+    author_overlap_denominator: AuthorOverlapDenominator
+    country_sentinel: int | None
+    citation_group_sentinel: int | None
+    max_contributions: int
+    author_overlap_exponent: float
+    exclude_same_citation_group: bool
+    tie_break: TieBreak
+    score_mode: ScoreMode
+    mismatch_penalty: float
+    year_distance_scale: float
+    nn_blend: float
+    def __new__(
+        cls,
+        country_boost: float,
+        cg_boost: float,
+        author_boost: float,
+        year_factor: float,
+        year_boost: float,
+        score_cutoff: float,
+        probability_cutoff: float,
+        author_year_interaction: float = 0.0,
+        length_mismatch_penalty: float = 0.0,
+        below_cutoff_factor: float = 0.0,
+        unknown_bucket_score: float | None = None,
+        normalize_with_unknown: bool = True,
+        aggregation: Aggregation = Aggregation.Sum,
+        min_support: int = 1,
+        ordered_author_overlap: bool = False,
+        min_unknown_prob: float = 0.0,
+        reject_collection: int | None = None,
+        combiner: Combiner = Combiner.Multiplicative,
+        year_curve: Sequence[float] | None = None,
+        min_margin: float = 0.0,
+        author_overlap_denominator: AuthorOverlapDenominator = AuthorOverlapDenominator.Union,
+        country_sentinel: int | None = None,
+        citation_group_sentinel: int | None = None,
+        max_contributions: int = 0,
+        author_overlap_exponent: float = 1.0,
+        exclude_same_citation_group: bool = False,
+        tie_break: TieBreak = TieBreak.LowestId,
+        score_mode: ScoreMode = ScoreMode.Boost,
+        mismatch_penalty: float = 1.0,
+        year_distance_scale: float = 0.1,
+        nn_blend: float = 0.0,
+    ) -> Params: ...
+    def validate(self) -> list[str]: ...
+
+class ScoringFunction:
+    false_positive_cost: float
+    false_negative_cost: float
+    partial_credit: float
+    def __new__(
+        cls,
+        false_positive_cost: float,
+        false_negative_cost: float,
+        partial_credit: float = 0.5,
+    ) -> ScoringFunction: ...
+
+class ScoreBreakdown:
+    country: float
+    citation_group: float
+    author: float
+    year: float
+    year_boost: float
+
+class ScoringTable:
+    country_factor: float
+    country_sentinel: int | None
+    citation_group_factor: float
+    citation_group_sentinel: int | None
+    author_factors: list[float]
+    year_decays: list[float]
+    author_year_interaction: float
+    ordered_author_overlap: bool
+    author_overlap_denominator: AuthorOverlapDenominator
+    author_overlap_exponent: float
+    year_boost: float
+    combiner: Combiner
+    def score(self, nam1: NameData, nam2: NameData) -> float: ...
+
+class FieldMatch:
+    country_match: bool
+    cg_match: bool
+    authors_equal: bool
+    shared_author_count: int
+    year_difference: int | None
+
+class PredictionExplanation:
+    collection: int | None
+    probability: float | None
+    margin: float | None
+    influential_name_id: int | None
+    contribution: float | None
+    field_match: FieldMatch | None
+
+class ScoreInfo:
+    score: float
+    correct: int
+    incorrect: int
+    no_value: int
+    partial_correct: int
+    weighted_correct: float
+    weighted_incorrect: float
+    weighted_no_value: float
+    weighted_partial_correct: float
+
+class ScoreStats:
+    count: int
+    min: float
+    max: float
+    mean: float
+    median: float
+    p90: float
+    p99: float
+
+class LatencyStats:
+    count: int
+    min: float
+    mean: float
+    p50: float
+    p95: float
+    p99: float
+    max: float
+
+class CollectionAccuracy:
+    collection: int
+    info: ScoreInfo
+    accuracy: float
+    lower: float
+    upper: float
+
+class PredictionRecord:
+    name_id: int
+    predicted_collection: int | None
+    top_prob: float
+    true_collection: int | None
+    is_correct: bool
+
+class ConfusionExplanation:
+    true_collection: int
+    predicted_collection: int
+    count: int
+    explained: int
+    mean_author_overlap: float | None
+    mean_year_difference: float | None
+    country_match_rate: float | None
+    cg_match_rate: float | None
+
+class PredictionOutcome:
+    class Predicted(PredictionOutcome):
+        collection: int
+        probability: float
+        def __new__(cls, collection: int, probability: float) -> PredictionOutcome.Predicted: ...
+
+    class BelowCutoff(PredictionOutcome):
+        best_probability: float
+        def __new__(cls, best_probability: float) -> PredictionOutcome.BelowCutoff: ...
+
+    class Ambiguous(PredictionOutcome):
+        collection: int
+        probability: float
+        margin: float
+        def __new__(
+            cls, collection: int, probability: float, margin: float
+        ) -> PredictionOutcome.Ambiguous: ...
+
+    class NoCandidates(PredictionOutcome):
+        def __new__(cls) -> PredictionOutcome.NoCandidates: ...
+
+class GridSearchResult:
+    num_combinations: int
+    seconds_per_evaluation: float
+    best_params: Params | None
+    best_info: ScoreInfo | None
+    estimated_seconds: float
+
+class PrecomputedEvaluator:
+    def __new__(
+        cls, train_data: Sequence[NameData], test_data: Sequence[NameData]
+    ) -> PrecomputedEvaluator: ...
+    def evaluate(
+        self, scoring_function: ScoringFunction, params: Params
+    ) -> ScoreInfo: ...
+
+class CachedPredictions:
+    def __new__(
+        cls,
+        train_data: Sequence[NameData],
+        test_data: Sequence[NameData],
+        params: Params,
+    ) -> CachedPredictions: ...
+    @staticmethod
+    def load_or_compute(
+        path: str | os.PathLike[str],
+        train_data: Sequence[NameData],
+        test_data: Sequence[NameData],
+        params: Params,
+    ) -> CachedPredictions: ...
+    def save(self, path: str | os.PathLike[str]) -> None: ...
+    @property
+    def loaded(self) -> bool: ...
+    def at_cutoff(self, scoring_function: ScoringFunction, cutoff: float) -> ScoreInfo: ...
+
+class RawScores:
+    def update(
+        self, data: NameData, new_train_data: Sequence[NameData], params: Params
+    ) -> RawScores: ...
+    def scores(self) -> dict[int, float]: ...
+    def probs(self) -> dict[int, float]: ...
+
+def get_score(
+    nam1: NameData,
+    nam2: NameData,
+    params: Params,
+    extra_boost: Callable[[NameData, NameData], float] | None = None,
+) -> float: ...
+def benchmark_scoring(
+    params: Params, iterations: int = 100000, train_size: int = 1000
+) -> dict[str, float]: ...
+def get_distance(nam1: NameData, nam2: NameData, params: Params) -> float: ...
+def get_score_verbose(
+    nam1: NameData, nam2: NameData, params: Params
+) -> tuple[float, ScoreBreakdown]: ...
+def export_scoring_table(
+    params: Params, max_year_difference: int = 100, max_author_length_difference: int = 20
+) -> ScoringTable: ...
+def field_match(nam1: NameData, nam2: NameData) -> FieldMatch: ...
+def author_overlap(
+    nam1: NameData, nam2: NameData, params: Params | None = None
+) -> float: ...
+def get_probs(
+    data: NameData,
+    train_data: Sequence[NameData],
+    params: Params,
+    allowed_collections: Sequence[int] | None = None,
+    round_to: int | None = None,
+    min_prob: float | None = None,
+    renormalize: bool = False,
+) -> dict[int, float]: ...
+def get_probs_parallel(
+    data: NameData, train_data: Sequence[NameData], params: Params
+) -> dict[int, float]: ...
+def get_raw_scores(
+    data: NameData, train_data: Sequence[NameData], params: Params
+) -> RawScores: ...
+def contribution_counts(
+    data: NameData, train_data: Sequence[NameData], params: Params
+) -> dict[int, int]: ...
+def intern_authors(data: Sequence[NameData]) -> list[NameData]: ...
+def validate_dataset(data: Sequence[NameData]) -> list[str]: ...
+def compute_author_frequencies(train_data: Sequence[NameData]) -> dict[int, int]: ...
+def compute_citation_group_sizes(train_data: Sequence[NameData]) -> dict[int, int]: ...
+def score_matrix_by_collection(
+    queries: Sequence[NameData], train_data: Sequence[NameData], params: Params
+) -> list[dict[int, float]]: ...
+def score_stats(
+    query: NameData, train_data: Sequence[NameData], params: Params
+) -> ScoreStats: ...
+def benchmark_queries(
+    queries: Sequence[NameData], train_data: Sequence[NameData], params: Params
+) -> LatencyStats: ...
+def score_histogram(
+    data: NameData, train_data: Sequence[NameData], params: Params, n_bins: int
+) -> list[tuple[float, float, int]]: ...
+def top_prob_distribution(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    params: Params,
+    n_bins: int,
+) -> list[tuple[float, float, int]]: ...
+def ensemble_probs(
+    data: NameData,
+    train_data_sets: Sequence[Sequence[NameData]],
+    params: Params,
+    weights: Sequence[float] | None = None,
+) -> dict[int, float]: ...
+def gated_top_choice(
+    query: NameData,
+    train_data: Sequence[NameData],
+    params_author: Params,
+    params_year: Params,
+    gate_fn: Callable[[NameData], tuple[float, float]],
+) -> tuple[int, float] | None: ...
+def get_top_choice(
+    data: NameData,
+    train_data: Sequence[NameData],
+    params: Params,
+    allowed_collections: Sequence[int] | None = None,
+) -> tuple[int, float] | None: ...
+def plausible_collection_count(
+    query: NameData, train_data: Sequence[NameData], params: Params, min_prob: float
+) -> int: ...
+def count_ties(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    params: Params,
+    epsilon: float = 1e-12,
+) -> int: ...
+def get_top_k(
+    data: NameData, train_data: Sequence[NameData], params: Params, k: int
+) -> list[tuple[int, float]]: ...
+def get_top_choice_early(
+    data: NameData, train_data: Sequence[NameData], params: Params
+) -> tuple[int, float] | None: ...
+def bagged_top_choice(
+    query: NameData,
+    train_data: Sequence[NameData],
+    params: Params,
+    n_bags: int,
+    seed: int,
+) -> tuple[int, float] | None: ...
+def best_over_params(
+    query: NameData, train_data: Sequence[NameData], params_list: Sequence[Params]
+) -> tuple[int, float, int] | None: ...
+def counterfactual_country(
+    query: NameData,
+    train_data: Sequence[NameData],
+    params: Params,
+    candidate_countries: Sequence[int],
+) -> dict[int, tuple[int, float] | None]: ...
+def nearest_neighbor_choice(
+    query: NameData, train_data: Sequence[NameData], params: Params
+) -> tuple[int, float] | None: ...
+def model_vs_nn_agreement(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> tuple[float, list[int]]: ...
+def prediction_diversity(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> tuple[int, float]: ...
+def most_influential(
+    query: NameData, train_data: Sequence[NameData], params: Params
+) -> tuple[int, float] | None: ...
+def winning_contributions(
+    query: NameData, train_data: Sequence[NameData], params: Params
+) -> list[tuple[int, float]]: ...
+def explain_prediction(
+    query: NameData, train_data: Sequence[NameData], params: Params
+) -> PredictionExplanation: ...
+def top_contributor_sensitivity(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> list[tuple[int, bool]]: ...
+def confusion_matrix(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> dict[tuple[int, int], int]: ...
+def merge_candidates(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    params: Params,
+    confusion_threshold: float,
+) -> list[tuple[int, int, float]]: ...
+def misclassification_report(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> list[ConfusionExplanation]: ...
+def top_choice_explained(
+    data: NameData, train_data: Sequence[NameData], params: Params
+) -> PredictionOutcome: ...
+def predict_batch(
+    data: Sequence[NameData], train_data: Sequence[NameData], params: Params
+) -> list[PredictionOutcome]: ...
+def evaluate_model(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    weights: Sequence[float] | None = None,
+    parents: Mapping[int, int] | None = None,
+    balance_collections: bool = False,
+    leave_one_out: bool = False,
+    exclude_duplicates: bool = False,
+) -> ScoreInfo: ...
+def expected_score(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    test_confidences: Sequence[float],
+    scoring_function: ScoringFunction,
+    params: Params,
+) -> float: ...
+def evaluate_full(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+) -> tuple[ScoreInfo, dict[int, ScoreInfo]]: ...
+def feature_correlation(train_data: Sequence[NameData]) -> dict[str, float]: ...
+def feature_mutual_information(train_data: Sequence[NameData]) -> dict[str, float]: ...
+def evaluate_confident(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    min_confidence: float,
+) -> tuple[ScoreInfo, int]: ...
+def evaluate_calibrated(
+    train_data: Sequence[NameData],
+    calib_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+) -> tuple[float, ScoreInfo, float]: ...
+def accuracy_by_year(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    bin_size: int,
+) -> list[tuple[int, ScoreInfo]]: ...
+def accuracy_by_author_count(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+) -> dict[int, ScoreInfo]: ...
+def simulate_new_collection(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    reassign: Mapping[int, int],
+) -> tuple[ScoreInfo, ScoreInfo]: ...
+def label_noise_estimate(
+    train_data: Sequence[NameData], params: Params
+) -> tuple[float, list[int]]: ...
+def evaluate_forward_only(
+    data: Sequence[NameData], scoring_function: ScoringFunction, params: Params
+) -> ScoreInfo: ...
+def accuracy_by_collection(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    z: float = 1.96,
+) -> list[CollectionAccuracy]: ...
+def evaluate_detailed(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> list[PredictionRecord]: ...
+def expected_abstentions(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> int: ...
+def abstentions_by_cutoff(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    params: Params,
+    cutoffs: Sequence[float],
+) -> list[tuple[float, int]]: ...
+def best_cutoff(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+) -> tuple[float, ScoreInfo]: ...
+def best_cutoff_balanced(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> tuple[float, float]: ...
+def fit_novelty_threshold(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    novel_test_data: Sequence[NameData],
+    params: Params,
+) -> float: ...
+def zero_error_cutoff(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> float | None: ...
+def to_label_arrays(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    params: Params,
+    abstain_label: int = -1,
+) -> tuple[list[int], list[int]]: ...
+def cross_agreement(
+    subset_a: Sequence[NameData], subset_b: Sequence[NameData], params: Params
+) -> float: ...
+def find_near_duplicates(
+    train_data: Sequence[NameData], params: Params, threshold: float
+) -> list[tuple[int, int, float]]: ...
+def cluster_by_similarity(
+    data: Sequence[NameData], params: Params, threshold: float
+) -> list[list[int]]: ...
+def f_beta(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    params: Params,
+    beta: float,
+) -> float: ...
+def break_even_cost(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> float: ...
+def ovr_scores(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> list[tuple[int, float, bool]]: ...
+def nucleus_accuracy(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    params: Params,
+    p: float,
+) -> float: ...
+def prune_train(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> list[NameData]: ...
+def most_influential_train_item(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> tuple[int, int]: ...
+def predictions_digest(
+    train_data: Sequence[NameData], test_data: Sequence[NameData], params: Params
+) -> str: ...
+def write_predictions_parquet(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    params: Params,
+    path: str | os.PathLike[str],
+) -> None: ...
+def per_collection_cutoffs(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    params: Params,
+    target_precision: float,
+) -> dict[int, float]: ...
+def prediction_stability(
+    query: NameData,
+    train_data: Sequence[NameData],
+    params: Params,
+    relative_step: float,
+) -> bool: ...
+def sensitivity(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    relative_step: float,
+) -> dict[str, float]: ...
+def optimize_params(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    step: float = 0.1,
+    max_rounds: int = 10,
+    pinned: Sequence[str] = (),
+) -> Params: ...
+def optimize_params_cached(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    step: float = 0.1,
+    max_rounds: int = 10,
+    pinned: Sequence[str] = (),
+) -> Params: ...
+def scan_parameter(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    param_name: str,
+    values: Sequence[float],
+) -> list[tuple[float, ScoreInfo]]: ...
+def grid_search(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    grid: Mapping[str, Sequence[float]],
+    dry_run: bool = False,
+    pinned: Sequence[str] = (),
+) -> GridSearchResult: ...
+def build_params_grid(grids: Mapping[str, Sequence[float]]) -> list[Params]: ...
+def bottleneck_param(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    candidates: Mapping[str, Sequence[float]],
+) -> tuple[str, float, ScoreInfo]: ...
+def learning_curve(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    scoring_function: ScoringFunction,
+    params: Params,
+    fractions: Sequence[float],
+    seed: int,
+) -> list[tuple[float, ScoreInfo]]: ...
+def fit_boosts(
+    train_data: Sequence[NameData],
+    test_data: Sequence[NameData],
+    initial: Params,
+    learning_rate: float,
+    epochs: int,
+) -> tuple[Params, list[float]]: ...
+def cross_validate(
+    data: Sequence[NameData],
+    num_folds: int,
+    seed: int,
+    scoring_function: ScoringFunction,
+    params: Params,
+) -> list[ScoreInfo]: ...
@@ -1,45 +1,229 @@
+#![allow(clippy::needless_return)]
+
 use pyo3::prelude::*;
 
-#[pyclass(get_all, frozen)]
+fn format_optional<T: std::fmt::Display>(value: Option<T>) -> String {
+    return match value {
+        Some(value) => value.to_string(),
+        None => "None".to_string(),
+    };
+}
+
+fn python_bool(value: bool) -> &'static str {
+    return if value { "True" } else { "False" };
+}
+
+#[pyclass(frozen)]
+#[derive(Clone)]
 struct NameData {
-    collection: i32,
+    #[pyo3(get)]
+    collection: Option<i32>,
+    #[pyo3(get)]
     tl_country: i32,
-    year: i32,
-    authors: Vec<i32>,
-    citation_group: i32,
+    #[pyo3(get)]
+    year: Option<i32>,
+    // Shared between names whose author lists were interned with intern_authors
+    authors: std::sync::Arc<Vec<i32>>,
+    #[pyo3(get)]
+    citation_groups: Vec<i32>,
+    #[pyo3(get)]
     name_id: i32,
+    // Lets most comparisons of different author lists stop without looking at the lists
+    authors_hash: u64,
+}
+
+/// A name's citation groups, given either as a single id or as a list of ids.
+#[derive(FromPyObject)]
+enum CitationGroups {
+    Single(i32),
+    Multiple(Vec<i32>),
+}
+
+impl CitationGroups {
+    fn into_vec(self) -> Vec<i32> {
+        return match self {
+            CitationGroups::Single(citation_group) => vec![citation_group],
+            CitationGroups::Multiple(citation_groups) => citation_groups,
+        };
+    }
 }
 
 #[pymethods]
 impl NameData {
     #[new]
+    #[pyo3(signature = (collection, tl_country, year, authors, citation_group, name_id))]
     fn new(
-        collection: i32,
+        collection: Option<i32>,
         tl_country: i32,
-        year: i32,
+        year: Option<i32>,
         authors: Vec<i32>,
-        citation_group: i32,
+        citation_group: CitationGroups,
         name_id: i32,
     ) -> Self {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        std::hash::Hash::hash(&authors, &mut hasher);
         NameData {
             collection,
             tl_country,
             year,
-            authors,
-            citation_group,
+            authors_hash: std::hash::Hasher::finish(&hasher),
+            authors: std::sync::Arc::new(authors),
+            citation_groups: citation_group.into_vec(),
             name_id,
         }
     }
 
+    /// Creates a name whose collection is not known, such as a name to predict a
+    /// collection for.
+    #[staticmethod]
+    #[pyo3(signature = (tl_country, year, authors, citation_group, name_id))]
+    fn query(
+        tl_country: i32,
+        year: Option<i32>,
+        authors: Vec<i32>,
+        citation_group: CitationGroups,
+        name_id: i32,
+    ) -> Self {
+        return NameData::new(None, tl_country, year, authors, citation_group, name_id);
+    }
+
+    #[getter]
+    fn authors(&self) -> Vec<i32> {
+        return self.authors.to_vec();
+    }
+
+    /// The first citation group, for names that have a single one.
+    #[getter]
+    fn citation_group(&self) -> Option<i32> {
+        return self.citation_groups.first().copied();
+    }
+
     fn __repr__(&self) -> PyResult<String> {
         Ok(format!(
-            "NameData(collection={}, tl_country={}, year={}, authors={:?}, citation_group={}, name_id={})",
-            self.collection, self.tl_country, self.year, self.authors, self.citation_group, self.name_id
+            "NameData(collection={}, tl_country={}, year={}, authors={:?}, citation_groups={:?}, name_id={})",
+            format_optional(self.collection), self.tl_country, format_optional(self.year), self.authors, self.citation_groups, self.name_id
         ))
     }
 }
 
+impl NameData {
+    fn same_authors(&self, other: &NameData) -> bool {
+        if std::sync::Arc::ptr_eq(&self.authors, &other.authors) {
+            return true;
+        }
+        return self.authors_hash == other.authors_hash && self.authors == other.authors;
+    }
+}
+
+/// How the scores of a collection's training names become the collection's score.
+#[pyclass(frozen)]
+#[derive(Clone, Copy, PartialEq)]
+enum Aggregation {
+    /// The sum of the scores.
+    Sum,
+    /// The mean of the scores above the cutoff, if there are at least min_support of them;
+    /// otherwise the collection is left out.
+    MeanWithSupport,
+}
+
+impl Aggregation {
+    fn repr_name(&self) -> &'static str {
+        return match self {
+            Aggregation::Sum => "Aggregation.Sum",
+            Aggregation::MeanWithSupport => "Aggregation.MeanWithSupport",
+        };
+    }
+}
+
+/// How get_score combines its factors (for country, citation groups, authors, year and
+/// year_boost), each of which is 1 when it has no effect.
+#[pyclass(frozen)]
+#[derive(Clone, Copy, PartialEq)]
+enum Combiner {
+    /// The product of the factors.
+    Multiplicative,
+    /// 1 plus the sum of the logarithms of the factors, or 0 if that is negative. Strong
+    /// matches on several fields then add up instead of compounding.
+    LogAdditive,
+    /// The largest factor, so a name scores by its single best matching field.
+    MaxFactor,
+}
+
+impl Combiner {
+    fn repr_name(&self) -> &'static str {
+        return match self {
+            Combiner::Multiplicative => "Combiner.Multiplicative",
+            Combiner::LogAdditive => "Combiner.LogAdditive",
+            Combiner::MaxFactor => "Combiner.MaxFactor",
+        };
+    }
+}
+
+/// What the shared authors are a proportion of when measuring author overlap. In both
+/// cases equal author lists have an overlap of 1.
+#[pyclass(frozen)]
+#[derive(Clone, Copy, PartialEq)]
+enum AuthorOverlapDenominator {
+    /// All authors of either name.
+    Union,
+    /// The authors of the query (the first name), so authors recorded only for the
+    /// training name do not lower the overlap.
+    Query,
+}
+
+impl AuthorOverlapDenominator {
+    fn repr_name(&self) -> &'static str {
+        return match self {
+            AuthorOverlapDenominator::Union => "AuthorOverlapDenominator.Union",
+            AuthorOverlapDenominator::Query => "AuthorOverlapDenominator.Query",
+        };
+    }
+}
+
+/// How get_score compares two names.
+#[pyclass(frozen)]
+#[derive(Clone, Copy, PartialEq)]
+enum ScoreMode {
+    /// The boosts of the matching fields, combined as configured by combiner.
+    Boost,
+    /// e^-distance, where the distance adds mismatch_penalty for a different country and
+    /// for non-overlapping citation groups (a fraction of it for partial overlap), 1 minus
+    /// the author overlap, and year_distance_scale per year of difference. get_distance
+    /// returns the distance itself.
+    Distance,
+}
+
+impl ScoreMode {
+    fn repr_name(&self) -> &'static str {
+        return match self {
+            ScoreMode::Boost => "ScoreMode.Boost",
+            ScoreMode::Distance => "ScoreMode.Distance",
+        };
+    }
+}
+
+/// How predictions pick among collections with the same probability.
+#[pyclass(frozen)]
+#[derive(Clone, Copy, PartialEq)]
+enum TieBreak {
+    /// The lowest collection id.
+    LowestId,
+    /// The collection whose best single training name scored highest, then the lowest id.
+    /// The unknown bucket has no training names, so it loses ties to any collection.
+    BestMatch,
+}
+
+impl TieBreak {
+    fn repr_name(&self) -> &'static str {
+        return match self {
+            TieBreak::LowestId => "TieBreak.LowestId",
+            TieBreak::BestMatch => "TieBreak.BestMatch",
+        };
+    }
+}
+
 #[pyclass(get_all, frozen)]
+#[derive(Clone, PartialEq)]
 struct Params {
     country_boost: f64,
     cg_boost: f64,
@@ -48,11 +232,116 @@ struct Params {
     year_boost: f64,
     score_cutoff: f64,
     probability_cutoff: f64,
+    // How much author overlap softens the year decay: 0 leaves it unchanged, 1 removes it
+    // entirely for names with identical authors.
+    author_year_interaction: f64,
+    // Fraction by which the author boost shrinks per author of difference in list length
+    // when the names share authors.
+    length_mismatch_penalty: f64,
+    // Scores at or below score_cutoff are multiplied by this instead of being
+    // dropped from sums; 0 gives a hard cutoff. They never count towards means or best
+    // matches.
+    below_cutoff_factor: f64,
+    // If set, the unknown bucket (0) receives this constant instead of the highest
+    // single score.
+    unknown_bucket_score: Option<f64>,
+    // Whether the unknown bucket is part of the denominator when normalizing. If not,
+    // the real collections' probabilities sum to 1 and the unknown bucket reports its
+    // share of the total including it.
+    normalize_with_unknown: bool,
+    // How the scores of the training names in a collection are combined.
+    aggregation: Aggregation,
+    // The number of training names above the score cutoff a collection needs under
+    // Aggregation.MeanWithSupport.
+    min_support: i32,
+    // Whether author overlap counts only the shared authors that appear in the same
+    // order in both lists (their longest common subsequence) rather than all of them.
+    ordered_author_overlap: bool,
+    // The lowest probability the unknown bucket may get; the other probabilities are
+    // scaled down to make room. Only applies when normalize_with_unknown is set.
+    min_unknown_prob: f64,
+    // Training names in this collection are known not to belong to any collection, so
+    // their scores go to the unknown bucket.
+    reject_collection: Option<i32>,
+    // How the factors of get_score are combined into a score.
+    combiner: Combiner,
+    // If set, replaces the year_factor decay: entry d is the multiplier for a year
+    // difference of d, and the last entry is used for larger differences.
+    year_curve: Option<Vec<f64>>,
+    // No collection is predicted if the two most probable collections are closer than
+    // this in probability.
+    min_margin: f64,
+    // What the shared authors are a proportion of in author overlap.
+    author_overlap_denominator: AuthorOverlapDenominator,
+    // A tl_country value meaning the country is not known. The country factor is 1 when
+    // either name has it.
+    country_sentinel: Option<i32>,
+    // A citation group meaning the citation group is not known. The citation group
+    // factor is 1 when it is the only citation group of either name.
+    citation_group_sentinel: Option<i32>,
+    // If positive, only this many of the highest-scoring training names contribute to
+    // the collection scores.
+    max_contributions: usize,
+    // The author overlap is raised to this power before it weakens the year decay (and
+    // before it shortens the distance in distance mode), so values above 1 favor
+    // near-complete overlap. The author factor itself depends only on the author list
+    // length difference, not on the overlap, so in boost mode this has no effect unless
+    // author_year_interaction is set. Must be positive.
+    author_overlap_exponent: f64,
+    // If set, training names sharing a citation group with the query are skipped, so that
+    // names from the same publication cannot vouch for each other.
+    exclude_same_citation_group: bool,
+    // How predictions pick among collections with the same probability.
+    tie_break: TieBreak,
+    // Whether pairs are scored by multiplying boosts or by a distance.
+    score_mode: ScoreMode,
+    // In distance mode, the distance added by a different country and by citation groups
+    // that do not overlap at all.
+    mismatch_penalty: f64,
+    // In distance mode, the distance added per year of difference.
+    year_distance_scale: f64,
+    // How far each collection's score moves from its aggregated score (at 0) to the
+    // score of its best single match (at 1).
+    nn_blend: f64,
 }
 
 #[pymethods]
 impl Params {
     #[new]
+    #[pyo3(signature = (
+        country_boost,
+        cg_boost,
+        author_boost,
+        year_factor,
+        year_boost,
+        score_cutoff,
+        probability_cutoff,
+        author_year_interaction=0.0,
+        length_mismatch_penalty=0.0,
+        below_cutoff_factor=0.0,
+        unknown_bucket_score=None,
+        normalize_with_unknown=true,
+        aggregation=Aggregation::Sum,
+        min_support=1,
+        ordered_author_overlap=false,
+        min_unknown_prob=0.0,
+        reject_collection=None,
+        combiner=Combiner::Multiplicative,
+        year_curve=None,
+        min_margin=0.0,
+        author_overlap_denominator=AuthorOverlapDenominator::Union,
+        country_sentinel=None,
+        citation_group_sentinel=None,
+        max_contributions=0,
+        author_overlap_exponent=1.0,
+        exclude_same_citation_group=false,
+        tie_break=TieBreak::LowestId,
+        score_mode=ScoreMode::Boost,
+        mismatch_penalty=1.0,
+        year_distance_scale=0.1,
+        nn_blend=0.0,
+    ))]
+    #[allow(clippy::too_many_arguments)]
     fn new(
         country_boost: f64,
         cg_boost: f64,
@@ -61,8 +350,38 @@ impl Params {
         year_boost: f64,
         score_cutoff: f64,
         probability_cutoff: f64,
-    ) -> Self {
-        Params {
+        author_year_interaction: f64,
+        length_mismatch_penalty: f64,
+        below_cutoff_factor: f64,
+        unknown_bucket_score: Option<f64>,
+        normalize_with_unknown: bool,
+        aggregation: Aggregation,
+        min_support: i32,
+        ordered_author_overlap: bool,
+        min_unknown_prob: f64,
+        reject_collection: Option<i32>,
+        combiner: Combiner,
+        year_curve: Option<Vec<f64>>,
+        min_margin: f64,
+        author_overlap_denominator: AuthorOverlapDenominator,
+        country_sentinel: Option<i32>,
+        citation_group_sentinel: Option<i32>,
+        max_contributions: usize,
+        author_overlap_exponent: f64,
+        exclude_same_citation_group: bool,
+        tie_break: TieBreak,
+        score_mode: ScoreMode,
+        mismatch_penalty: f64,
+        year_distance_scale: f64,
+        nn_blend: f64,
+    ) -> PyResult<Self> {
+        if year_curve.as_ref().is_some_and(|curve| curve.is_empty()) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "year_curve must not be empty",
+            ));
+        }
+        check_author_overlap_exponent(author_overlap_exponent)?;
+        Ok(Params {
             country_boost,
             cg_boost,
             author_boost,
@@ -70,213 +389,10145 @@ impl Params {
             year_boost,
             score_cutoff,
             probability_cutoff,
-        }
+            author_year_interaction,
+            length_mismatch_penalty,
+            below_cutoff_factor,
+            unknown_bucket_score,
+            normalize_with_unknown,
+            aggregation,
+            min_support,
+            ordered_author_overlap,
+            min_unknown_prob,
+            reject_collection,
+            combiner,
+            year_curve,
+            min_margin,
+            author_overlap_denominator,
+            country_sentinel,
+            citation_group_sentinel,
+            max_contributions,
+            author_overlap_exponent,
+            exclude_same_citation_group,
+            tie_break,
+            score_mode,
+            mismatch_penalty,
+            year_distance_scale,
+            nn_blend,
+        })
     }
 
     fn __repr__(&self) -> PyResult<String> {
+        let year_curve = match &self.year_curve {
+            Some(curve) => format!("{:?}", curve),
+            None => "None".to_string(),
+        };
         Ok(format!(
-            "Params(country_boost={:.3}, cg_boost={:.3}, author_boost={:.3}, year_factor={:.3}, year_boost={:.3}, score_cutoff={:.3}, probability_cutoff={:.3})",
-            self.country_boost, self.cg_boost, self.author_boost, self.year_factor, self.year_boost, self.score_cutoff, self.probability_cutoff
+            "Params(country_boost={:.3}, cg_boost={:.3}, author_boost={:.3}, year_factor={:.3}, year_boost={:.3}, score_cutoff={:.3}, probability_cutoff={:.3}, author_year_interaction={:.3}, length_mismatch_penalty={:.3}, below_cutoff_factor={:.3}, unknown_bucket_score={}, normalize_with_unknown={}, aggregation={}, min_support={}, ordered_author_overlap={}, min_unknown_prob={:.3}, reject_collection={}, combiner={}, year_curve={}, min_margin={:.3}, author_overlap_denominator={}, country_sentinel={}, citation_group_sentinel={}, max_contributions={}, author_overlap_exponent={:.3}, exclude_same_citation_group={}, tie_break={}, score_mode={}, mismatch_penalty={:.3}, year_distance_scale={:.3}, nn_blend={:.3})",
+            self.country_boost, self.cg_boost, self.author_boost, self.year_factor, self.year_boost, self.score_cutoff, self.probability_cutoff, self.author_year_interaction, self.length_mismatch_penalty, self.below_cutoff_factor, format_optional(self.unknown_bucket_score), python_bool(self.normalize_with_unknown), self.aggregation.repr_name(), self.min_support, python_bool(self.ordered_author_overlap), self.min_unknown_prob, format_optional(self.reject_collection), self.combiner.repr_name(), year_curve, self.min_margin, self.author_overlap_denominator.repr_name(), format_optional(self.country_sentinel), format_optional(self.citation_group_sentinel), self.max_contributions, self.author_overlap_exponent, python_bool(self.exclude_same_citation_group), self.tie_break.repr_name(), self.score_mode.repr_name(), self.mismatch_penalty, self.year_distance_scale, self.nn_blend
         ))
     }
+
+    /// Warnings about settings that are allowed but probably not intended, such as ones
+    /// under which every name is left unpredicted.
+    fn validate(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for name in PARAM_NAMES {
+            let value = self.get_value(name).expect("PARAM_NAMES are all valid");
+            if !value.is_finite() {
+                warnings.push(format!("{} is {}", name, value));
+            }
+        }
+        for (name, value) in [
+            ("country_boost", self.country_boost),
+            ("cg_boost", self.cg_boost),
+            ("author_boost", self.author_boost),
+            ("year_boost", self.year_boost),
+        ] {
+            if self.score_mode == ScoreMode::Boost && value <= 0.0 {
+                warnings.push(format!(
+                    "{} is {}, so matching names score no higher than others",
+                    name, value
+                ));
+            }
+        }
+        if self.score_mode == ScoreMode::Distance {
+            for (name, value) in [
+                ("mismatch_penalty", self.mismatch_penalty),
+                ("year_distance_scale", self.year_distance_scale),
+            ] {
+                if value < 0.0 {
+                    warnings.push(format!(
+                        "{} is {}, so mismatching names are closer than matching ones",
+                        name, value
+                    ));
+                }
+            }
+        }
+        if self.score_mode == ScoreMode::Boost
+            && self.year_curve.is_none()
+            && self.year_factor < 1.0
+        {
+            warnings.push(format!(
+                "year_factor is {}, so names further apart in time score higher",
+                self.year_factor
+            ));
+        }
+        if let Some(curve) = &self.year_curve {
+            if curve.iter().any(|value| *value < 0.0) {
+                warnings.push("year_curve has negative values".to_string());
+            }
+        }
+        for (name, value) in [
+            ("author_year_interaction", self.author_year_interaction),
+            ("length_mismatch_penalty", self.length_mismatch_penalty),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                warnings.push(format!("{} is {}, outside [0, 1]", name, value));
+            }
+        }
+        if self.below_cutoff_factor == 0.0 {
+            if let Some(max_score) = max_score(self) {
+                if self.score_cutoff >= max_score {
+                    warnings.push(format!(
+                        "score_cutoff is {}, but no pair of names can score above {}, so no training name contributes",
+                        self.score_cutoff, max_score
+                    ));
+                }
+            }
+        }
+        if self.probability_cutoff >= 1.0 {
+            warnings.push(format!(
+                "probability_cutoff is {}, so no name is ever predicted",
+                self.probability_cutoff
+            ));
+        } else if self.probability_cutoff < 0.0 {
+            warnings.push(format!(
+                "probability_cutoff is {}, so names are predicted however unlikely",
+                self.probability_cutoff
+            ));
+        }
+        if self.min_margin > 1.0 {
+            warnings.push(format!(
+                "min_margin is {}, so no name is ever predicted",
+                self.min_margin
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.nn_blend) {
+            warnings.push(format!(
+                "nn_blend is {}, outside the range from summed (0) to nearest-neighbor (1) scores",
+                self.nn_blend
+            ));
+        }
+        if self.min_unknown_prob >= 1.0 {
+            warnings.push(format!(
+                "min_unknown_prob is {}, so the unknown bucket gets all the probability",
+                self.min_unknown_prob
+            ));
+        }
+        if self.aggregation == Aggregation::MeanWithSupport && self.min_support < 1 {
+            warnings.push(format!(
+                "min_support is {}, which is treated as 1",
+                self.min_support
+            ));
+        }
+        return warnings;
+    }
+}
+
+/// The numeric fields of Params that can be varied by name.
+const PARAM_NAMES: [&str; 16] = [
+    "country_boost",
+    "cg_boost",
+    "author_boost",
+    "year_factor",
+    "year_boost",
+    "score_cutoff",
+    "probability_cutoff",
+    "author_year_interaction",
+    "length_mismatch_penalty",
+    "below_cutoff_factor",
+    "min_unknown_prob",
+    "min_margin",
+    "author_overlap_exponent",
+    "mismatch_penalty",
+    "year_distance_scale",
+    "nn_blend",
+];
+
+/// A power of at most 0 would give names sharing no authors full overlap credit, or make
+/// 0^exponent infinite.
+fn check_author_overlap_exponent(value: f64) -> PyResult<()> {
+    if value > 0.0 {
+        return Ok(());
+    }
+    return Err(pyo3::exceptions::PyValueError::new_err(format!(
+        "author_overlap_exponent must be positive, not {}",
+        value
+    )));
+}
+
+fn unknown_param_error(name: &str) -> PyErr {
+    return pyo3::exceptions::PyValueError::new_err(format!("unknown parameter: {}", name));
+}
+
+impl Params {
+    /// country_boost, cg_boost, author_boost and year_boost.
+    fn boosts(&self) -> [f64; 4] {
+        return [
+            self.country_boost,
+            self.cg_boost,
+            self.author_boost,
+            self.year_boost,
+        ];
+    }
+
+    fn with_boosts(&self, boosts: [f64; 4]) -> Params {
+        let mut params = self.clone();
+        [
+            params.country_boost,
+            params.cg_boost,
+            params.author_boost,
+            params.year_boost,
+        ] = boosts;
+        return params;
+    }
+
+    fn get_value(&self, name: &str) -> PyResult<f64> {
+        return Ok(match name {
+            "country_boost" => self.country_boost,
+            "cg_boost" => self.cg_boost,
+            "author_boost" => self.author_boost,
+            "year_factor" => self.year_factor,
+            "year_boost" => self.year_boost,
+            "score_cutoff" => self.score_cutoff,
+            "probability_cutoff" => self.probability_cutoff,
+            "author_year_interaction" => self.author_year_interaction,
+            "length_mismatch_penalty" => self.length_mismatch_penalty,
+            "below_cutoff_factor" => self.below_cutoff_factor,
+            "min_unknown_prob" => self.min_unknown_prob,
+            "min_margin" => self.min_margin,
+            "author_overlap_exponent" => self.author_overlap_exponent,
+            "mismatch_penalty" => self.mismatch_penalty,
+            "year_distance_scale" => self.year_distance_scale,
+            "nn_blend" => self.nn_blend,
+            _ => return Err(unknown_param_error(name)),
+        });
+    }
+
+    fn with_value(&self, name: &str, value: f64) -> PyResult<Params> {
+        let mut params = self.clone();
+        let field = match name {
+            "country_boost" => &mut params.country_boost,
+            "cg_boost" => &mut params.cg_boost,
+            "author_boost" => &mut params.author_boost,
+            "year_factor" => &mut params.year_factor,
+            "year_boost" => &mut params.year_boost,
+            "score_cutoff" => &mut params.score_cutoff,
+            "probability_cutoff" => &mut params.probability_cutoff,
+            "author_year_interaction" => &mut params.author_year_interaction,
+            "length_mismatch_penalty" => &mut params.length_mismatch_penalty,
+            "below_cutoff_factor" => &mut params.below_cutoff_factor,
+            "min_unknown_prob" => &mut params.min_unknown_prob,
+            "min_margin" => &mut params.min_margin,
+            "author_overlap_exponent" => &mut params.author_overlap_exponent,
+            "mismatch_penalty" => &mut params.mismatch_penalty,
+            "year_distance_scale" => &mut params.year_distance_scale,
+            "nn_blend" => &mut params.nn_blend,
+            _ => return Err(unknown_param_error(name)),
+        };
+        *field = value;
+        if name == "author_overlap_exponent" {
+            check_author_overlap_exponent(value)?;
+        }
+        return Ok(params);
+    }
 }
 
 #[pyclass(get_all, frozen)]
 struct ScoringFunction {
     false_positive_cost: f64,
     false_negative_cost: f64,
+    // The reward for predicting a collection with the same parent as the true one, when
+    // evaluating with parents
+    partial_credit: f64,
 }
 
 #[pymethods]
 impl ScoringFunction {
     #[new]
-    fn new(false_positive_cost: f64, false_negative_cost: f64) -> Self {
+    #[pyo3(signature = (false_positive_cost, false_negative_cost, partial_credit=0.5))]
+    fn new(false_positive_cost: f64, false_negative_cost: f64, partial_credit: f64) -> Self {
         ScoringFunction {
             false_positive_cost,
             false_negative_cost,
+            partial_credit,
         }
     }
 
     fn __repr__(&self) -> PyResult<String> {
         Ok(format!(
-            "ScoringFunction(false_positive_cost={}, false_negative_cost={})",
-            self.false_positive_cost, self.false_negative_cost
+            "ScoringFunction(false_positive_cost={}, false_negative_cost={}, partial_credit={})",
+            self.false_positive_cost, self.false_negative_cost, self.partial_credit
         ))
     }
 }
 
-#[pyfunction]
-fn get_score(nam1: &NameData, nam2: &NameData, params: &Params) -> PyResult<f64> {
-    if nam1.name_id == nam2.name_id {
-        return Ok(0.0);
-    }
-    let mut score: f64 = 1.0;
-    if nam1.tl_country == nam2.tl_country {
-        score *= params.country_boost;
+// Above this many items, a linear scan per item gets slower than building a set.
+const SET_LOOKUP_THRESHOLD: usize = 16;
+
+fn count_shared(items1: &[i32], items2: &[i32]) -> usize {
+    if items1.len() <= SET_LOOKUP_THRESHOLD && items2.len() <= SET_LOOKUP_THRESHOLD {
+        // Faster than using sets since usually it's a small number of authors
+        return items1.iter().filter(|item| items2.contains(item)).count();
     }
-    if nam1.citation_group == nam2.citation_group {
-        score *= params.cg_boost;
+    let items2_set: std::collections::HashSet<&i32> = items2.iter().collect();
+    return items1
+        .iter()
+        .filter(|item| items2_set.contains(item))
+        .count();
+}
+
+/// The number of shared items as a proportion of the union of two lists.
+fn overlap_proportion(shared: usize, len1: usize, len2: usize) -> f64 {
+    let union = (len1 + len2).saturating_sub(shared);
+    if union == 0 {
+        return 1.0;
     }
-    if nam1.authors == nam2.authors {
-        score *= params.author_boost;
-    } else {
-        // Faster than using sets since usually it's a small number of authors
-        let mut shared_authors = 0;
-        for author in &nam1.authors {
-            if nam2.authors.contains(&author) {
-                shared_authors += 1;
-            }
-        }
-        if shared_authors > 0 {
-            score *= params.author_boost;
-        }
+    return ((shared as f64) / (union as f64)).min(1.0);
+}
+
+fn citation_group_overlap(citation_groups1: &[i32], citation_groups2: &[i32]) -> f64 {
+    if citation_groups1 == citation_groups2 {
+        return 1.0;
     }
-    let year_difference = (nam1.year - nam2.year).abs();
-    score *= (1.0 / params.year_factor.powf(year_difference as f64)) * params.year_boost;
-    return Ok(score);
+    let shared = count_shared(citation_groups1, citation_groups2);
+    return overlap_proportion(shared, citation_groups1.len(), citation_groups2.len());
+}
+
+fn shares_citation_group(nam1: &NameData, nam2: &NameData) -> bool {
+    return count_shared(&nam1.citation_groups, &nam2.citation_groups) > 0;
 }
 
+/// Whether exclude_same_citation_group keeps a training name from counting for a query.
+fn excluded(query: &NameData, train_datum: &NameData, params: &Params) -> bool {
+    return params.exclude_same_citation_group && shares_citation_group(query, train_datum);
+}
+
+/// The score of nam2 as evidence for nam1's collection. If extra_boost is given, it is
+/// called with the two names and its result is multiplied into the score. It is called with
+/// the GIL held, so it is only available here and not in the functions that score whole
+/// training sets, some of which release the GIL.
 #[pyfunction]
-fn get_probs(
-    data: &NameData,
-    train_data: Vec<Bound<'_, NameData>>,
+#[pyo3(signature = (nam1, nam2, params, extra_boost=None))]
+fn get_score(
+    nam1: &Bound<'_, NameData>,
+    nam2: &Bound<'_, NameData>,
     params: &Params,
-) -> PyResult<std::collections::HashMap<i32, f64>> {
-    return get_probs_impl(data, &train_data, params);
+    extra_boost: Option<&Bound<'_, PyAny>>,
+) -> PyResult<f64> {
+    let score = get_score_impl(nam1.get(), nam2.get(), params)?;
+    let Some(extra_boost) = extra_boost else {
+        return Ok(score);
+    };
+    let boost: f64 = extra_boost.call1((nam1, nam2))?.extract()?;
+    return Ok(finite_score(score * boost));
 }
 
-fn get_probs_impl(
-    data: &NameData,
-    train_data: &Vec<Bound<'_, NameData>>,
-    params: &Params,
-) -> PyResult<std::collections::HashMap<i32, f64>> {
-    let mut scores: std::collections::HashMap<i32, f64> = std::collections::HashMap::new();
-    let mut highest_score: f64 = 1.0;
-    for train_datum in train_data {
-        let score = get_score(data, train_datum.get(), params)?;
-        if score > params.score_cutoff {
-            *scores.entry(train_datum.get().collection).or_insert(0.0) += score;
-        }
-        if score > highest_score {
-            highest_score = score;
-        }
+fn get_score_impl(nam1: &NameData, nam2: &NameData, params: &Params) -> PyResult<f64> {
+    return Ok(combine_features(&pair_features(nam1, nam2), params));
+}
+
+/// The distance between two names that ScoreMode.Distance scores as e^-distance, whatever
+/// params.score_mode is. A name is infinitely far from itself, since it scores 0.
+#[pyfunction]
+fn get_distance(nam1: &NameData, nam2: &NameData, params: &Params) -> f64 {
+    let features = pair_features(nam1, nam2);
+    if features.same_name {
+        return f64::INFINITY;
     }
-    *scores.entry(0).or_insert(0.0) += highest_score;
-    let total_score: f64 = scores.values().sum();
-    let mut result: std::collections::HashMap<i32, f64> = std::collections::HashMap::new();
-    for (key, value) in scores.iter() {
-        *result.entry(*key).or_insert(0.0) = value / total_score;
+    return pair_distance(&features, params);
+}
+
+/// The factors get_score combined into a score, each of which is 1 when it has no effect.
+/// Under Combiner.Multiplicative the score is their product, except that a name compared
+/// with itself scores 0 whatever its factors.
+#[pyclass(get_all, frozen)]
+struct ScoreBreakdown {
+    country: f64,
+    citation_group: f64,
+    author: f64,
+    year: f64,
+    year_boost: f64,
+}
+
+#[pymethods]
+impl ScoreBreakdown {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "ScoreBreakdown(country={}, citation_group={}, author={}, year={}, year_boost={})",
+            self.country, self.citation_group, self.author, self.year, self.year_boost
+        ))
     }
-    return Ok(result);
 }
 
+/// The score of get_score together with the factors it was combined from.
 #[pyfunction]
-fn get_top_choice(
-    data: &NameData,
-    train_data: Vec<Bound<'_, NameData>>,
+fn get_score_verbose(
+    nam1: &NameData,
+    nam2: &NameData,
     params: &Params,
-) -> PyResult<Option<(i32, f64)>> {
-    let probs = get_probs_impl(data, &train_data, params)?;
-    let mut top_choice: i32 = -1;
-    let mut top_score: f64 = params.probability_cutoff;
-    for (key, value) in probs.iter() {
-        if *value > top_score {
-            top_choice = *key;
-            top_score = *value;
+) -> PyResult<(f64, ScoreBreakdown)> {
+    if params.score_mode != ScoreMode::Boost {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "get_score_verbose requires the boost score mode",
+        ));
+    }
+    let features = pair_features(nam1, nam2);
+    let factors = score_factors(&features, params);
+    let score = if features.same_name {
+        0.0
+    } else {
+        combine_factors(&factors, params.combiner)
+    };
+    let [country, citation_group, author, year, year_boost] = factors;
+    let breakdown = ScoreBreakdown {
+        country,
+        citation_group,
+        author,
+        year,
+        year_boost,
+    };
+    return Ok((score, breakdown));
+}
+
+/// The factors of get_score precomputed from a Params, so that scores can be reproduced
+/// without this library. The lists are indexed by difference (in years, or in number of
+/// authors), and their last entry applies to any larger difference.
+#[pyclass(get_all, frozen)]
+struct ScoringTable {
+    // The country factor for names in the same country, unless either has country_sentinel
+    country_factor: f64,
+    country_sentinel: Option<i32>,
+    // Raised to the citation group overlap, unless either name only has
+    // citation_group_sentinel
+    citation_group_factor: f64,
+    citation_group_sentinel: Option<i32>,
+    // The author factor for names sharing any authors, by author list length difference
+    author_factors: Vec<f64>,
+    // The year decay by year difference; the year factor is decay + (1 − decay) ×
+    // author overlap ^ author_overlap_exponent × author_year_interaction
+    year_decays: Vec<f64>,
+    author_year_interaction: f64,
+    ordered_author_overlap: bool,
+    author_overlap_denominator: AuthorOverlapDenominator,
+    author_overlap_exponent: f64,
+    year_boost: f64,
+    combiner: Combiner,
+}
+
+#[pymethods]
+impl ScoringTable {
+    /// get_score computed from the table alone, for checking it. Matches get_score
+    /// exactly for differences the table covers.
+    fn score(&self, nam1: &NameData, nam2: &NameData) -> f64 {
+        let features = pair_features(nam1, nam2);
+        if features.same_name {
+            return 0.0;
         }
+        let (country1, country2) = features.tl_countries;
+        let country_missing = self
+            .country_sentinel
+            .is_some_and(|sentinel| country1 == sentinel || country2 == sentinel);
+        let country = if features.country_match && !country_missing {
+            self.country_factor
+        } else {
+            1.0
+        };
+        let (citation_group1, citation_group2) = features.sole_citation_groups;
+        let citation_group = if self.citation_group_sentinel.is_some_and(|sentinel| {
+            citation_group1 == Some(sentinel) || citation_group2 == Some(sentinel)
+        }) {
+            1.0
+        } else {
+            self.citation_group_factor
+                .powf(features.citation_group_overlap)
+        };
+        let author = if features.authors_equal || features.shared_authors > 0 {
+            table_entry(&self.author_factors, features.author_length_difference)
+        } else {
+            1.0
+        };
+        let year = match features.year_difference {
+            Some(year_difference) => {
+                let decay = table_entry(&self.year_decays, year_difference as usize);
+                let author_overlap = author_overlap_impl(
+                    &features,
+                    self.ordered_author_overlap,
+                    self.author_overlap_denominator,
+                )
+                .powf(self.author_overlap_exponent);
+                decay + (1.0 - decay) * author_overlap * self.author_year_interaction
+            }
+            None => 1.0,
+        };
+        return combine_factors(
+            &[country, citation_group, author, year, self.year_boost],
+            self.combiner,
+        );
     }
-    if top_choice == -1 {
-        return Ok(None);
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "ScoringTable(country_factor={}, citation_group_factor={}, author_factors=<{} entries>, year_decays=<{} entries>, year_boost={}, combiner={})",
+            self.country_factor,
+            self.citation_group_factor,
+            self.author_factors.len(),
+            self.year_decays.len(),
+            self.year_boost,
+            self.combiner.repr_name()
+        ))
     }
-    return Ok(Some((top_choice, top_score)));
 }
 
-fn get_top_choice_impl(
-    data: &NameData,
-    train_data: &Vec<Bound<'_, NameData>>,
+fn table_entry(table: &[f64], index: usize) -> f64 {
+    return table[index.min(table.len() - 1)];
+}
+
+/// The factors of get_score under params as a ScoringTable covering year differences up to
+/// max_year_difference and author list length differences up to
+/// max_author_length_difference.
+#[pyfunction]
+#[pyo3(signature = (params, max_year_difference=100, max_author_length_difference=20))]
+fn export_scoring_table(
     params: &Params,
-) -> PyResult<i32> {
-    let probs = get_probs_impl(data, train_data, params)?;
-    let mut top_choice: i32 = -1;
-    let mut top_score: f64 = params.probability_cutoff;
-    for (key, value) in probs.iter() {
-        if *value > top_score {
-            top_choice = *key;
-            top_score = *value;
-        }
+    max_year_difference: usize,
+    max_author_length_difference: usize,
+) -> PyResult<ScoringTable> {
+    if params.score_mode != ScoreMode::Boost {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "export_scoring_table requires the boost score mode",
+        ));
+    }
+    // Feed each difference through score_factors so the table cannot drift from get_score
+    let features = |year_difference: usize, author_length_difference: usize| PairFeatures {
+        same_name: false,
+        country_match: true,
+        tl_countries: (0, 0),
+        sole_citation_groups: (None, None),
+        citation_group_overlap: 1.0,
+        shares_citation_group: true,
+        authors_equal: false,
+        shared_authors: 1,
+        author_length_difference,
+        in_order_authors: 0,
+        author_counts: (1, 1),
+        year_difference: Some(year_difference as i64),
+    };
+    let factors = score_factors(&features(0, 0), params);
+    let mut no_interaction = params.clone();
+    no_interaction.author_year_interaction = 0.0;
+    return Ok(ScoringTable {
+        country_factor: factors[0],
+        country_sentinel: params.country_sentinel,
+        citation_group_factor: factors[1],
+        citation_group_sentinel: params.citation_group_sentinel,
+        author_factors: (0..=max_author_length_difference)
+            .map(|difference| score_factors(&features(0, difference), params)[2])
+            .collect(),
+        year_decays: (0..=max_year_difference)
+            .map(|difference| score_factors(&features(difference, 0), &no_interaction)[3])
+            .collect(),
+        author_year_interaction: params.author_year_interaction,
+        ordered_author_overlap: params.ordered_author_overlap,
+        author_overlap_denominator: params.author_overlap_denominator,
+        author_overlap_exponent: params.author_overlap_exponent,
+        year_boost: factors[4],
+        combiner: params.combiner,
+    });
+}
+
+/// Everything get_score needs to know about a pair of names, independent of the parameters.
+#[derive(Clone)]
+struct PairFeatures {
+    same_name: bool,
+    country_match: bool,
+    tl_countries: (i32, i32),
+    // Each name's citation group if it has exactly one
+    sole_citation_groups: (Option<i32>, Option<i32>),
+    citation_group_overlap: f64,
+    // Whether any citation group is in both lists
+    shares_citation_group: bool,
+    authors_equal: bool,
+    shared_authors: usize,
+    author_length_difference: usize,
+    // The shared authors that appear in the same order in both lists
+    in_order_authors: usize,
+    author_counts: (usize, usize),
+    // None if either name has no year
+    year_difference: Option<i64>,
+}
+
+fn pair_features(nam1: &NameData, nam2: &NameData) -> PairFeatures {
+    let authors_equal = nam1.same_authors(nam2);
+    let shared_authors = count_shared(&nam1.authors, &nam2.authors);
+    PairFeatures {
+        same_name: nam1.name_id == nam2.name_id,
+        country_match: nam1.tl_country == nam2.tl_country,
+        tl_countries: (nam1.tl_country, nam2.tl_country),
+        sole_citation_groups: (
+            sole_citation_group(&nam1.citation_groups),
+            sole_citation_group(&nam2.citation_groups),
+        ),
+        citation_group_overlap: citation_group_overlap(
+            &nam1.citation_groups,
+            &nam2.citation_groups,
+        ),
+        shares_citation_group: shares_citation_group(nam1, nam2),
+        authors_equal,
+        shared_authors,
+        author_length_difference: nam1.authors.len().abs_diff(nam2.authors.len()),
+        // Without shared authors there is no subsequence to look for
+        in_order_authors: if shared_authors == 0 || authors_equal {
+            shared_authors
+        } else {
+            longest_common_subsequence(&nam1.authors, &nam2.authors)
+        },
+        author_counts: (nam1.authors.len(), nam2.authors.len()),
+        // Years may be negative, so widen before subtracting to avoid overflow
+        year_difference: match (nam1.year, nam2.year) {
+            (Some(year1), Some(year2)) => Some((i64::from(year1) - i64::from(year2)).abs()),
+            _ => None,
+        },
     }
-    return Ok(top_choice);
 }
 
+fn sole_citation_group(citation_groups: &[i32]) -> Option<i32> {
+    return match citation_groups {
+        [citation_group] => Some(*citation_group),
+        _ => None,
+    };
+}
+
+/// Which fields of two names agree, without any of the scoring.
 #[pyclass(get_all, frozen)]
-struct ScoreInfo {
-    score: f64,
-    correct: i32,
-    incorrect: i32,
-    no_value: i32,
+#[derive(Clone)]
+struct FieldMatch {
+    country_match: bool,
+    // Whether the citation groups overlap fully, so the full cg_boost applies
+    cg_match: bool,
+    authors_equal: bool,
+    shared_author_count: usize,
+    // None if either name has no year
+    year_difference: Option<i64>,
 }
 
 #[pymethods]
-impl ScoreInfo {
+impl FieldMatch {
     fn __repr__(&self) -> PyResult<String> {
         Ok(format!(
-            "ScoreInfo(score={}, correct={}, incorrect={}, no_value={})",
-            self.score, self.correct, self.incorrect, self.no_value
+            "FieldMatch(country_match={}, cg_match={}, authors_equal={}, shared_author_count={}, year_difference={})",
+            python_bool(self.country_match),
+            python_bool(self.cg_match),
+            python_bool(self.authors_equal),
+            self.shared_author_count,
+            format_optional(self.year_difference)
         ))
     }
 }
 
 #[pyfunction]
-fn evaluate_model(
-    train_data: Vec<Bound<'_, NameData>>,
-    test_data: Vec<Bound<'_, NameData>>,
-    scoring_function: &ScoringFunction,
-    params: &Params,
-) -> PyResult<ScoreInfo> {
-    return evaluate_model_impl(&train_data, &test_data, scoring_function, params);
+fn field_match(nam1: &NameData, nam2: &NameData) -> FieldMatch {
+    let features = pair_features(nam1, nam2);
+    return FieldMatch {
+        country_match: features.country_match,
+        cg_match: features.citation_group_overlap == 1.0,
+        authors_equal: features.authors_equal,
+        shared_author_count: features.shared_authors,
+        year_difference: features.year_difference,
+    };
 }
 
-fn evaluate_model_impl(
-    train_data: &Vec<Bound<'_, NameData>>,
-    test_data: &Vec<Bound<'_, NameData>>,
-    scoring_function: &ScoringFunction,
-    params: &Params,
-) -> PyResult<ScoreInfo> {
-    let mut correct: i32 = 0;
-    let mut incorrect: i32 = 0;
-    let mut no_value: i32 = 0;
-    for nam in test_data {
-        let top_choice = get_top_choice_impl(nam.get(), train_data, params)?;
-        if top_choice == -1 {
-            no_value += 1;
-            continue;
+/// The proportion of the two names' authors that they share, as used by get_score with
+/// params (by default, with ordered_author_overlap off and the union as the denominator).
+/// Equal author lists (including two empty ones) have an overlap of 1.
+#[pyfunction]
+#[pyo3(signature = (nam1, nam2, params=None))]
+fn author_overlap(nam1: &NameData, nam2: &NameData, params: Option<&Params>) -> f64 {
+    let features = pair_features(nam1, nam2);
+    return match params {
+        Some(params) => author_overlap_impl(
+            &features,
+            params.ordered_author_overlap,
+            params.author_overlap_denominator,
+        ),
+        None => author_overlap_impl(&features, false, AuthorOverlapDenominator::Union),
+    };
+}
+
+fn author_overlap_impl(
+    features: &PairFeatures,
+    ordered: bool,
+    denominator: AuthorOverlapDenominator,
+) -> f64 {
+    if features.authors_equal {
+        return 1.0;
+    }
+    // Out-of-order shared authors count towards the union but not the overlap
+    let shared = if ordered {
+        features.in_order_authors
+    } else {
+        features.shared_authors
+    };
+    let (query_count, train_count) = features.author_counts;
+    return match denominator {
+        AuthorOverlapDenominator::Union => {
+            let union = query_count + train_count - features.shared_authors;
+            ((shared as f64) / (union as f64)).min(1.0)
         }
-        if top_choice == nam.get().collection {
-            correct += 1;
-        } else {
-            incorrect += 1;
+        // A query without authors shares none with a training name that has some
+        AuthorOverlapDenominator::Query if query_count == 0 => 0.0,
+        AuthorOverlapDenominator::Query => ((shared as f64) / (query_count as f64)).min(1.0),
+    };
+}
+
+fn longest_common_subsequence(items1: &[i32], items2: &[i32]) -> usize {
+    let mut previous = vec![0; items2.len() + 1];
+    let mut current = vec![0; items2.len() + 1];
+    for item1 in items1 {
+        for (index, item2) in items2.iter().enumerate() {
+            current[index + 1] = if item1 == item2 {
+                previous[index] + 1
+            } else {
+                previous[index + 1].max(current[index])
+            };
         }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    return previous[items2.len()];
+}
+
+fn combine_features(features: &PairFeatures, params: &Params) -> f64 {
+    if features.same_name {
+        return 0.0;
+    }
+    if params.score_mode == ScoreMode::Distance {
+        return finite_score((-pair_distance(features, params)).exp());
     }
-    let score = (correct as f64)
-        - ((incorrect as f64) * scoring_function.false_positive_cost)
-        - ((no_value as f64) * scoring_function.false_negative_cost);
-    return Ok(ScoreInfo {
-        score,
-        correct,
-        incorrect,
-        no_value,
+    return combine_factors(&score_factors(features, params), params.combiner);
+}
+
+/// The distance between two different names in distance mode.
+fn pair_distance(features: &PairFeatures, params: &Params) -> f64 {
+    let (country1, country2) = features.tl_countries;
+    let country_missing = params
+        .country_sentinel
+        .is_some_and(|sentinel| country1 == sentinel || country2 == sentinel);
+    let (citation_group1, citation_group2) = features.sole_citation_groups;
+    let citation_group_missing = params.citation_group_sentinel.is_some_and(|sentinel| {
+        citation_group1 == Some(sentinel) || citation_group2 == Some(sentinel)
     });
+    let mut mismatches = 0.0;
+    if !features.country_match && !country_missing {
+        mismatches += 1.0;
+    }
+    if !citation_group_missing {
+        mismatches += 1.0 - features.citation_group_overlap;
+    }
+    let author_overlap = author_overlap_impl(
+        features,
+        params.ordered_author_overlap,
+        params.author_overlap_denominator,
+    )
+    .powf(params.author_overlap_exponent);
+    let year_difference = features.year_difference.unwrap_or(0) as f64;
+    return params.mismatch_penalty * mismatches
+        + (1.0 - author_overlap)
+        + params.year_distance_scale * year_difference;
 }
 
-#[pymodule]
-fn repoguess(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(get_score, m)?)?;
-    m.add_function(wrap_pyfunction!(get_probs, m)?)?;
-    m.add_function(wrap_pyfunction!(get_top_choice, m)?)?;
-    m.add_function(wrap_pyfunction!(evaluate_model, m)?)?;
-    m.add_class::<NameData>()?;
-    m.add_class::<Params>()?;
-    m.add_class::<ScoreInfo>()?;
-    m.add_class::<ScoringFunction>()?;
-    Ok(())
+fn combine_factors(factors: &[f64; 5], combiner: Combiner) -> f64 {
+    let score = match combiner {
+        Combiner::Multiplicative => factors.iter().product(),
+        Combiner::LogAdditive => {
+            (1.0 + factors.iter().map(|factor| factor.ln()).sum::<f64>()).max(0.0)
+        }
+        Combiner::MaxFactor => factors.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+    };
+    return finite_score(score);
+}
+
+/// The factors that get_score combines for a pair of different names: country, citation
+/// group, author, year and year_boost.
+fn score_factors(features: &PairFeatures, params: &Params) -> [f64; 5] {
+    let (country1, country2) = features.tl_countries;
+    let country_missing = params
+        .country_sentinel
+        .is_some_and(|sentinel| country1 == sentinel || country2 == sentinel);
+    let country = if features.country_match && !country_missing {
+        params.country_boost
+    } else {
+        1.0
+    };
+    let (citation_group1, citation_group2) = features.sole_citation_groups;
+    let citation_group_missing = params.citation_group_sentinel.is_some_and(|sentinel| {
+        citation_group1 == Some(sentinel) || citation_group2 == Some(sentinel)
+    });
+    let citation_group = if citation_group_missing {
+        1.0
+    } else {
+        // Partially overlapping citation groups get a fractional share of the boost
+        params.cg_boost.powf(features.citation_group_overlap)
+    };
+    let author = if features.authors_equal {
+        params.author_boost
+    } else if features.shared_authors > 0 {
+        params.author_boost
+            * (1.0 - params.length_mismatch_penalty).powi(features.author_length_difference as i32)
+    } else {
+        1.0
+    };
+    // A name without a year gives no information about the year difference
+    let mut year = 1.0;
+    if let Some(year_difference) = features.year_difference {
+        let year_decay = match &params.year_curve {
+            Some(curve) => curve[(year_difference as usize).min(curve.len() - 1)],
+            // Guard against overflow for a year_factor close to 0 and large year differences
+            None => params
+                .year_factor
+                .powf(-(year_difference as f64))
+                .min(f64::MAX),
+        };
+        let author_overlap = author_overlap_impl(
+            features,
+            params.ordered_author_overlap,
+            params.author_overlap_denominator,
+        )
+        .powf(params.author_overlap_exponent);
+        year = year_decay + (1.0 - year_decay) * author_overlap * params.author_year_interaction;
+    }
+    return [country, citation_group, author, year, params.year_boost];
+}
+
+/// Keeps extreme parameters from producing scores that break normalization: NaN (from
+/// multiplying an overflowed factor by zero) becomes 0 and infinity becomes f64::MAX.
+fn finite_score(score: f64) -> f64 {
+    if score.is_nan() {
+        return 0.0;
+    }
+    return score.min(f64::MAX);
+}
+
+/// An upper bound on get_score for any pair of names, or None if there is no finite bound
+/// (for example, when a year_factor below 1 rewards large year differences).
+fn max_score(params: &Params) -> Option<f64> {
+    if params.score_mode == ScoreMode::Distance {
+        // Scores are e^-distance, so they are at most 1 when no distance can be negative
+        if params.mismatch_penalty < 0.0
+            || params.year_distance_scale < 0.0
+            || params.author_overlap_exponent <= 0.0
+        {
+            return None;
+        }
+        return Some(1.0);
+    }
+    if params.country_boost < 0.0
+        || params.cg_boost < 0.0
+        || params.author_boost < 0.0
+        || params.year_boost < 0.0
+        || params.author_year_interaction < 0.0
+        || params.author_overlap_exponent <= 0.0
+        || !(0.0..=1.0).contains(&params.length_mismatch_penalty)
+    {
+        return None;
+    }
+    // The year factor interpolates between the decay and the interaction
+    let max_year_decay = match &params.year_curve {
+        Some(curve) => {
+            if curve.iter().any(|value| !value.is_finite() || *value < 0.0) {
+                return None;
+            }
+            curve.iter().copied().fold(1.0, f64::max)
+        }
+        None if params.year_factor < 1.0 => return None,
+        None => 1.0,
+    };
+    // The other combiners never exceed the product of the factors' bounds when every
+    // bound is at least 1
+    let year_boost_bound = match params.combiner {
+        Combiner::Multiplicative => params.year_boost,
+        Combiner::LogAdditive | Combiner::MaxFactor => params.year_boost.max(1.0),
+    };
+    return Some(
+        params.country_boost.max(1.0)
+            * params.cg_boost.max(1.0)
+            * params.author_boost.max(1.0)
+            * params.author_year_interaction.max(max_year_decay)
+            * year_boost_bound,
+    );
+}
+
+fn borrow_names<'a>(data: &'a [Bound<'_, NameData>]) -> Vec<&'a NameData> {
+    return data.iter().map(|datum| datum.get()).collect();
+}
+
+/// The probability of each collection. If allowed_collections is given, only training
+/// names in those collections are considered, so the unknown bucket is measured against
+/// them too. If min_prob is given, only collections with at least that probability are
+/// returned, and with renormalize their probabilities are scaled to sum to 1. If round_to
+/// is given, probabilities are rounded to that many decimals in a way that keeps their sum.
+#[pyfunction]
+#[pyo3(signature = (
+    data,
+    train_data,
+    params,
+    allowed_collections=None,
+    round_to=None,
+    min_prob=None,
+    renormalize=false,
+))]
+fn get_probs(
+    data: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    allowed_collections: Option<Vec<i32>>,
+    round_to: Option<i32>,
+    min_prob: Option<f64>,
+    renormalize: bool,
+) -> PyResult<std::collections::HashMap<i32, f64>> {
+    let train_data = filter_allowed(borrow_names(&train_data), allowed_collections);
+    let mut probs = get_probs_impl(data, &train_data, params)?;
+    if let Some(min_prob) = min_prob {
+        probs.retain(|_, prob| *prob >= min_prob);
+    }
+    if renormalize {
+        let total: f64 = probs.values().sum();
+        if total > 0.0 {
+            for prob in probs.values_mut() {
+                *prob /= total;
+            }
+        }
+    }
+    return match round_to {
+        Some(decimals) if (0..=15).contains(&decimals) => Ok(round_probs(&probs, decimals)),
+        Some(_) => Err(pyo3::exceptions::PyValueError::new_err(
+            "round_to must be between 0 and 15",
+        )),
+        None => Ok(probs),
+    };
+}
+
+/// Rounds probs to multiples of 10^-decimals with the largest remainder method: values are
+/// rounded down, and the units lost are given back to the values that lost the most (the
+/// lowest collection id first on ties), so the rounded values keep the original sum.
+fn round_probs(
+    probs: &std::collections::HashMap<i32, f64>,
+    decimals: i32,
+) -> std::collections::HashMap<i32, f64> {
+    let scale = 10f64.powi(decimals);
+    let target_units = (probs.values().sum::<f64>() * scale).round() as i64;
+    let mut units: Vec<(i32, i64, f64)> = probs
+        .iter()
+        .map(|(collection, prob)| {
+            let scaled = prob * scale;
+            (*collection, scaled.floor() as i64, scaled - scaled.floor())
+        })
+        .collect();
+    units.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(&b.0)));
+    let missing = target_units - units.iter().map(|(_, floor, _)| floor).sum::<i64>();
+    for (index, entry) in units.iter_mut().enumerate() {
+        if (index as i64) < missing {
+            entry.1 += 1;
+        }
+    }
+    return units
+        .into_iter()
+        .map(|(collection, count, _)| (collection, (count as f64) / scale))
+        .collect();
+}
+
+fn filter_allowed(
+    train_data: Vec<&NameData>,
+    allowed_collections: Option<Vec<i32>>,
+) -> Vec<&NameData> {
+    let Some(allowed_collections) = allowed_collections else {
+        return train_data;
+    };
+    let allowed: std::collections::HashSet<i32> = allowed_collections.into_iter().collect();
+    return train_data
+        .into_iter()
+        .filter(|datum| {
+            datum
+                .collection
+                .is_some_and(|collection| allowed.contains(&collection))
+        })
+        .collect();
+}
+
+/// The same as get_probs without allowed_collections and round_to, but scoring the
+/// training names across all cores, for very large training sets.
+#[pyfunction]
+fn get_probs_parallel(
+    py: Python<'_>,
+    data: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<std::collections::HashMap<i32, f64>> {
+    let train_data = borrow_names(&train_data);
+    let scores = py.allow_threads(|| {
+        parallel_map(&train_data, |train_datum| {
+            get_score_impl(data, train_datum, params)
+        })
+    });
+    // Adding the scores in training order keeps the sums identical to get_probs
+    let mut contributions = Vec::with_capacity(train_data.len());
+    for (train_datum, score) in train_data.iter().zip(scores) {
+        let score = score?;
+        if let Some(collection) = train_datum.collection {
+            if !excluded(data, train_datum, params) {
+                contributions.push((collection, score));
+            }
+        }
+    }
+    let mut raw_scores = RawScores::new(params);
+    raw_scores.add_all(contributions, params);
+    return Ok(raw_scores.normalized());
+}
+
+fn get_probs_impl(
+    data: &NameData,
+    train_data: &[&NameData],
+    params: &Params,
+) -> PyResult<std::collections::HashMap<i32, f64>> {
+    let raw_scores = get_raw_scores_impl(data, train_data, params)?;
+    return Ok(raw_scores.normalized());
+}
+
+/// The evidence for one collection: the training names that passed the score cutoff, plus
+/// the softened scores of those that did not.
+#[derive(Clone, Default)]
+struct CollectionScore {
+    total: f64,
+    count: i32,
+    // The highest score of any training name in the collection that passed the cutoff
+    best: f64,
+    // The scores below the cutoff times below_cutoff_factor, which only count in sums
+    below_cutoff_total: f64,
+}
+
+/// The scores of a query against a training set, before normalization. Keeping these
+/// around allows folding in more training names later without rescoring the old ones.
+#[pyclass(frozen)]
+#[derive(Clone)]
+struct RawScores {
+    collections: std::collections::HashMap<i32, CollectionScore>,
+    highest_score: f64,
+    // The parameters the scores were computed with
+    params: Params,
+}
+
+impl RawScores {
+    fn new(params: &Params) -> Self {
+        RawScores {
+            collections: std::collections::HashMap::new(),
+            highest_score: 1.0,
+            params: params.clone(),
+        }
+    }
+
+    fn add(&mut self, collection: i32, score: f64, params: &Params) {
+        let collection = if params.reject_collection == Some(collection) {
+            0
+        } else {
+            collection
+        };
+        if score > params.score_cutoff {
+            let entry = self.collections.entry(collection).or_default();
+            entry.total += score;
+            entry.count += 1;
+            entry.best = entry.best.max(score);
+        } else if params.below_cutoff_factor > 0.0 {
+            let entry = self.collections.entry(collection).or_default();
+            entry.below_cutoff_total += score * params.below_cutoff_factor;
+        }
+        if score > self.highest_score {
+            self.highest_score = score;
+        }
+    }
+
+    /// The collection get_top_choice predicts from these scores, if any.
+    fn top_choice(&self, params: &Params) -> Option<(i32, f64)> {
+        return self.choose(&self.normalized(), params);
+    }
+
+    /// The most probable collection in probs, which are usually these scores normalized,
+    /// if it passes the probability cutoff and the margin.
+    fn choose(
+        &self,
+        probs: &std::collections::HashMap<i32, f64>,
+        params: &Params,
+    ) -> Option<(i32, f64)> {
+        return check_choice(probs, self.best_choice(probs, params)?, params);
+    }
+
+    /// The most probable collection in probs regardless of the probability cutoff, with
+    /// ties broken as tie_break says, using the best single matches in these scores.
+    fn best_choice(
+        &self,
+        probs: &std::collections::HashMap<i32, f64>,
+        params: &Params,
+    ) -> Option<(i32, f64)> {
+        if params.tie_break == TieBreak::LowestId {
+            return best_choice(probs);
+        }
+        let best_match = |collection: i32| {
+            self.collections
+                .get(&collection)
+                .map_or(0.0, |entry| entry.best)
+        };
+        let mut best: Option<(i32, f64)> = None;
+        for (collection, probability) in probs.iter() {
+            match best {
+                Some((best_collection, best_probability))
+                    if *probability < best_probability
+                        || (*probability == best_probability
+                            && (best_match(*collection) < best_match(best_collection)
+                                || (best_match(*collection) == best_match(best_collection)
+                                    && *collection > best_collection))) => {}
+                _ => best = Some((*collection, *probability)),
+            }
+        }
+        return best;
+    }
+
+    fn num_candidates(&self) -> i32 {
+        return self.collections.values().map(|entry| entry.count).sum();
+    }
+
+    /// The aggregated score per collection, blended with its best match by nn_blend, with
+    /// the unknown bucket (0) receiving the highest single score or the configured constant.
+    fn totals(&self) -> std::collections::HashMap<i32, f64> {
+        let mut scores: std::collections::HashMap<i32, f64> = self
+            .collections
+            .iter()
+            .filter_map(|(collection, entry)| {
+                let aggregated = match self.params.aggregation {
+                    Aggregation::Sum => entry.total + entry.below_cutoff_total,
+                    Aggregation::MeanWithSupport => {
+                        if entry.count < self.params.min_support.max(1) {
+                            return None;
+                        }
+                        entry.total / (entry.count as f64)
+                    }
+                };
+                let blend = self.params.nn_blend;
+                if blend == 0.0 {
+                    return Some((*collection, aggregated));
+                }
+                Some((*collection, (1.0 - blend) * aggregated + blend * entry.best))
+            })
+            .collect();
+        *scores.entry(0).or_insert(0.0) += self
+            .params
+            .unknown_bucket_score
+            .unwrap_or(self.highest_score);
+        return scores;
+    }
+
+    /// The probability per collection, normalized as configured by normalize_with_unknown.
+    fn normalized(&self) -> std::collections::HashMap<i32, f64> {
+        let totals = self.totals();
+        let known_total: f64 = totals
+            .iter()
+            .filter(|(collection, _)| **collection != 0)
+            .map(|(_, score)| score)
+            .sum();
+        // Without any known collection there is nothing else to normalize over
+        if self.params.normalize_with_unknown || known_total <= 0.0 {
+            return apply_unknown_floor(normalize_scores(&totals), &self.params);
+        }
+        let total_score: f64 = totals.values().sum();
+        return totals
+            .iter()
+            .map(|(collection, score)| {
+                let denominator = if *collection == 0 {
+                    total_score
+                } else {
+                    known_total
+                };
+                (*collection, score / denominator)
+            })
+            .collect();
+    }
+}
+
+/// Raises the unknown bucket's probability to min_unknown_prob if it is lower, scaling
+/// the other probabilities so they still sum to 1.
+fn apply_unknown_floor(
+    mut probs: std::collections::HashMap<i32, f64>,
+    params: &Params,
+) -> std::collections::HashMap<i32, f64> {
+    let floor = params.min_unknown_prob.min(1.0);
+    let unknown_prob = probs.get(&0).copied().unwrap_or(0.0);
+    if !params.normalize_with_unknown || unknown_prob >= floor {
+        return probs;
+    }
+    let scale = (1.0 - floor) / (1.0 - unknown_prob);
+    for (collection, prob) in probs.iter_mut() {
+        *prob = if *collection == 0 {
+            floor
+        } else {
+            *prob * scale
+        };
+    }
+    return probs;
+}
+
+fn get_raw_scores_impl(
+    data: &NameData,
+    train_data: &[&NameData],
+    params: &Params,
+) -> PyResult<RawScores> {
+    let mut raw_scores = RawScores::new(params);
+    raw_scores.add_train_data(data, train_data, params)?;
+    return Ok(raw_scores);
+}
+
+impl RawScores {
+    fn add_train_data(
+        &mut self,
+        data: &NameData,
+        train_data: &[&NameData],
+        params: &Params,
+    ) -> PyResult<()> {
+        let mut contributions = Vec::with_capacity(train_data.len());
+        for train_datum in train_data {
+            // Names without a known collection are not evidence for any collection
+            let Some(collection) = train_datum.collection else {
+                continue;
+            };
+            if excluded(data, train_datum, params) {
+                continue;
+            }
+            contributions.push((collection, get_score_impl(data, train_datum, params)?));
+        }
+        self.add_all(contributions, params);
+        return Ok(());
+    }
+
+    /// Adds the (collection, score) pairs in order, keeping only the max_contributions
+    /// highest-scoring ones if that is set. Ties go to the earlier pair.
+    fn add_all(&mut self, contributions: Vec<(i32, f64)>, params: &Params) {
+        let limit = params.max_contributions;
+        if limit == 0 || contributions.len() <= limit {
+            for (collection, score) in contributions {
+                self.add(collection, score, params);
+            }
+            return;
+        }
+        let scores = contributions.iter().map(|(_, score)| *score);
+        let mut kept: Vec<usize> = top_k(scores.enumerate(), limit)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect();
+        // Add in the original order so the sums do not depend on the selection
+        kept.sort_unstable();
+        for index in kept {
+            let (collection, score) = contributions[index];
+            self.add(collection, score, params);
+        }
+    }
+}
+
+#[pymethods]
+impl RawScores {
+    /// Returns new scores that also include new_train_data. data and params must be the
+    /// same as those used to compute these scores (a ValueError is raised if params is
+    /// not), and params must not set max_contributions.
+    fn update(
+        &self,
+        data: &NameData,
+        new_train_data: Vec<Bound<'_, NameData>>,
+        params: &Params,
+    ) -> PyResult<RawScores> {
+        if *params != self.params {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "params differ from those the scores were computed with",
+            ));
+        }
+        if params.max_contributions > 0 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "update does not support max_contributions, since names added later can displace earlier contributions",
+            ));
+        }
+        let mut raw_scores = self.clone();
+        raw_scores.add_train_data(data, &borrow_names(&new_train_data), params)?;
+        return Ok(raw_scores);
+    }
+
+    /// The summed score per collection, including the unknown bucket.
+    fn scores(&self) -> std::collections::HashMap<i32, f64> {
+        return self.totals();
+    }
+
+    /// The same probabilities get_probs would return for all training names seen so far.
+    fn probs(&self) -> std::collections::HashMap<i32, f64> {
+        return self.normalized();
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "RawScores(num_collections={}, num_candidates={}, highest_score={})",
+            self.collections.len(),
+            self.num_candidates(),
+            self.highest_score
+        ))
+    }
+}
+
+#[pyfunction]
+fn get_raw_scores(
+    data: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<RawScores> {
+    return get_raw_scores_impl(data, &borrow_names(&train_data), params);
+}
+
+#[pyfunction]
+fn contribution_counts(
+    data: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<std::collections::HashMap<i32, i32>> {
+    let raw_scores = get_raw_scores_impl(data, &borrow_names(&train_data), params)?;
+    return Ok(raw_scores
+        .collections
+        .iter()
+        .map(|(collection, entry)| (*collection, entry.count))
+        .collect());
+}
+
+/// For each query, the aggregated score per collection against train_data, including the
+/// unknown bucket. These are the raw scores get_probs normalizes, not probabilities.
+#[pyfunction]
+fn score_matrix_by_collection(
+    py: Python<'_>,
+    queries: Vec<Bound<'_, NameData>>,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<Vec<std::collections::HashMap<i32, f64>>> {
+    let queries = borrow_names(&queries);
+    let train_data = borrow_names(&train_data);
+    return py
+        .allow_threads(|| {
+            parallel_map(&queries, |query| {
+                get_raw_scores_impl(query, &train_data, params)
+                    .map(|raw_scores| raw_scores.totals())
+            })
+        })
+        .into_iter()
+        .collect();
+}
+
+fn normalize_scores(
+    scores: &std::collections::HashMap<i32, f64>,
+) -> std::collections::HashMap<i32, f64> {
+    let total_score: f64 = scores.values().sum();
+    // Nothing scored, which an unknown_bucket_score of 0 allows, so all of it is unknown
+    if total_score <= 0.0 {
+        return scores
+            .keys()
+            .map(|key| (*key, if *key == 0 { 1.0 } else { 0.0 }))
+            .collect();
+    }
+    let mut result: std::collections::HashMap<i32, f64> = std::collections::HashMap::new();
+    for (key, value) in scores.iter() {
+        *result.entry(*key).or_insert(0.0) = value / total_score;
+    }
+    return result;
+}
+
+/// Copies of data in which names with identical author lists share a single copy of the
+/// list. This saves memory when many names have the same authors, and lets get_score
+/// compare such lists without looking at them.
+#[pyfunction]
+fn intern_authors(data: Vec<Bound<'_, NameData>>) -> Vec<NameData> {
+    let mut interned: std::collections::HashMap<&Vec<i32>, std::sync::Arc<Vec<i32>>> =
+        std::collections::HashMap::new();
+    let mut result = Vec::with_capacity(data.len());
+    for nam in borrow_names(&data) {
+        let authors = interned
+            .entry(&*nam.authors)
+            .or_insert_with(|| nam.authors.clone())
+            .clone();
+        result.push(NameData {
+            authors,
+            ..nam.clone()
+        });
+    }
+    return result;
+}
+
+/// Problems in data that would make scores misleading, one message per problem: repeated
+/// name_ids (get_score treats names with the same id as the same name), negative ids,
+/// collection 0 (which is the unknown bucket), and empty author or citation group lists
+/// (two empty lists count as a full match).
+#[pyfunction]
+fn validate_dataset(data: Vec<Bound<'_, NameData>>) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    let mut counts: std::collections::HashMap<i32, usize> = std::collections::HashMap::new();
+    for nam in borrow_names(&data) {
+        let count = counts.entry(nam.name_id).or_insert(0);
+        *count += 1;
+        if *count == 2 {
+            problems.push(format!("name_id {} is used more than once", nam.name_id));
+        }
+        if nam.name_id < 0 {
+            problems.push(format!("name {} has a negative name_id", nam.name_id));
+        }
+        match nam.collection {
+            Some(0) => problems.push(format!(
+                "name {} is in collection 0, which is reserved for the unknown bucket",
+                nam.name_id
+            )),
+            Some(collection) if collection < 0 => problems.push(format!(
+                "name {} is in collection {}, which is negative",
+                nam.name_id, collection
+            )),
+            _ => {}
+        }
+        if nam.authors.is_empty() {
+            problems.push(format!("name {} has no authors", nam.name_id));
+        }
+        if nam.citation_groups.is_empty() {
+            problems.push(format!("name {} has no citation groups", nam.name_id));
+        }
+    }
+    return problems;
+}
+
+/// The number of training names each author appears on, for weighting authors by how
+/// rare they are.
+#[pyfunction]
+fn compute_author_frequencies(
+    train_data: Vec<Bound<'_, NameData>>,
+) -> std::collections::HashMap<i32, i32> {
+    return document_frequencies(borrow_names(&train_data).iter().map(|nam| &*nam.authors));
+}
+
+/// The number of training names in each citation group.
+#[pyfunction]
+fn compute_citation_group_sizes(
+    train_data: Vec<Bound<'_, NameData>>,
+) -> std::collections::HashMap<i32, i32> {
+    return document_frequencies(
+        borrow_names(&train_data)
+            .iter()
+            .map(|nam| &nam.citation_groups),
+    );
+}
+
+/// For each item, the number of lists it appears in. Repeats within a list count once.
+fn document_frequencies<'a>(
+    lists: impl Iterator<Item = &'a Vec<i32>>,
+) -> std::collections::HashMap<i32, i32> {
+    let mut frequencies: std::collections::HashMap<i32, i32> = std::collections::HashMap::new();
+    for list in lists {
+        let distinct: std::collections::HashSet<&i32> = list.iter().collect();
+        for item in distinct {
+            *frequencies.entry(*item).or_insert(0) += 1;
+        }
+    }
+    return frequencies;
+}
+
+/// Summary statistics of the scores of a query against a training set.
+#[pyclass(get_all, frozen)]
+struct ScoreStats {
+    count: usize,
+    min: f64,
+    max: f64,
+    mean: f64,
+    median: f64,
+    p90: f64,
+    p99: f64,
+}
+
+#[pymethods]
+impl ScoreStats {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "ScoreStats(count={}, min={}, max={}, mean={}, median={}, p90={}, p99={})",
+            self.count, self.min, self.max, self.mean, self.median, self.p90, self.p99
+        ))
+    }
+}
+
+/// Statistics of the scores of query against every training name. Percentiles interpolate
+/// linearly between the closest scores.
+#[pyfunction]
+fn score_stats(
+    query: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<ScoreStats> {
+    if train_data.is_empty() {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "train_data must not be empty",
+        ));
+    }
+    let mut scores: Vec<f64> = Vec::with_capacity(train_data.len());
+    for train_datum in train_data.iter() {
+        scores.push(get_score_impl(query, train_datum.get(), params)?);
+    }
+    scores.sort_by(|a, b| a.total_cmp(b));
+    return Ok(ScoreStats {
+        count: scores.len(),
+        min: scores[0],
+        max: scores[scores.len() - 1],
+        mean: scores.iter().sum::<f64>() / (scores.len() as f64),
+        median: percentile(&scores, 50.0),
+        p90: percentile(&scores, 90.0),
+        p99: percentile(&scores, 99.0),
+    });
+}
+
+/// The given percentile of a non-empty sorted list.
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    let position = (percent / 100.0) * ((sorted.len() - 1) as f64);
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    let fraction = position - (lower as f64);
+    return sorted[lower] + (sorted[upper] - sorted[lower]) * fraction;
+}
+
+/// How long get_probs took per query, in microseconds.
+#[pyclass(get_all, frozen)]
+struct LatencyStats {
+    count: usize,
+    min: f64,
+    mean: f64,
+    p50: f64,
+    p95: f64,
+    p99: f64,
+    max: f64,
+}
+
+#[pymethods]
+impl LatencyStats {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "LatencyStats(count={}, min={}, mean={}, p50={}, p95={}, p99={}, max={})",
+            self.count, self.min, self.mean, self.p50, self.p95, self.p99, self.max
+        ))
+    }
+}
+
+/// Times get_probs for each query against train_data, one query at a time with the GIL
+/// released, using a monotonic clock. Percentiles interpolate as in score_stats.
+#[pyfunction]
+fn benchmark_queries(
+    py: Python<'_>,
+    queries: Vec<Bound<'_, NameData>>,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<LatencyStats> {
+    if queries.is_empty() {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "queries must not be empty",
+        ));
+    }
+    let queries = borrow_names(&queries);
+    let train_data = borrow_names(&train_data);
+    let mut times = py.allow_threads(|| -> PyResult<Vec<f64>> {
+        let mut times: Vec<f64> = Vec::with_capacity(queries.len());
+        for query in queries.iter() {
+            let start = std::time::Instant::now();
+            std::hint::black_box(get_probs_impl(query, &train_data, params)?);
+            times.push(start.elapsed().as_secs_f64() * 1e6);
+        }
+        return Ok(times);
+    })?;
+    times.sort_by(|a, b| a.total_cmp(b));
+    return Ok(LatencyStats {
+        count: times.len(),
+        min: times[0],
+        mean: times.iter().sum::<f64>() / (times.len() as f64),
+        p50: percentile(&times, 50.0),
+        p95: percentile(&times, 95.0),
+        p99: percentile(&times, 99.0),
+        max: times[times.len() - 1],
+    });
+}
+
+/// A synthetic name for benchmark_scoring, with up to 20 collections and fields drawn from
+/// small ranges so that training names often match. The fields are hashed from name_id, so
+/// the same name_id always gives the same name.
+fn synthetic_name(name_id: i32) -> NameData {
+    let field = |salt: u64, bound: u64| {
+        let mut z = (name_id as u64) ^ salt.wrapping_mul(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        return ((z ^ (z >> 31)) % bound) as i32;
+    };
+    let num_authors = 1 + field(1, 5) as u64;
+    let authors = (0..num_authors)
+        .map(|index| field(10 + index, 200))
+        .collect();
+    let year = match field(2, 4) {
+        0 => None,
+        _ => Some(1800 + field(3, 200)),
+    };
+    return NameData::new(
+        Some(1 + field(4, 20)),
+        field(5, 10),
+        year,
+        authors,
+        CitationGroups::Single(field(6, 50)),
+        name_id,
+    );
+}
+
+/// Times get_score on representative pairs of names and get_probs against a synthetic
+/// training set of train_size names, to catch changes that slow down scoring. Returns the
+/// mean nanoseconds per call for "short_authors_matching", "short_authors_different",
+/// "long_authors_matching" and "long_authors_different" (2 or 20 authors, with the
+/// country, citation group and year equal or not), "same_length_authors" (20 authors
+/// differing only in the last one, which the cached hash of the authors tells apart),
+/// "interned_authors_matching" (20 authors shared as by intern_authors), each
+/// called iterations times, and for "get_probs", called on iterations / train_size (at
+/// least one) queries. The synthetic names are fixed, so every run times the same inputs.
+#[pyfunction]
+#[pyo3(signature = (params, iterations=100000, train_size=1000))]
+fn benchmark_scoring(
+    py: Python<'_>,
+    params: &Params,
+    iterations: usize,
+    train_size: usize,
+) -> PyResult<std::collections::HashMap<String, f64>> {
+    if iterations == 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "iterations must be positive",
+        ));
+    }
+    let pair = |num_authors: i32, matching: bool| {
+        let authors1: Vec<i32> = (0..num_authors).collect();
+        // Half the authors are shared, and the lists differ in length
+        let authors2: Vec<i32> = (num_authors / 2..num_authors * 2).collect();
+        let nam1 = NameData::new(
+            Some(1),
+            1,
+            Some(1900),
+            authors1,
+            CitationGroups::Single(1),
+            1,
+        );
+        let (tl_country, year, citation_group) = if matching { (1, 1900, 1) } else { (2, 1950, 2) };
+        let nam2 = NameData::new(
+            Some(2),
+            tl_country,
+            Some(year),
+            authors2,
+            CitationGroups::Single(citation_group),
+            2,
+        );
+        return (nam1, nam2);
+    };
+    // Lists of the same length that differ only at the end, so comparing them element by
+    // element gets furthest before telling them apart
+    let mut authors: Vec<i32> = (0..20).collect();
+    let same_length1 = NameData::new(
+        Some(1),
+        1,
+        Some(1900),
+        authors.clone(),
+        CitationGroups::Single(1),
+        1,
+    );
+    authors[19] = 100;
+    let same_length2 = NameData::new(
+        Some(2),
+        1,
+        Some(1900),
+        authors,
+        CitationGroups::Single(1),
+        2,
+    );
+    // As intern_authors leaves them, the two names share one author list
+    let interned1 = pair(20, true).0;
+    let interned2 = NameData {
+        collection: Some(2),
+        name_id: 2,
+        ..interned1.clone()
+    };
+    let cases = [
+        ("short_authors_matching", pair(2, true)),
+        ("short_authors_different", pair(2, false)),
+        ("long_authors_matching", pair(20, true)),
+        ("long_authors_different", pair(20, false)),
+        ("same_length_authors", (same_length1, same_length2)),
+        ("interned_authors_matching", (interned1, interned2)),
+    ];
+    let train_data: Vec<NameData> = (0..train_size)
+        .map(|index| synthetic_name(index as i32))
+        .collect();
+    let queries: Vec<NameData> = (0..(iterations / train_size.max(1)).max(1))
+        .map(|index| synthetic_name((train_size + index) as i32))
+        .collect();
+    return py.allow_threads(|| {
+        let mut result: std::collections::HashMap<String, f64> = std::collections::HashMap::new();
+        for (case, (nam1, nam2)) in cases.iter() {
+            let start = std::time::Instant::now();
+            for _ in 0..iterations {
+                std::hint::black_box(get_score_impl(
+                    std::hint::black_box(nam1),
+                    std::hint::black_box(nam2),
+                    params,
+                )?);
+            }
+            let nanoseconds = start.elapsed().as_secs_f64() * 1e9 / (iterations as f64);
+            result.insert(case.to_string(), nanoseconds);
+        }
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        let start = std::time::Instant::now();
+        for query in queries.iter() {
+            std::hint::black_box(get_probs_impl(query, &train_data, params)?);
+        }
+        let nanoseconds = start.elapsed().as_secs_f64() * 1e9 / (queries.len() as f64);
+        result.insert("get_probs".to_string(), nanoseconds);
+        return Ok(result);
+    });
+}
+
+/// Bins the scores of data against every training name into n_bins equal-width bins
+/// between the lowest and highest score, returned as (low, high, count).
+#[pyfunction]
+fn score_histogram(
+    data: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    n_bins: usize,
+) -> PyResult<Vec<(f64, f64, i32)>> {
+    if n_bins == 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "n_bins must be positive",
+        ));
+    }
+    let mut scores: Vec<f64> = Vec::with_capacity(train_data.len());
+    for train_datum in train_data.iter() {
+        scores.push(get_score_impl(data, train_datum.get(), params)?);
+    }
+    return Ok(histogram(&scores, n_bins));
+}
+
+/// Bins the probability of the most probable collection of each test name, whether or not
+/// it passes the probability cutoff, into n_bins equal-width bins between 0 and 1,
+/// returned as (low, high, count).
+#[pyfunction]
+fn top_prob_distribution(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    n_bins: usize,
+) -> PyResult<Vec<(f64, f64, i32)>> {
+    if n_bins == 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "n_bins must be positive",
+        ));
+    }
+    let train_data = borrow_names(&train_data);
+    let mut top_probs: Vec<f64> = Vec::with_capacity(test_data.len());
+    for nam in borrow_names(&test_data) {
+        let probs = get_probs_impl(nam, &train_data, params)?;
+        if let Some((_, probability)) = best_choice(&probs) {
+            top_probs.push(probability);
+        }
+    }
+    return Ok(histogram_in_range(&top_probs, n_bins, 0.0, 1.0));
+}
+
+fn histogram(values: &[f64], n_bins: usize) -> Vec<(f64, f64, i32)> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let low = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let high = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if low == high {
+        return vec![(low, high, values.len() as i32)];
+    }
+    return histogram_in_range(values, n_bins, low, high);
+}
+
+fn histogram_in_range(values: &[f64], n_bins: usize, low: f64, high: f64) -> Vec<(f64, f64, i32)> {
+    let width = (high - low) / (n_bins as f64);
+    let mut counts = vec![0; n_bins];
+    for value in values {
+        // The highest value goes into the last bin
+        let bin = (((value - low) / width) as usize).min(n_bins - 1);
+        counts[bin] += 1;
+    }
+    return counts
+        .into_iter()
+        .enumerate()
+        .map(|(bin, count)| {
+            let bin_low = low + width * (bin as f64);
+            let bin_high = if bin == n_bins - 1 {
+                high
+            } else {
+                low + width * ((bin + 1) as f64)
+            };
+            (bin_low, bin_high, count)
+        })
+        .collect();
+}
+
+#[pyfunction]
+#[pyo3(signature = (data, train_data_sets, params, weights=None))]
+fn ensemble_probs(
+    data: &NameData,
+    train_data_sets: Vec<Vec<Bound<'_, NameData>>>,
+    params: &Params,
+    weights: Option<Vec<f64>>,
+) -> PyResult<std::collections::HashMap<i32, f64>> {
+    // Each training set acts as a separate model; collections missing from one
+    // model's output count as probability 0 for that model.
+    let mut result: std::collections::HashMap<i32, f64> = std::collections::HashMap::new();
+    if train_data_sets.is_empty() {
+        return Ok(result);
+    }
+    let weights = match weights {
+        Some(weights) => {
+            if weights.len() != train_data_sets.len() {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "got {} weights for {} training sets",
+                    weights.len(),
+                    train_data_sets.len()
+                )));
+            }
+            if weights.iter().any(|weight| *weight < 0.0) {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "weights must be non-negative",
+                ));
+            }
+            weights
+        }
+        None => vec![1.0; train_data_sets.len()],
+    };
+    let total_weight: f64 = weights.iter().sum();
+    if total_weight <= 0.0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "weights must not all be zero",
+        ));
+    }
+    for (train_data, weight) in train_data_sets.iter().zip(weights.iter()) {
+        let probs = get_probs_impl(data, &borrow_names(train_data), params)?;
+        for (key, value) in probs.iter() {
+            *result.entry(*key).or_insert(0.0) += value * weight / total_weight;
+        }
+    }
+    return Ok(result);
+}
+
+/// A mixture of two models over the same training set: gate_fn is called with the query and
+/// returns the weights of params_author and params_year, and the top choice is taken from
+/// the weighted average of their probabilities. The probability cutoff and margin are
+/// those of the model with the larger weight (params_author on a tie).
+#[pyfunction]
+fn gated_top_choice(
+    py: Python<'_>,
+    query: &Bound<'_, NameData>,
+    train_data: Vec<Bound<'_, NameData>>,
+    params_author: &Params,
+    params_year: &Params,
+    gate_fn: &Bound<'_, PyAny>,
+) -> PyResult<Option<(i32, f64)>> {
+    let (author_weight, year_weight): (f64, f64) = gate_fn.call1((query,))?.extract()?;
+    if author_weight < 0.0 || year_weight < 0.0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "gate weights must be non-negative",
+        ));
+    }
+    let total_weight = author_weight + year_weight;
+    if total_weight <= 0.0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "gate weights must not both be zero",
+        ));
+    }
+    let query = query.get();
+    let train_data = borrow_names(&train_data);
+    let (author_scores, year_scores) = py.allow_threads(|| -> PyResult<_> {
+        return Ok((
+            get_raw_scores_impl(query, &train_data, params_author)?,
+            get_raw_scores_impl(query, &train_data, params_year)?,
+        ));
+    })?;
+    let mut probs: std::collections::HashMap<i32, f64> = std::collections::HashMap::new();
+    for (raw_scores, weight) in [(&author_scores, author_weight), (&year_scores, year_weight)] {
+        for (collection, prob) in raw_scores.normalized() {
+            *probs.entry(collection).or_insert(0.0) += prob * weight / total_weight;
+        }
+    }
+    // Ties are broken by the best matches under the same model
+    let (raw_scores, params) = if year_weight > author_weight {
+        (&year_scores, params_year)
+    } else {
+        (&author_scores, params_author)
+    };
+    return Ok(raw_scores.choose(&probs, params));
+}
+
+/// The most probable collection and its probability, if it passes the probability cutoff.
+/// allowed_collections is as for get_probs.
+#[pyfunction]
+#[pyo3(signature = (data, train_data, params, allowed_collections=None))]
+fn get_top_choice(
+    data: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    allowed_collections: Option<Vec<i32>>,
+) -> PyResult<Option<(i32, f64)>> {
+    let train_data = filter_allowed(borrow_names(&train_data), allowed_collections);
+    return get_top_choice_impl(data, &train_data, params);
+}
+
+fn get_top_choice_impl(
+    data: &NameData,
+    train_data: &[&NameData],
+    params: &Params,
+) -> PyResult<Option<(i32, f64)>> {
+    let raw_scores = get_raw_scores_impl(data, train_data, params)?;
+    return Ok(raw_scores.top_choice(params));
+}
+
+/// Returns the most probable collection, regardless of the probability cutoff. Ties go to
+/// the lowest collection id, so the result does not depend on HashMap iteration order.
+fn best_choice(probs: &std::collections::HashMap<i32, f64>) -> Option<(i32, f64)> {
+    let mut best: Option<(i32, f64)> = None;
+    for (key, value) in probs.iter() {
+        match best {
+            Some((best_key, best_prob))
+                if *value < best_prob || (*value == best_prob && *key > best_key) => {}
+            _ => best = Some((*key, *value)),
+        }
+    }
+    return best;
+}
+
+/// The number of collections (not counting the unknown bucket) whose probability exceeds
+/// min_prob, as a measure of how ambiguous the query is.
+#[pyfunction]
+fn plausible_collection_count(
+    query: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    min_prob: f64,
+) -> PyResult<i32> {
+    let probs = get_probs_impl(query, &borrow_names(&train_data), params)?;
+    return Ok(probs
+        .iter()
+        .filter(|(collection, prob)| **collection != 0 && **prob > min_prob)
+        .count() as i32);
+}
+
+/// The number of test names whose two most probable collections (including the unknown
+/// bucket) are within epsilon of each other in probability, so that the tie-break decides
+/// the top choice.
+#[pyfunction]
+#[pyo3(signature = (train_data, test_data, params, epsilon=1e-12))]
+fn count_ties(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    epsilon: f64,
+) -> PyResult<i32> {
+    let train_data = borrow_names(&train_data);
+    let mut ties = 0;
+    for nam in borrow_names(&test_data) {
+        let probs = get_probs_impl(nam, &train_data, params)?;
+        if probs.len() < 2 {
+            continue;
+        }
+        if let Some((collection, probability)) = best_choice(&probs) {
+            if probability - runner_up_probability(&probs, collection) <= epsilon {
+                ties += 1;
+            }
+        }
+    }
+    return Ok(ties);
+}
+
+/// The k most probable collections (including the unknown bucket), most probable first,
+/// regardless of the probability cutoff. Ties are broken as in get_top_choice.
+#[pyfunction]
+fn get_top_k(
+    data: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    k: usize,
+) -> PyResult<Vec<(i32, f64)>> {
+    let probs = get_probs_impl(data, &borrow_names(&train_data), params)?;
+    return Ok(top_k(probs.into_iter(), k));
+}
+
+/// An entry of a bounded heap, ordered so that greater entries have greater values. Among
+/// equal values the lowest key ranks highest, as in best_choice.
+struct Ranked<K>(K, f64);
+
+impl<K: Ord> PartialEq for Ranked<K> {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == std::cmp::Ordering::Equal;
+    }
+}
+
+impl<K: Ord> Eq for Ranked<K> {}
+
+impl<K: Ord> PartialOrd for Ranked<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl<K: Ord> Ord for Ranked<K> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        return self.1.total_cmp(&other.1).then(other.0.cmp(&self.0));
+    }
+}
+
+/// The k entries with the greatest values, greatest first. Keeps a min-heap of the best k
+/// seen so far, so only k entries are ever sorted.
+fn top_k<K: Ord>(entries: impl Iterator<Item = (K, f64)>, k: usize) -> Vec<(K, f64)> {
+    if k == 0 {
+        return Vec::new();
+    }
+    let mut heap = std::collections::BinaryHeap::with_capacity(k + 1);
+    for (key, value) in entries {
+        heap.push(std::cmp::Reverse(Ranked(key, value)));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    // Ascending order of the reversed entries is descending value
+    return heap
+        .into_sorted_vec()
+        .into_iter()
+        .map(|std::cmp::Reverse(Ranked(key, value))| (key, value))
+        .collect();
+}
+
+/// The choice, unless it fails the probability cutoff or the margin over the runner-up.
+fn check_choice(
+    probs: &std::collections::HashMap<i32, f64>,
+    (collection, probability): (i32, f64),
+    params: &Params,
+) -> Option<(i32, f64)> {
+    if probability <= params.probability_cutoff
+        || probability - runner_up_probability(probs, collection) < params.min_margin
+    {
+        return None;
+    }
+    return Some((collection, probability));
+}
+
+/// The highest probability of any collection other than best, or 0 if there is none.
+fn runner_up_probability(probs: &std::collections::HashMap<i32, f64>, best: i32) -> f64 {
+    return probs
+        .iter()
+        .filter(|(collection, _)| **collection != best)
+        .map(|(_, prob)| *prob)
+        .fold(0.0, f64::max);
+}
+
+/// Like get_top_choice, but stops scanning the training set once the remaining names
+/// cannot change the outcome. The chosen collection is always the same as get_top_choice's;
+/// when the scan stops early the returned probability is a lower bound on the exact one.
+#[pyfunction]
+fn get_top_choice_early(
+    data: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<Option<(i32, f64)>> {
+    return get_top_choice_early_impl(data, &borrow_names(&train_data), params);
+}
+
+// How many training names to score between checks for early termination.
+const EARLY_TERMINATION_INTERVAL: usize = 64;
+
+fn get_top_choice_early_impl(
+    data: &NameData,
+    train_data: &[&NameData],
+    params: &Params,
+) -> PyResult<Option<(i32, f64)>> {
+    // The probability bounds assume all summed scores normalized together with an
+    // unadjusted unknown bucket, and say nothing about the margin
+    if !params.normalize_with_unknown
+        || params.aggregation != Aggregation::Sum
+        || params.min_unknown_prob > 0.0
+        || params.min_margin > 0.0
+        || params.max_contributions > 0
+        || params.nn_blend != 0.0
+    {
+        return get_top_choice_impl(data, train_data, params);
+    }
+    let upper_bound = match max_score(params) {
+        // Softened scores below the cutoff could exceed the bound if the factor is above 1
+        Some(upper_bound) => upper_bound * params.below_cutoff_factor.max(1.0),
+        None => return get_top_choice_impl(data, train_data, params),
+    };
+    let mut raw_scores = RawScores::new(params);
+    for (index, train_datum) in train_data.iter().enumerate() {
+        if let Some(collection) = train_datum.collection {
+            if !excluded(data, train_datum, params) {
+                let score = get_score_impl(data, train_datum, params)?;
+                raw_scores.add(collection, score, params);
+            }
+        }
+        let remaining = train_data.len() - index - 1;
+        if remaining > 0 && (index + 1) % EARLY_TERMINATION_INTERVAL == 0 {
+            if let Some(choice) = settled_choice(&raw_scores, remaining, upper_bound, params) {
+                return Ok(Some(choice));
+            }
+        }
+    }
+    return Ok(raw_scores.top_choice(params));
+}
+
+/// Returns the top choice if no assignment of the remaining training names' scores could
+/// change it, together with a lower bound on its final probability.
+fn settled_choice(
+    raw_scores: &RawScores,
+    remaining: usize,
+    upper_bound: f64,
+    params: &Params,
+) -> Option<(i32, f64)> {
+    let totals = raw_scores.totals();
+    let (leader, leader_score) = best_choice(&totals)?;
+    // Each remaining name adds at most upper_bound to one collection, and may also raise
+    // the highest score that goes into the unknown bucket.
+    let remaining_score = (remaining as f64) * upper_bound;
+    let unknown_increase = match raw_scores.params.unknown_bucket_score {
+        Some(_) => 0.0,
+        None => (upper_bound - raw_scores.highest_score).max(0.0),
+    };
+    // Collections not seen yet start from zero.
+    let mut strongest_rival = remaining_score;
+    for (collection, score) in totals.iter() {
+        if *collection == leader {
+            continue;
+        }
+        let mut rival_bound = score + remaining_score;
+        if *collection == 0 {
+            rival_bound += unknown_increase;
+        }
+        strongest_rival = strongest_rival.max(rival_bound);
+    }
+    if leader_score <= strongest_rival {
+        return None;
+    }
+    let total_score: f64 = totals.values().sum();
+    let min_probability = leader_score / (total_score + remaining_score + unknown_increase);
+    if min_probability <= params.probability_cutoff {
+        return None;
+    }
+    return Some((leader, min_probability));
+}
+
+/// The collection predicted most often for query across n_bags resamples of train_data
+/// (drawn with replacement, reproducibly from seed), with the fraction of resamples that
+/// predicted it. Ties go to the lowest collection id; None if no resample predicts anything.
+#[pyfunction]
+fn bagged_top_choice(
+    py: Python<'_>,
+    query: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    n_bags: usize,
+    seed: u64,
+) -> PyResult<Option<(i32, f64)>> {
+    if n_bags == 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "n_bags must be positive",
+        ));
+    }
+    let train_data = borrow_names(&train_data);
+    // Draw every resample before going parallel so they only depend on the seed
+    let mut rng = SeededRng::new(seed);
+    let bags: Vec<Vec<&NameData>> = (0..n_bags)
+        .map(|_| {
+            (0..train_data.len())
+                .map(|_| train_data[rng.below(train_data.len())])
+                .collect()
+        })
+        .collect();
+    let predictions =
+        py.allow_threads(|| parallel_map(&bags, |bag| get_top_choice_impl(query, bag, params)));
+    let mut votes: std::collections::HashMap<i32, f64> = std::collections::HashMap::new();
+    for prediction in predictions {
+        if let Some((collection, _)) = prediction? {
+            *votes.entry(collection).or_insert(0.0) += 1.0;
+        }
+    }
+    return Ok(best_choice(&votes).map(|(collection, count)| (collection, count / n_bags as f64)));
+}
+
+/// The most probable top choice of query under any of params_list, with the index of the
+/// Params that produced it. Params under which no collection is predicted are skipped;
+/// ties go to the earliest Params.
+#[pyfunction]
+fn best_over_params(
+    query: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params_list: Vec<PyRef<'_, Params>>,
+) -> PyResult<Option<(i32, f64, usize)>> {
+    let train_data = borrow_names(&train_data);
+    let mut best: Option<(i32, f64, usize)> = None;
+    for (index, params) in params_list.iter().enumerate() {
+        let Some((collection, probability)) = get_top_choice_impl(query, &train_data, params)?
+        else {
+            continue;
+        };
+        if best.is_none_or(|(_, best_probability, _)| probability > best_probability) {
+            best = Some((collection, probability, index));
+        }
+    }
+    return Ok(best);
+}
+
+/// The top choice for query if its country were each of candidate_countries instead, with
+/// everything else unchanged.
+#[pyfunction]
+fn counterfactual_country(
+    query: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    candidate_countries: Vec<i32>,
+) -> PyResult<std::collections::HashMap<i32, Option<(i32, f64)>>> {
+    let train_data = borrow_names(&train_data);
+    let mut result = std::collections::HashMap::new();
+    for tl_country in candidate_countries {
+        let counterfactual = NameData {
+            tl_country,
+            ..query.clone()
+        };
+        let top_choice = get_top_choice_impl(&counterfactual, &train_data, params)?;
+        result.insert(tl_country, top_choice);
+    }
+    return Ok(result);
+}
+
+/// The collection of the single highest-scoring training name, with its score, as a
+/// nearest-neighbor baseline to the summed-score prediction. Only scores above
+/// score_cutoff are considered; ties go to the earlier training name.
+#[pyfunction]
+fn nearest_neighbor_choice(
+    query: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<Option<(i32, f64)>> {
+    return nearest_neighbor_choice_impl(query, &borrow_names(&train_data), params);
+}
+
+fn nearest_neighbor_choice_impl(
+    query: &NameData,
+    train_data: &[&NameData],
+    params: &Params,
+) -> PyResult<Option<(i32, f64)>> {
+    let mut best: Option<(i32, f64)> = None;
+    for train_datum in train_data.iter() {
+        let Some(collection) = train_datum.collection else {
+            continue;
+        };
+        if excluded(query, train_datum, params) {
+            continue;
+        }
+        let score = get_score_impl(query, train_datum, params)?;
+        if score > params.score_cutoff && best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((collection, score));
+        }
+    }
+    return Ok(best);
+}
+
+/// The fraction of test names for which get_top_choice and nearest_neighbor_choice pick the
+/// same collection (or both pick none), and the name_ids of the others. The fraction is 1
+/// for an empty test set.
+#[pyfunction]
+fn model_vs_nn_agreement(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<(f64, Vec<i32>)> {
+    let train_data = borrow_names(&train_data);
+    let test_data = borrow_names(&test_data);
+    let mut disagreements: Vec<i32> = Vec::new();
+    for nam in test_data.iter() {
+        let model = get_top_choice_impl(nam, &train_data, params)?;
+        let nearest = nearest_neighbor_choice_impl(nam, &train_data, params)?;
+        if model.map(|(collection, _)| collection) != nearest.map(|(collection, _)| collection) {
+            disagreements.push(nam.name_id);
+        }
+    }
+    if test_data.is_empty() {
+        return Ok((1.0, disagreements));
+    }
+    let agreement = 1.0 - disagreements.len() as f64 / test_data.len() as f64;
+    return Ok((agreement, disagreements));
+}
+
+/// The number of distinct collections get_top_choice predicts for the test names, and the
+/// Shannon entropy of how often each is predicted, divided by its maximum (the log of that
+/// number) so that 1 means every predicted collection is predicted equally often. The
+/// entropy is 0 if at most one collection is ever predicted. Names for which no collection
+/// is predicted are left out.
+#[pyfunction]
+fn prediction_diversity(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<(i32, f64)> {
+    let train_data = borrow_names(&train_data);
+    let mut counts: std::collections::HashMap<i32, usize> = std::collections::HashMap::new();
+    for nam in borrow_names(&test_data) {
+        if let Some((collection, _)) = get_top_choice_impl(nam, &train_data, params)? {
+            *counts.entry(collection).or_insert(0) += 1;
+        }
+    }
+    let distinct = counts.len();
+    if distinct <= 1 {
+        return Ok((distinct as i32, 0.0));
+    }
+    let total = counts.values().sum::<usize>() as f64;
+    let entropy = -counts
+        .values()
+        .map(|count| {
+            let share = (*count as f64) / total;
+            share * share.ln()
+        })
+        .sum::<f64>();
+    return Ok((distinct as i32, entropy / (distinct as f64).ln()));
+}
+
+/// The training name that contributed the most to the score of the predicted collection,
+/// with its contribution, or None if no collection is predicted.
+#[pyfunction]
+fn most_influential(
+    query: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<Option<(i32, f64)>> {
+    let train_data = borrow_names(&train_data);
+    let Some((predicted, _)) = get_top_choice_impl(query, &train_data, params)? else {
+        return Ok(None);
+    };
+    let contributor = strongest_contributor(query, &train_data, predicted, params)?;
+    return Ok(contributor.map(|(train_datum, contribution)| (train_datum.name_id, contribution)));
+}
+
+/// Every training name that adds to the score of the predicted collection, as (name_id,
+/// contribution) from the largest contribution down, with ties in training order. Without
+/// max_contributions, the contributions add up to the collection's summed score. Empty if
+/// no collection is predicted.
+#[pyfunction]
+fn winning_contributions(
+    query: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<Vec<(i32, f64)>> {
+    let train_data = borrow_names(&train_data);
+    let Some((predicted, _)) = get_top_choice_impl(query, &train_data, params)? else {
+        return Ok(Vec::new());
+    };
+    let mut contributions: Vec<(i32, f64)> = Vec::new();
+    for train_datum in train_data {
+        if train_datum.collection != Some(predicted) || excluded(query, train_datum, params) {
+            continue;
+        }
+        let contribution = contribution(get_score_impl(query, train_datum, params)?, params);
+        if contribution > 0.0 {
+            contributions.push((train_datum.name_id, contribution));
+        }
+    }
+    contributions.sort_by(|a, b| b.1.total_cmp(&a.1));
+    return Ok(contributions);
+}
+
+/// For each test name, its name_id and whether removing the training name that most_influential
+/// returns for it changes the predicted collection. Names without a prediction have no
+/// such training name and never change.
+#[pyfunction]
+fn top_contributor_sensitivity(
+    py: Python<'_>,
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<Vec<(i32, bool)>> {
+    let train_data = borrow_names(&train_data);
+    let test_data = borrow_names(&test_data);
+    let changes = py.allow_threads(|| {
+        parallel_map(&test_data, |nam| -> PyResult<bool> {
+            let Some((predicted, _)) = get_top_choice_impl(nam, &train_data, params)? else {
+                return Ok(false);
+            };
+            let Some((contributor, _)) =
+                strongest_contributor(nam, &train_data, predicted, params)?
+            else {
+                return Ok(false);
+            };
+            let remaining: Vec<&NameData> = train_data
+                .iter()
+                .copied()
+                .filter(|train_datum| !std::ptr::eq(*train_datum, contributor))
+                .collect();
+            let choice = get_top_choice_impl(nam, &remaining, params)?;
+            return Ok(choice.map(|(collection, _)| collection) != Some(predicted));
+        })
+    });
+    return test_data
+        .iter()
+        .zip(changes)
+        .map(|(nam, changed)| Ok((nam.name_id, changed?)))
+        .collect();
+}
+
+/// What a training name with this score adds to its collection's score, by the same rule
+/// RawScores::add uses.
+fn contribution(score: f64, params: &Params) -> f64 {
+    if score > params.score_cutoff {
+        return score;
+    }
+    return score * params.below_cutoff_factor.max(0.0);
+}
+
+/// The training name in collection that adds the most to query's score for it.
+fn strongest_contributor<'a>(
+    query: &NameData,
+    train_data: &[&'a NameData],
+    collection: i32,
+    params: &Params,
+) -> PyResult<Option<(&'a NameData, f64)>> {
+    let mut best: Option<(&NameData, f64)> = None;
+    for train_datum in train_data.iter().copied() {
+        if train_datum.collection != Some(collection) || excluded(query, train_datum, params) {
+            continue;
+        }
+        let contribution = contribution(get_score_impl(query, train_datum, params)?, params);
+        if contribution > 0.0 && best.is_none_or(|(_, best_score)| contribution > best_score) {
+            best = Some((train_datum, contribution));
+        }
+    }
+    return Ok(best);
+}
+
+/// The prediction for a query with what explains it, as returned by explain_prediction.
+/// Everything is None if no collection is predicted.
+#[pyclass(get_all, frozen)]
+struct PredictionExplanation {
+    collection: Option<i32>,
+    probability: Option<f64>,
+    // The probability above that of the next most probable collection
+    margin: Option<f64>,
+    // The training name that most_influential returns, with its contribution
+    influential_name_id: Option<i32>,
+    contribution: Option<f64>,
+    // How the query compares with that training name
+    field_match: Option<FieldMatch>,
+}
+
+#[pymethods]
+impl PredictionExplanation {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "PredictionExplanation(collection={}, probability={}, margin={}, influential_name_id={}, contribution={}, field_match={})",
+            format_optional(self.collection),
+            format_optional(self.probability),
+            format_optional(self.margin),
+            format_optional(self.influential_name_id),
+            format_optional(self.contribution),
+            match &self.field_match {
+                Some(field_match) => field_match.__repr__()?,
+                None => "None".to_string(),
+            }
+        ))
+    }
+}
+
+/// The results of get_top_choice, most_influential and field_match for the query and the
+/// most influential training name, scoring the training set only once.
+#[pyfunction]
+fn explain_prediction(
+    query: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<PredictionExplanation> {
+    let mut scored: Vec<(&NameData, i32, f64)> = Vec::with_capacity(train_data.len());
+    for train_datum in borrow_names(&train_data) {
+        let Some(collection) = train_datum.collection else {
+            continue;
+        };
+        if excluded(query, train_datum, params) {
+            continue;
+        }
+        scored.push((
+            train_datum,
+            collection,
+            get_score_impl(query, train_datum, params)?,
+        ));
+    }
+    let mut raw_scores = RawScores::new(params);
+    raw_scores.add_all(
+        scored
+            .iter()
+            .map(|(_, collection, score)| (*collection, *score))
+            .collect(),
+        params,
+    );
+    let Some((predicted, probability)) = raw_scores.top_choice(params) else {
+        return Ok(PredictionExplanation {
+            collection: None,
+            probability: None,
+            margin: None,
+            influential_name_id: None,
+            contribution: None,
+            field_match: None,
+        });
+    };
+    let margin = probability - runner_up_probability(&raw_scores.normalized(), predicted);
+    let mut influential: Option<(&NameData, f64)> = None;
+    for (train_datum, collection, score) in scored {
+        let contribution = contribution(score, params);
+        if collection == predicted
+            && contribution > 0.0
+            && influential.is_none_or(|(_, best)| contribution > best)
+        {
+            influential = Some((train_datum, contribution));
+        }
+    }
+    return Ok(PredictionExplanation {
+        collection: Some(predicted),
+        probability: Some(probability),
+        margin: Some(margin),
+        influential_name_id: influential.map(|(train_datum, _)| train_datum.name_id),
+        contribution: influential.map(|(_, contribution)| contribution),
+        field_match: influential.map(|(train_datum, _)| field_match(query, train_datum)),
+    });
+}
+
+/// The misclassifications of one true collection as one predicted collection, described
+/// by how each misclassified name compares to its strongest contributor (see
+/// most_influential) in the predicted collection.
+#[pyclass(get_all, frozen)]
+struct ConfusionExplanation {
+    true_collection: i32,
+    predicted_collection: i32,
+    count: i32,
+    // How many of the misclassified names had a contributor; the averages below are over
+    // these and are None if there are none.
+    explained: i32,
+    mean_author_overlap: Option<f64>,
+    // Also None if no name and its contributor both have a year
+    mean_year_difference: Option<f64>,
+    country_match_rate: Option<f64>,
+    cg_match_rate: Option<f64>,
+}
+
+#[pymethods]
+impl ConfusionExplanation {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "ConfusionExplanation(true_collection={}, predicted_collection={}, count={}, explained={}, mean_author_overlap={}, mean_year_difference={}, country_match_rate={}, cg_match_rate={})",
+            self.true_collection,
+            self.predicted_collection,
+            self.count,
+            self.explained,
+            format_optional(self.mean_author_overlap),
+            format_optional(self.mean_year_difference),
+            format_optional(self.country_match_rate),
+            format_optional(self.cg_match_rate)
+        ))
+    }
+}
+
+#[derive(Default)]
+struct ConfusionTotals {
+    count: i32,
+    explained: i32,
+    author_overlap: f64,
+    year_difference: f64,
+    with_years: i32,
+    country_matches: i32,
+    cg_matches: i32,
+}
+
+/// The number of test names with each pair of true and predicted collection, including
+/// correct predictions. Names left unpredicted are not counted.
+#[pyfunction]
+fn confusion_matrix(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<std::collections::HashMap<(i32, i32), i32>> {
+    return confusion_matrix_impl(
+        &borrow_names(&train_data),
+        &borrow_names(&test_data),
+        params,
+    );
+}
+
+fn confusion_matrix_impl(
+    train_data: &[&NameData],
+    test_data: &[&NameData],
+    params: &Params,
+) -> PyResult<std::collections::HashMap<(i32, i32), i32>> {
+    let mut matrix: std::collections::HashMap<(i32, i32), i32> = std::collections::HashMap::new();
+    for nam in test_data.iter() {
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        if let Some((predicted, _)) = get_top_choice_impl(nam, train_data, params)? {
+            *matrix.entry((true_collection, predicted)).or_insert(0) += 1;
+        }
+    }
+    return Ok(matrix);
+}
+
+/// Pairs of collections that are confused with each other so often that they may be one
+/// collection: the names of either predicted as the other, as a fraction of all predicted
+/// names of both, when that exceeds confusion_threshold. Each pair appears once, with the
+/// lower id first, and the most confused pairs come first.
+#[pyfunction]
+fn merge_candidates(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    confusion_threshold: f64,
+) -> PyResult<Vec<(i32, i32, f64)>> {
+    let matrix = confusion_matrix_impl(
+        &borrow_names(&train_data),
+        &borrow_names(&test_data),
+        params,
+    )?;
+    let mut predicted_counts: std::collections::HashMap<i32, i32> =
+        std::collections::HashMap::new();
+    for ((true_collection, _), count) in matrix.iter() {
+        *predicted_counts.entry(*true_collection).or_insert(0) += count;
+    }
+    // The confusions in both directions, by pair with the lower id first
+    let mut confused: std::collections::HashMap<(i32, i32), i32> = std::collections::HashMap::new();
+    for ((true_collection, predicted), count) in matrix.iter() {
+        // The unknown bucket is not a collection to merge
+        if true_collection == predicted || *true_collection == 0 || *predicted == 0 {
+            continue;
+        }
+        let pair = if true_collection < predicted {
+            (*true_collection, *predicted)
+        } else {
+            (*predicted, *true_collection)
+        };
+        *confused.entry(pair).or_insert(0) += count;
+    }
+    let mut result: Vec<(i32, i32, f64)> = Vec::new();
+    for ((collection1, collection2), count) in confused {
+        let total = predicted_counts.get(&collection1).unwrap_or(&0)
+            + predicted_counts.get(&collection2).unwrap_or(&0);
+        let rate = count as f64 / total as f64;
+        if rate > confusion_threshold {
+            result.push((collection1, collection2, rate));
+        }
+    }
+    result.sort_by(|a, b| b.2.total_cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+    return Ok(result);
+}
+
+/// One ConfusionExplanation for each pair of true and predicted collections that occurs
+/// among the wrong predictions on test_data, the most frequent first.
+#[pyfunction]
+fn misclassification_report(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<Vec<ConfusionExplanation>> {
+    let train_data = borrow_names(&train_data);
+    let mut confusions: std::collections::HashMap<(i32, i32), ConfusionTotals> =
+        std::collections::HashMap::new();
+    for nam in borrow_names(&test_data) {
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        let Some((predicted, _)) = get_top_choice_impl(nam, &train_data, params)? else {
+            continue;
+        };
+        if predicted == true_collection {
+            continue;
+        }
+        let totals = confusions.entry((true_collection, predicted)).or_default();
+        totals.count += 1;
+        let Some((contributor, _)) = strongest_contributor(nam, &train_data, predicted, params)?
+        else {
+            continue;
+        };
+        let matches = field_match(nam, contributor);
+        totals.explained += 1;
+        totals.author_overlap += author_overlap(nam, contributor, Some(params));
+        if let Some(year_difference) = matches.year_difference {
+            totals.year_difference += year_difference as f64;
+            totals.with_years += 1;
+        }
+        totals.country_matches += matches.country_match as i32;
+        totals.cg_matches += matches.cg_match as i32;
+    }
+    let mean = |sum: f64, count: i32| {
+        if count == 0 {
+            return None;
+        }
+        return Some(sum / (count as f64));
+    };
+    let mut result: Vec<ConfusionExplanation> = confusions
+        .into_iter()
+        .map(
+            |((true_collection, predicted_collection), totals)| ConfusionExplanation {
+                true_collection,
+                predicted_collection,
+                count: totals.count,
+                explained: totals.explained,
+                mean_author_overlap: mean(totals.author_overlap, totals.explained),
+                mean_year_difference: mean(totals.year_difference, totals.with_years),
+                country_match_rate: mean(totals.country_matches as f64, totals.explained),
+                cg_match_rate: mean(totals.cg_matches as f64, totals.explained),
+            },
+        )
+        .collect();
+    result.sort_by_key(|explanation| {
+        (
+            -explanation.count,
+            explanation.true_collection,
+            explanation.predicted_collection,
+        )
+    });
+    return Ok(result);
+}
+
+/// The result of a prediction, including why no collection was predicted.
+#[pyclass(get_all, frozen)]
+enum PredictionOutcome {
+    /// The collection was predicted with the given probability.
+    Predicted { collection: i32, probability: f64 },
+    /// The most probable collection did not pass the probability cutoff.
+    BelowCutoff { best_probability: f64 },
+    /// The most probable collection passed the probability cutoff, but led the next one by
+    /// less than min_margin.
+    Ambiguous {
+        collection: i32,
+        probability: f64,
+        margin: f64,
+    },
+    /// No training name passed the score cutoff.
+    NoCandidates {},
+}
+
+#[pymethods]
+impl PredictionOutcome {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(match self {
+            PredictionOutcome::Predicted {
+                collection,
+                probability,
+            } => format!(
+                "PredictionOutcome.Predicted(collection={}, probability={})",
+                collection, probability
+            ),
+            PredictionOutcome::BelowCutoff { best_probability } => format!(
+                "PredictionOutcome.BelowCutoff(best_probability={})",
+                best_probability
+            ),
+            PredictionOutcome::Ambiguous {
+                collection,
+                probability,
+                margin,
+            } => format!(
+                "PredictionOutcome.Ambiguous(collection={}, probability={}, margin={})",
+                collection, probability, margin
+            ),
+            PredictionOutcome::NoCandidates {} => "PredictionOutcome.NoCandidates()".to_string(),
+        })
+    }
+}
+
+#[pyfunction]
+fn top_choice_explained(
+    data: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<PredictionOutcome> {
+    return top_choice_explained_impl(data, &borrow_names(&train_data), params);
+}
+
+fn top_choice_explained_impl(
+    data: &NameData,
+    train_data: &[&NameData],
+    params: &Params,
+) -> PyResult<PredictionOutcome> {
+    let raw_scores = get_raw_scores_impl(data, train_data, params)?;
+    if raw_scores.num_candidates() == 0 {
+        return Ok(PredictionOutcome::NoCandidates {});
+    }
+    let probs = raw_scores.normalized();
+    return Ok(match raw_scores.best_choice(&probs, params) {
+        Some((collection, probability)) if probability > params.probability_cutoff => {
+            let margin = probability - runner_up_probability(&probs, collection);
+            if margin < params.min_margin {
+                PredictionOutcome::Ambiguous {
+                    collection,
+                    probability,
+                    margin,
+                }
+            } else {
+                PredictionOutcome::Predicted {
+                    collection,
+                    probability,
+                }
+            }
+        }
+        Some((_, best_probability)) => PredictionOutcome::BelowCutoff { best_probability },
+        None => PredictionOutcome::NoCandidates {},
+    });
+}
+
+/// The outcome of top_choice_explained for each name in data, computed across all cores.
+#[pyfunction]
+fn predict_batch(
+    py: Python<'_>,
+    data: Vec<Bound<'_, NameData>>,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<Vec<PredictionOutcome>> {
+    let data = borrow_names(&data);
+    let train_data = borrow_names(&train_data);
+    let outcomes = py.allow_threads(|| {
+        parallel_map(&data, |nam| {
+            top_choice_explained_impl(nam, &train_data, params)
+        })
+    });
+    return outcomes.into_iter().collect();
+}
+
+#[pyclass(get_all, frozen)]
+#[derive(Clone)]
+struct ScoreInfo {
+    // Computed from the weighted counts
+    score: f64,
+    correct: i32,
+    incorrect: i32,
+    no_value: i32,
+    // Predictions of a different collection with the same parent as the true one, which
+    // are not counted as incorrect; always 0 without parents
+    partial_correct: i32,
+    // The counts with each test name counted by its weight; equal to the counts above
+    // when no weights are given.
+    weighted_correct: f64,
+    weighted_incorrect: f64,
+    weighted_no_value: f64,
+    weighted_partial_correct: f64,
+}
+
+#[pymethods]
+impl ScoreInfo {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "ScoreInfo(score={}, correct={}, incorrect={}, no_value={}, partial_correct={})",
+            self.score, self.correct, self.incorrect, self.no_value, self.partial_correct
+        ))
+    }
+}
+
+/// Evaluates predictions on test_data. If weights are given, there must be one for each
+/// test name, and each name counts by its weight in the score. If parents (mapping
+/// collections to their parent collections) are given, predicting a sibling of the true
+/// collection counts as partial_correct and earns the scoring function's partial_credit.
+/// If balance_collections is set, each name's weight is further multiplied by the inverse
+/// frequency of its true collection in test_data, scaled so that the weights of the
+/// evaluated names still add up to their number, and every collection counts equally.
+/// With leave_one_out, training names with the same name_id as a test name are left out
+/// when predicting it, as are exact duplicates of it with exclude_duplicates, for honest
+/// results when the training and test sets overlap.
+#[pyfunction]
+#[pyo3(signature = (
+    train_data,
+    test_data,
+    scoring_function,
+    params,
+    weights=None,
+    parents=None,
+    balance_collections=false,
+    leave_one_out=false,
+    exclude_duplicates=false,
+))]
+#[allow(clippy::too_many_arguments)]
+fn evaluate_model(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    weights: Option<Vec<f64>>,
+    parents: Option<std::collections::HashMap<i32, i32>>,
+    balance_collections: bool,
+    leave_one_out: bool,
+    exclude_duplicates: bool,
+) -> PyResult<ScoreInfo> {
+    let leave_out = match (leave_one_out, exclude_duplicates) {
+        (false, false) => LeaveOut::Nothing,
+        (true, false) => LeaveOut::SameId,
+        (true, true) => LeaveOut::SameIdOrDuplicate,
+        (false, true) => {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "exclude_duplicates requires leave_one_out",
+            ))
+        }
+    };
+    if let Some(weights) = &weights {
+        if weights.len() != test_data.len() {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "got {} weights for {} test names",
+                weights.len(),
+                test_data.len()
+            )));
+        }
+        if weights.iter().any(|weight| *weight < 0.0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "weights must be non-negative",
+            ));
+        }
+    }
+    let test_data = borrow_names(&test_data);
+    let weights = if balance_collections {
+        let balance = collection_balance_weights(&test_data);
+        Some(match weights {
+            Some(weights) => weights.iter().zip(balance).map(|(a, b)| a * b).collect(),
+            None => balance,
+        })
+    } else {
+        weights
+    };
+    return evaluate_model_weighted_impl(
+        &borrow_names(&train_data),
+        &test_data,
+        weights.as_deref(),
+        parents.as_ref(),
+        leave_out,
+        scoring_function,
+        params,
+    );
+}
+
+/// Which training names evaluate_model leaves out when predicting a test name.
+#[derive(Clone, Copy, PartialEq)]
+enum LeaveOut {
+    Nothing,
+    // Names with the same name_id
+    SameId,
+    // Names with the same name_id, or the same values in every field other than name_id
+    // and collection
+    SameIdOrDuplicate,
+}
+
+impl LeaveOut {
+    fn excludes(&self, test_datum: &NameData, train_datum: &NameData) -> bool {
+        return match self {
+            LeaveOut::Nothing => false,
+            LeaveOut::SameId => train_datum.name_id == test_datum.name_id,
+            LeaveOut::SameIdOrDuplicate => {
+                train_datum.name_id == test_datum.name_id
+                    || (train_datum.tl_country == test_datum.tl_country
+                        && train_datum.year == test_datum.year
+                        && train_datum.same_authors(test_datum)
+                        && train_datum.citation_groups == test_datum.citation_groups)
+            }
+        };
+    }
+}
+
+/// The expected evaluate_model score when each test name's label is right only with the
+/// probability in test_confidences (one for each test name). A prediction matching the
+/// label is correct with that probability and incorrect otherwise; a prediction of another
+/// collection is incorrect if the label is right, and counts for nothing otherwise, since
+/// the true collection is then unknown. With every confidence at 1, this is the score of
+/// evaluate_model.
+#[pyfunction]
+fn expected_score(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    test_confidences: Vec<f64>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+) -> PyResult<f64> {
+    return expected_score_impl(
+        &borrow_names(&train_data),
+        &borrow_names(&test_data),
+        &test_confidences,
+        scoring_function,
+        params,
+    );
+}
+
+fn expected_score_impl(
+    train_data: &[&NameData],
+    test_data: &[&NameData],
+    test_confidences: &[f64],
+    scoring_function: &ScoringFunction,
+    params: &Params,
+) -> PyResult<f64> {
+    if test_confidences.len() != test_data.len() {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "got {} confidences for {} test names",
+            test_confidences.len(),
+            test_data.len()
+        )));
+    }
+    if test_confidences
+        .iter()
+        .any(|confidence| !(0.0..=1.0).contains(confidence))
+    {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "confidences must be between 0 and 1",
+        ));
+    }
+    let mut counter = ScoreCounter::default();
+    for (nam, confidence) in test_data.iter().zip(test_confidences.iter().copied()) {
+        let Some(label) = nam.collection else {
+            continue;
+        };
+        let prediction =
+            get_top_choice_impl(nam, train_data, params)?.map(|(collection, _)| collection);
+        match prediction {
+            Some(collection) if collection == label => {
+                counter.record_weighted(Some(label), label, confidence, None);
+                counter.record_weighted_miss(1.0 - confidence);
+            }
+            Some(_) => counter.record_weighted(prediction, label, confidence, None),
+            None => counter.record(None, label),
+        }
+    }
+    return Ok(counter.finish(scoring_function).score);
+}
+
+/// For each test name, total / (number of collections × names in its collection),
+/// counting only names with a known collection. Names without one get 1 (they are not
+/// evaluated anyway).
+fn collection_balance_weights(test_data: &[&NameData]) -> Vec<f64> {
+    let mut counts: std::collections::HashMap<i32, usize> = std::collections::HashMap::new();
+    for nam in test_data.iter() {
+        if let Some(collection) = nam.collection {
+            *counts.entry(collection).or_insert(0) += 1;
+        }
+    }
+    let total: usize = counts.values().sum();
+    let num_collections = counts.len();
+    return test_data
+        .iter()
+        .map(|nam| match nam.collection {
+            Some(collection) => total as f64 / (num_collections * counts[&collection]) as f64,
+            None => 1.0,
+        })
+        .collect();
+}
+
+fn evaluate_model_impl(
+    train_data: &[&NameData],
+    test_data: &[&NameData],
+    scoring_function: &ScoringFunction,
+    params: &Params,
+) -> PyResult<ScoreInfo> {
+    return evaluate_model_weighted_impl(
+        train_data,
+        test_data,
+        None,
+        None,
+        LeaveOut::Nothing,
+        scoring_function,
+        params,
+    );
+}
+
+fn evaluate_model_weighted_impl(
+    train_data: &[&NameData],
+    test_data: &[&NameData],
+    weights: Option<&[f64]>,
+    parents: Option<&std::collections::HashMap<i32, i32>>,
+    leave_out: LeaveOut,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+) -> PyResult<ScoreInfo> {
+    let mut counter = ScoreCounter::default();
+    for (index, nam) in test_data.iter().enumerate() {
+        // Names without a known collection cannot be evaluated
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        let top_choice = if leave_out == LeaveOut::Nothing {
+            get_top_choice_impl(nam, train_data, params)?
+        } else {
+            let remaining: Vec<&NameData> = train_data
+                .iter()
+                .copied()
+                .filter(|train_datum| !leave_out.excludes(nam, train_datum))
+                .collect();
+            get_top_choice_impl(nam, &remaining, params)?
+        };
+        counter.record_weighted(
+            top_choice.map(|(collection, _)| collection),
+            true_collection,
+            weights.map_or(1.0, |weights| weights[index]),
+            parents,
+        );
+    }
+    return Ok(counter.finish(scoring_function));
+}
+
+/// Counts of prediction outcomes, turned into a ScoreInfo once all are recorded.
+#[derive(Clone, Copy, Default)]
+struct ScoreCounter {
+    correct: i32,
+    incorrect: i32,
+    no_value: i32,
+    partial_correct: i32,
+    weighted_correct: f64,
+    weighted_incorrect: f64,
+    weighted_no_value: f64,
+    weighted_partial_correct: f64,
+}
+
+impl ScoreCounter {
+    fn record(&mut self, prediction: Option<i32>, true_collection: i32) {
+        self.record_weighted(prediction, true_collection, 1.0, None);
+    }
+
+    fn record_weighted(
+        &mut self,
+        prediction: Option<i32>,
+        true_collection: i32,
+        weight: f64,
+        parents: Option<&std::collections::HashMap<i32, i32>>,
+    ) {
+        let same_parent = |collection: i32| {
+            parents.is_some_and(|parents| {
+                parents
+                    .get(&collection)
+                    .is_some_and(|parent| parents.get(&true_collection) == Some(parent))
+            })
+        };
+        match prediction {
+            Some(collection) if collection == true_collection => {
+                self.correct += 1;
+                self.weighted_correct += weight;
+            }
+            Some(collection) if same_parent(collection) => {
+                self.partial_correct += 1;
+                self.weighted_partial_correct += weight;
+            }
+            Some(_) => {
+                self.incorrect += 1;
+                self.weighted_incorrect += weight;
+            }
+            None => {
+                self.no_value += 1;
+                self.weighted_no_value += weight;
+            }
+        }
+    }
+
+    /// Records an incorrect prediction with the given weight, for when only the fact that
+    /// the prediction is wrong is known, not the true collection.
+    fn record_weighted_miss(&mut self, weight: f64) {
+        self.incorrect += 1;
+        self.weighted_incorrect += weight;
+    }
+
+    fn finish(&self, scoring_function: &ScoringFunction) -> ScoreInfo {
+        let score = self.weighted_correct
+            + (self.weighted_partial_correct * scoring_function.partial_credit)
+            - (self.weighted_incorrect * scoring_function.false_positive_cost)
+            - (self.weighted_no_value * scoring_function.false_negative_cost);
+        return ScoreInfo {
+            score,
+            correct: self.correct,
+            incorrect: self.incorrect,
+            no_value: self.no_value,
+            partial_correct: self.partial_correct,
+            weighted_correct: self.weighted_correct,
+            weighted_incorrect: self.weighted_incorrect,
+            weighted_no_value: self.weighted_no_value,
+            weighted_partial_correct: self.weighted_partial_correct,
+        };
+    }
+}
+
+/// The evaluation of test_data as a whole and for each true collection separately. The
+/// per-collection counts and scores add up to the overall ones.
+#[pyfunction]
+fn evaluate_full(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+) -> PyResult<(ScoreInfo, std::collections::HashMap<i32, ScoreInfo>)> {
+    let train_data = borrow_names(&train_data);
+    let mut overall = ScoreCounter::default();
+    let mut by_collection: std::collections::HashMap<i32, ScoreCounter> =
+        std::collections::HashMap::new();
+    for nam in borrow_names(&test_data) {
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        let prediction =
+            get_top_choice_impl(nam, &train_data, params)?.map(|(collection, _)| collection);
+        overall.record(prediction, true_collection);
+        by_collection
+            .entry(true_collection)
+            .or_default()
+            .record(prediction, true_collection);
+    }
+    return Ok((
+        overall.finish(scoring_function),
+        by_collection
+            .into_iter()
+            .map(|(collection, counter)| (collection, counter.finish(scoring_function)))
+            .collect(),
+    ));
+}
+
+/// The evaluation of the test names whose most probable collection has at least
+/// min_confidence probability, with the number of other (deferred) test names. Deferred
+/// names are left out of the ScoreInfo entirely, unlike names below probability_cutoff,
+/// which count as no_value.
+#[pyfunction]
+fn evaluate_confident(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    min_confidence: f64,
+) -> PyResult<(ScoreInfo, i32)> {
+    let train_data = borrow_names(&train_data);
+    let mut counter = ScoreCounter::default();
+    let mut deferred = 0;
+    for nam in borrow_names(&test_data) {
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        let raw_scores = get_raw_scores_impl(nam, &train_data, params)?;
+        let probs = raw_scores.normalized();
+        if best_choice(&probs).is_none_or(|(_, probability)| probability < min_confidence) {
+            deferred += 1;
+            continue;
+        }
+        let prediction = raw_scores
+            .choose(&probs, params)
+            .map(|(collection, _)| collection);
+        counter.record(prediction, true_collection);
+    }
+    return Ok((counter.finish(scoring_function), deferred));
+}
+
+/// Sharpens (temperature below 1) or flattens (above 1) a probability distribution by
+/// raising each probability to 1 / temperature and renormalizing.
+fn apply_temperature(
+    probs: &std::collections::HashMap<i32, f64>,
+    temperature: f64,
+) -> std::collections::HashMap<i32, f64> {
+    // Probabilities are at most 1, so dividing by the largest power keeps them from
+    // underflowing together for small temperatures
+    let log_max = probs.values().copied().fold(0.0, f64::max).ln();
+    if log_max == f64::NEG_INFINITY {
+        return probs.clone();
+    }
+    let scaled: std::collections::HashMap<i32, f64> = probs
+        .iter()
+        .map(|(collection, prob)| (*collection, ((prob.ln() - log_max) / temperature).exp()))
+        .collect();
+    let total: f64 = scaled.values().sum();
+    return scaled
+        .into_iter()
+        .map(|(collection, prob)| (collection, prob / total))
+        .collect();
+}
+
+// Probabilities are clamped to at least this before taking logarithms for the log-loss.
+const MIN_LOG_LOSS_PROBABILITY: f64 = 1e-15;
+
+/// The mean negative log probability of the true collections, after applying temperature.
+/// A true collection without a probability is new to the training set, so the unknown
+/// bucket's probability counts for it.
+fn mean_log_loss(items: &[(std::collections::HashMap<i32, f64>, i32)], temperature: f64) -> f64 {
+    if items.is_empty() {
+        return 0.0;
+    }
+    let total: f64 = items
+        .iter()
+        .map(|(probs, true_collection)| {
+            let probs = apply_temperature(probs, temperature);
+            let prob = probs
+                .get(true_collection)
+                .or_else(|| probs.get(&0))
+                .copied()
+                .unwrap_or(0.0);
+            -prob.max(MIN_LOG_LOSS_PROBABILITY).ln()
+        })
+        .sum();
+    return total / (items.len() as f64);
+}
+
+// The range of temperatures fit_temperature searches, and how many steps it takes.
+const MIN_TEMPERATURE: f64 = 0.01;
+const MAX_TEMPERATURE: f64 = 100.0;
+const TEMPERATURE_SEARCH_STEPS: usize = 100;
+
+/// The temperature minimizing mean_log_loss on the items, by golden-section search over
+/// the logarithm of the temperature. The log-loss is unimodal in the temperature, so this
+/// finds the minimum within the searched range; a temperature of 1 (no change) is kept
+/// unless the search finds a strictly better one.
+fn fit_temperature(items: &[(std::collections::HashMap<i32, f64>, i32)]) -> f64 {
+    let ratio = (5.0_f64.sqrt() - 1.0) / 2.0;
+    let loss = |log_temperature: f64| mean_log_loss(items, log_temperature.exp());
+    let (mut low, mut high) = (MIN_TEMPERATURE.ln(), MAX_TEMPERATURE.ln());
+    let mut left = high - ratio * (high - low);
+    let mut right = low + ratio * (high - low);
+    let (mut left_loss, mut right_loss) = (loss(left), loss(right));
+    for _ in 0..TEMPERATURE_SEARCH_STEPS {
+        if left_loss <= right_loss {
+            high = right;
+            right = left;
+            right_loss = left_loss;
+            left = high - ratio * (high - low);
+            left_loss = loss(left);
+        } else {
+            low = left;
+            left = right;
+            left_loss = right_loss;
+            right = low + ratio * (high - low);
+            right_loss = loss(right);
+        }
+    }
+    let temperature = ((low + high) / 2.0).exp();
+    if mean_log_loss(items, temperature) < mean_log_loss(items, 1.0) {
+        return temperature;
+    }
+    return 1.0;
+}
+
+/// Fits a temperature for the probabilities on the names in calib_data, then evaluates
+/// test_data with it applied. Returns the temperature, the ScoreInfo (which the
+/// temperature can only change through the probability cutoff and margin), and the mean
+/// log-loss of the test names' true collections. Names without a known collection are left
+/// out of both sets.
+#[pyfunction]
+fn evaluate_calibrated(
+    train_data: Vec<Bound<'_, NameData>>,
+    calib_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+) -> PyResult<(f64, ScoreInfo, f64)> {
+    let train_data = borrow_names(&train_data);
+    let labelled_scores = |data: &[Bound<'_, NameData>]| -> PyResult<Vec<_>> {
+        let mut items = Vec::new();
+        for nam in borrow_names(data) {
+            if let Some(true_collection) = nam.collection {
+                items.push((
+                    get_raw_scores_impl(nam, &train_data, params)?,
+                    true_collection,
+                ));
+            }
+        }
+        return Ok(items);
+    };
+    let labelled_probs = |items: &[(RawScores, i32)]| -> Vec<_> {
+        return items
+            .iter()
+            .map(|(raw_scores, true_collection)| (raw_scores.normalized(), *true_collection))
+            .collect();
+    };
+    let temperature = fit_temperature(&labelled_probs(&labelled_scores(&calib_data)?));
+    let test_scores = labelled_scores(&test_data)?;
+    let test_items = labelled_probs(&test_scores);
+    let mut counter = ScoreCounter::default();
+    for ((probs, true_collection), (raw_scores, _)) in test_items.iter().zip(&test_scores) {
+        let probs = apply_temperature(probs, temperature);
+        let prediction = raw_scores
+            .choose(&probs, params)
+            .map(|(collection, _)| collection);
+        counter.record(prediction, *true_collection);
+    }
+    return Ok((
+        temperature,
+        counter.finish(scoring_function),
+        mean_log_loss(&test_items, temperature),
+    ));
+}
+
+/// The evaluation of test_data split into bins of bin_size years, keyed by the first year
+/// of each bin and sorted by it. Test names without a year are left out.
+#[pyfunction]
+fn accuracy_by_year(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    bin_size: i32,
+) -> PyResult<Vec<(i32, ScoreInfo)>> {
+    if bin_size <= 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "bin_size must be positive",
+        ));
+    }
+    let train_data = borrow_names(&train_data);
+    let mut by_bin: std::collections::HashMap<i32, ScoreCounter> = std::collections::HashMap::new();
+    for nam in borrow_names(&test_data) {
+        let (Some(true_collection), Some(year)) = (nam.collection, nam.year) else {
+            continue;
+        };
+        let prediction =
+            get_top_choice_impl(nam, &train_data, params)?.map(|(collection, _)| collection);
+        // Rounds towards negative infinity, so bins stay bin_size wide for negative years
+        let bin = year.div_euclid(bin_size) * bin_size;
+        by_bin
+            .entry(bin)
+            .or_default()
+            .record(prediction, true_collection);
+    }
+    let mut result: Vec<(i32, ScoreInfo)> = by_bin
+        .into_iter()
+        .map(|(bin, counter)| (bin, counter.finish(scoring_function)))
+        .collect();
+    result.sort_by_key(|(bin, _)| *bin);
+    return Ok(result);
+}
+
+/// The evaluation of test_data split by the number of authors of each test name.
+#[pyfunction]
+fn accuracy_by_author_count(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+) -> PyResult<std::collections::HashMap<i32, ScoreInfo>> {
+    let train_data = borrow_names(&train_data);
+    let mut by_count: std::collections::HashMap<i32, ScoreCounter> =
+        std::collections::HashMap::new();
+    for nam in borrow_names(&test_data) {
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        let prediction =
+            get_top_choice_impl(nam, &train_data, params)?.map(|(collection, _)| collection);
+        by_count
+            .entry(nam.authors.len() as i32)
+            .or_default()
+            .record(prediction, true_collection);
+    }
+    return Ok(by_count
+        .into_iter()
+        .map(|(count, counter)| (count, counter.finish(scoring_function)))
+        .collect());
+}
+
+/// evaluate_model before and after moving the names (in both train_data and test_data)
+/// whose name_ids are keys of reassign to the collections they map to, to preview a
+/// reorganization of collections.
+#[pyfunction]
+fn simulate_new_collection(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    reassign: std::collections::HashMap<i32, i32>,
+) -> PyResult<(ScoreInfo, ScoreInfo)> {
+    if reassign.values().any(|collection| *collection == 0) {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "collection 0 is reserved for the unknown bucket",
+        ));
+    }
+    let train_data = borrow_names(&train_data);
+    let test_data = borrow_names(&test_data);
+    let before = evaluate_model_impl(&train_data, &test_data, scoring_function, params)?;
+    let reassigned = |names: &[&NameData]| -> Vec<NameData> {
+        return names
+            .iter()
+            .map(|nam| {
+                let mut copy = (*nam).clone();
+                if let Some(collection) = reassign.get(&nam.name_id) {
+                    copy.collection = Some(*collection);
+                }
+                copy
+            })
+            .collect();
+    };
+    let new_train_data = reassigned(&train_data);
+    let new_test_data = reassigned(&test_data);
+    let after = evaluate_model_impl(
+        &new_train_data.iter().collect::<Vec<_>>(),
+        &new_test_data.iter().collect::<Vec<_>>(),
+        scoring_function,
+        params,
+    )?;
+    return Ok((before, after));
+}
+
+/// The evaluation of the test names in one collection.
+#[pyclass(get_all, frozen)]
+struct CollectionAccuracy {
+    collection: i32,
+    info: ScoreInfo,
+    // correct as a proportion of the collection's test names
+    accuracy: f64,
+    // The Wilson score interval around accuracy
+    lower: f64,
+    upper: f64,
+}
+
+#[pymethods]
+impl CollectionAccuracy {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "CollectionAccuracy(collection={}, info={}, accuracy={}, lower={}, upper={})",
+            self.collection,
+            self.info.__repr__()?,
+            self.accuracy,
+            self.lower,
+            self.upper
+        ))
+    }
+}
+
+/// The Wilson score interval for a proportion of successes out of total trials, with z
+/// standard deviations on either side (1.96 for 95%). Unlike the normal approximation it
+/// stays within [0, 1] and does not collapse to a point for proportions of 0 or 1.
+fn wilson_interval(successes: i32, total: i32, z: f64) -> (f64, f64) {
+    if total == 0 {
+        return (0.0, 1.0);
+    }
+    let n = total as f64;
+    let proportion = (successes as f64) / n;
+    let z_squared = z * z;
+    let denominator = 1.0 + z_squared / n;
+    let center = (proportion + z_squared / (2.0 * n)) / denominator;
+    let half_width =
+        z / denominator * (proportion * (1.0 - proportion) / n + z_squared / (4.0 * n * n)).sqrt();
+    return (
+        (center - half_width).max(0.0),
+        (center + half_width).min(1.0),
+    );
+}
+
+/// The evaluation of test_data split by true collection, sorted by collection, with a
+/// Wilson score interval on each collection's accuracy so that collections with few test
+/// names can be told apart from reliably measured ones.
+#[pyfunction]
+#[pyo3(signature = (train_data, test_data, scoring_function, params, z=1.96))]
+fn accuracy_by_collection(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    z: f64,
+) -> PyResult<Vec<CollectionAccuracy>> {
+    let train_data = borrow_names(&train_data);
+    let mut by_collection: std::collections::HashMap<i32, ScoreCounter> =
+        std::collections::HashMap::new();
+    for nam in borrow_names(&test_data) {
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        let prediction =
+            get_top_choice_impl(nam, &train_data, params)?.map(|(collection, _)| collection);
+        by_collection
+            .entry(true_collection)
+            .or_default()
+            .record(prediction, true_collection);
+    }
+    let mut result: Vec<CollectionAccuracy> = by_collection
+        .into_iter()
+        .map(|(collection, counter)| {
+            let info = counter.finish(scoring_function);
+            let total = info.correct + info.incorrect + info.no_value;
+            let (lower, upper) = wilson_interval(info.correct, total, z);
+            CollectionAccuracy {
+                collection,
+                accuracy: (info.correct as f64) / (total as f64),
+                lower,
+                upper,
+                info,
+            }
+        })
+        .collect();
+    result.sort_by_key(|accuracy| accuracy.collection);
+    return Ok(result);
+}
+
+/// The prediction for one test name alongside its true collection.
+#[pyclass(get_all, frozen)]
+struct PredictionRecord {
+    name_id: i32,
+    // None if the most probable collection did not pass the probability cutoff
+    predicted_collection: Option<i32>,
+    // The probability of the most probable collection, whether or not it was predicted
+    top_prob: f64,
+    true_collection: Option<i32>,
+    is_correct: bool,
+}
+
+#[pymethods]
+impl PredictionRecord {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "PredictionRecord(name_id={}, predicted_collection={}, top_prob={}, true_collection={}, is_correct={})",
+            self.name_id,
+            format_optional(self.predicted_collection),
+            self.top_prob,
+            format_optional(self.true_collection),
+            python_bool(self.is_correct)
+        ))
+    }
+}
+
+/// The prediction for every test name, in the order of test_data. Unlike evaluate_model,
+/// names without a known collection are included, and are never correct.
+#[pyfunction]
+fn evaluate_detailed(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<Vec<PredictionRecord>> {
+    let train_data = borrow_names(&train_data);
+    let mut records = Vec::with_capacity(test_data.len());
+    for nam in borrow_names(&test_data) {
+        let raw_scores = get_raw_scores_impl(nam, &train_data, params)?;
+        let probs = raw_scores.normalized();
+        let best = best_choice(&probs);
+        let predicted_collection = raw_scores
+            .choose(&probs, params)
+            .map(|(collection, _)| collection);
+        records.push(PredictionRecord {
+            name_id: nam.name_id,
+            predicted_collection,
+            top_prob: best.map_or(0.0, |(_, probability)| probability),
+            true_collection: nam.collection,
+            is_correct: predicted_collection.is_some() && predicted_collection == nam.collection,
+        });
+    }
+    return Ok(records);
+}
+
+/// For each test name with a known collection, the probability of its best choice and
+/// whether that choice is correct, or None if min_margin rules out a prediction at any
+/// probability_cutoff.
+fn cutoff_independent_choices(
+    train_data: &[&NameData],
+    test_data: &[&NameData],
+    params: &Params,
+) -> PyResult<Vec<Option<(f64, bool)>>> {
+    let mut choices = Vec::new();
+    for nam in test_data.iter() {
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        let raw_scores = get_raw_scores_impl(nam, train_data, params)?;
+        let probs = raw_scores.normalized();
+        let choice = raw_scores
+            .best_choice(&probs, params)
+            .filter(|(collection, probability)| {
+                probability - runner_up_probability(&probs, *collection) >= params.min_margin
+            });
+        choices.push(
+            choice.map(|(collection, probability)| (probability, collection == true_collection)),
+        );
+    }
+    return Ok(choices);
+}
+
+/// The number of test names with a known collection that get no prediction, which is the
+/// no_value count of evaluate_model.
+#[pyfunction]
+fn expected_abstentions(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<i32> {
+    let choices = cutoff_independent_choices(
+        &borrow_names(&train_data),
+        &borrow_names(&test_data),
+        params,
+    )?;
+    return Ok(count_abstentions(&choices, params.probability_cutoff));
+}
+
+/// The number of abstentions (as in expected_abstentions) for each cutoff used as the
+/// probability_cutoff. Each test name is scored once.
+#[pyfunction]
+fn abstentions_by_cutoff(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    cutoffs: Vec<f64>,
+) -> PyResult<Vec<(f64, i32)>> {
+    let choices = cutoff_independent_choices(
+        &borrow_names(&train_data),
+        &borrow_names(&test_data),
+        params,
+    )?;
+    return Ok(cutoffs
+        .into_iter()
+        .map(|cutoff| (cutoff, count_abstentions(&choices, cutoff)))
+        .collect());
+}
+
+fn count_abstentions(choices: &[Option<(f64, bool)>], cutoff: f64) -> i32 {
+    return choices
+        .iter()
+        .filter(|choice| choice.is_none_or(|(probability, _)| probability <= cutoff))
+        .count() as i32;
+}
+
+/// The probability_cutoff that maximizes evaluate_model's score on test_data, with the
+/// resulting ScoreInfo. Each test name is scored once; the candidate cutoffs are 0 and the
+/// distinct probabilities of the most probable collections. Ties go to the lowest cutoff.
+#[pyfunction]
+fn best_cutoff(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+) -> PyResult<(f64, ScoreInfo)> {
+    let choices = cutoff_independent_choices(
+        &borrow_names(&train_data),
+        &borrow_names(&test_data),
+        params,
+    )?;
+    // Names that are not predicted at any cutoff
+    let ambiguous = choices.iter().filter(|choice| choice.is_none()).count();
+    let mut choices: Vec<(f64, bool)> = choices.into_iter().flatten().collect();
+    choices.sort_by(|a, b| b.0.total_cmp(&a.0));
+    // Sweep the cutoff downwards; a cutoff equal to a probability excludes that choice
+    let mut predicted = ScoreCounter::default();
+    let mut candidates: Vec<(f64, ScoreCounter)> = Vec::new();
+    let mut index = 0;
+    while index <= choices.len() {
+        let cutoff = if index < choices.len() {
+            choices[index].0
+        } else {
+            0.0
+        };
+        let remaining = choices.len() - index + ambiguous;
+        let mut counter = predicted;
+        counter.no_value += remaining as i32;
+        counter.weighted_no_value += remaining as f64;
+        candidates.push((cutoff, counter));
+        if index == choices.len() || cutoff <= 0.0 {
+            break;
+        }
+        while index < choices.len() && choices[index].0 == cutoff {
+            // Only correctness matters, so any pair of collections will do
+            predicted.record(Some(i32::from(!choices[index].1)), 0);
+            index += 1;
+        }
+    }
+    let mut best: Option<(f64, ScoreInfo)> = None;
+    for (cutoff, counter) in candidates.into_iter().rev() {
+        let info = counter.finish(scoring_function);
+        if best
+            .as_ref()
+            .is_none_or(|(_, best_info)| info.score > best_info.score)
+        {
+            best = Some((cutoff, info));
+        }
+    }
+    return Ok(best.expect("there is always at least one candidate cutoff"));
+}
+
+/// The probability_cutoff that maximizes balanced accuracy on test_data, with that balanced
+/// accuracy: the mean over the collections of the test names of the fraction of each
+/// collection's predicted names that are predicted correctly, with collections none of
+/// whose names are predicted counting as 0. Unlike best_cutoff, this does not favor the
+/// cutoffs that suit the common collections. The candidates are as in best_cutoff, and
+/// ties go to the lowest cutoff.
+#[pyfunction]
+fn best_cutoff_balanced(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<(f64, f64)> {
+    let test_data = borrow_names(&test_data);
+    let choices = cutoff_independent_choices(&borrow_names(&train_data), &test_data, params)?;
+    // The choices are for the test names with a known collection, in order
+    let mut choices: Vec<(f64, bool, i32)> = choices
+        .into_iter()
+        .zip(test_data.iter().filter_map(|nam| nam.collection))
+        .filter_map(|(choice, collection)| {
+            choice.map(|(probability, correct)| (probability, correct, collection))
+        })
+        .collect();
+    // The number of predicted and correctly predicted names per collection
+    let mut counts: std::collections::HashMap<i32, (i32, i32)> = test_data
+        .iter()
+        .filter_map(|nam| Some((nam.collection?, (0, 0))))
+        .collect();
+    if counts.is_empty() {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "test_data has no names with a known collection",
+        ));
+    }
+    let balanced_accuracy = |counts: &std::collections::HashMap<i32, (i32, i32)>| {
+        let total: f64 = counts
+            .values()
+            .filter(|(predicted, _)| *predicted > 0)
+            .map(|(predicted, correct)| (*correct as f64) / (*predicted as f64))
+            .sum();
+        return total / (counts.len() as f64);
+    };
+    choices.sort_by(|a, b| b.0.total_cmp(&a.0));
+    // Sweep the cutoff downwards; a cutoff equal to a probability excludes that choice
+    let mut best: Option<(f64, f64)> = None;
+    let mut index = 0;
+    loop {
+        let cutoff = if index < choices.len() {
+            choices[index].0
+        } else {
+            0.0
+        };
+        let accuracy = balanced_accuracy(&counts);
+        // Lower cutoffs come later, so they win ties
+        if best.is_none_or(|(_, best_accuracy)| accuracy >= best_accuracy) {
+            best = Some((cutoff, accuracy));
+        }
+        if index == choices.len() || cutoff <= 0.0 {
+            break;
+        }
+        while index < choices.len() && choices[index].0 == cutoff {
+            let (_, correct, collection) = choices[index];
+            let entry = counts
+                .get_mut(&collection)
+                .expect("every choice is for a test name's collection");
+            entry.0 += 1;
+            entry.1 += i32::from(correct);
+            index += 1;
+        }
+    }
+    return Ok(best.expect("there is always at least one candidate cutoff"));
+}
+
+/// The threshold on the unknown bucket's probability that best tells the names in
+/// novel_test_data (whose collections are not in train_data) from those in test_data,
+/// calling a name novel if its unknown probability is at least the threshold. The threshold
+/// maximizes balanced accuracy, the mean of the fractions of each set classified
+/// correctly. The candidates are the observed probabilities, and ties go to the lowest.
+#[pyfunction]
+fn fit_novelty_threshold(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    novel_test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<f64> {
+    if test_data.is_empty() || novel_test_data.is_empty() {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "test_data and novel_test_data must not be empty",
+        ));
+    }
+    let train_data = borrow_names(&train_data);
+    let mut scores: Vec<(f64, bool)> = Vec::with_capacity(test_data.len() + novel_test_data.len());
+    for (names, novel) in [(&test_data, false), (&novel_test_data, true)] {
+        for nam in borrow_names(names) {
+            let probs = get_probs_impl(nam, &train_data, params)?;
+            scores.push((probs.get(&0).copied().unwrap_or(0.0), novel));
+        }
+    }
+    scores.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let num_novel = novel_test_data.len() as f64;
+    let num_known = test_data.len() as f64;
+    // Sweep the threshold downwards, counting the names at or above it
+    let mut novel_above = 0;
+    let mut known_above = 0;
+    let mut best: Option<(f64, f64)> = None;
+    let mut index = 0;
+    while index < scores.len() {
+        let threshold = scores[index].0;
+        while index < scores.len() && scores[index].0 == threshold {
+            if scores[index].1 {
+                novel_above += 1;
+            } else {
+                known_above += 1;
+            }
+            index += 1;
+        }
+        let balanced_accuracy = ((novel_above as f64) / num_novel
+            + (num_known - (known_above as f64)) / num_known)
+            / 2.0;
+        // Lower thresholds come later, so they win ties
+        if best.is_none_or(|(_, best_accuracy)| balanced_accuracy >= best_accuracy) {
+            best = Some((threshold, balanced_accuracy));
+        }
+    }
+    return Ok(best.expect("there is at least one name").0);
+}
+
+/// The smallest probability_cutoff with no incorrect predictions on test_data that still
+/// predicts at least one name, or None if the most confident predictions include an
+/// incorrect one, so that only predicting nothing avoids errors.
+#[pyfunction]
+fn zero_error_cutoff(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<Option<f64>> {
+    let choices = cutoff_independent_choices(
+        &borrow_names(&train_data),
+        &borrow_names(&test_data),
+        params,
+    )?;
+    // A cutoff only excludes probabilities up to and including itself
+    let mut highest_incorrect: Option<f64> = None;
+    let mut highest_correct: Option<f64> = None;
+    for (probability, correct) in choices.into_iter().flatten() {
+        let highest = if correct {
+            &mut highest_correct
+        } else {
+            &mut highest_incorrect
+        };
+        *highest = Some(highest.map_or(probability, |value| value.max(probability)));
+    }
+    let cutoff = highest_incorrect.unwrap_or(0.0);
+    return Ok(highest_correct
+        .filter(|correct| *correct > cutoff)
+        .map(|_| cutoff));
+}
+
+/// The best choice of every labelled test name, computed once, so that the evaluation at
+/// any probability_cutoff can be had without scoring names again. It can be saved to a
+/// file and loaded back for the same inputs.
+#[pyclass(frozen)]
+struct CachedPredictions {
+    // As returned by cutoff_independent_choices
+    choices: Vec<Option<(f64, bool)>>,
+    // A digest of the names and params the choices were computed from
+    key: String,
+    loaded: bool,
+}
+
+// The first line of a saved CachedPredictions file.
+const CACHED_PREDICTIONS_HEADER: &str = "repoguess CachedPredictions 1";
+
+impl CachedPredictions {
+    fn compute(
+        train_data: &[&NameData],
+        test_data: &[&NameData],
+        params: &Params,
+        key: String,
+    ) -> PyResult<Self> {
+        let choices = cutoff_independent_choices(train_data, test_data, params)?;
+        return Ok(CachedPredictions {
+            choices,
+            key,
+            loaded: false,
+        });
+    }
+
+    /// Identifies the inputs exactly: the repr of every name in order, and the repr of
+    /// params with its numeric values written out in full, since the repr rounds them.
+    fn inputs_key(
+        train_data: &[&NameData],
+        test_data: &[&NameData],
+        params: &Params,
+    ) -> PyResult<String> {
+        let mut serialized = params.__repr__()?;
+        for name in PARAM_NAMES {
+            serialized.push_str(&format!("\n{}={:?}", name, params.get_value(name)?));
+        }
+        for (label, names) in [("train", train_data), ("test", test_data)] {
+            serialized.push_str(&format!("\n{} {}", label, names.len()));
+            for nam in names.iter() {
+                serialized.push('\n');
+                serialized.push_str(&nam.__repr__()?);
+            }
+        }
+        return Ok(sha256_hex(serialized.as_bytes()));
+    }
+
+    /// The choices in a saved file, or None if it is malformed or was saved for other inputs.
+    fn parse(contents: &str, key: &str) -> Option<Vec<Option<(f64, bool)>>> {
+        let mut lines = contents.lines();
+        if lines.next()? != CACHED_PREDICTIONS_HEADER || lines.next()? != key {
+            return None;
+        }
+        let mut choices = Vec::new();
+        for line in lines {
+            if line == "-" {
+                choices.push(None);
+                continue;
+            }
+            let (probability, correct) = line.split_once(' ')?;
+            let correct = match correct {
+                "1" => true,
+                "0" => false,
+                _ => return None,
+            };
+            choices.push(Some((probability.parse().ok()?, correct)));
+        }
+        return Some(choices);
+    }
+}
+
+#[pymethods]
+impl CachedPredictions {
+    #[new]
+    fn new(
+        train_data: Vec<Bound<'_, NameData>>,
+        test_data: Vec<Bound<'_, NameData>>,
+        params: &Params,
+    ) -> PyResult<Self> {
+        let train_data = borrow_names(&train_data);
+        let test_data = borrow_names(&test_data);
+        let key = CachedPredictions::inputs_key(&train_data, &test_data, params)?;
+        return CachedPredictions::compute(&train_data, &test_data, params, key);
+    }
+
+    /// Loads the choices saved at path if they were computed from exactly these inputs, and
+    /// otherwise (including when the file is missing or unreadable) computes them and saves
+    /// them there.
+    #[staticmethod]
+    fn load_or_compute(
+        path: std::path::PathBuf,
+        train_data: Vec<Bound<'_, NameData>>,
+        test_data: Vec<Bound<'_, NameData>>,
+        params: &Params,
+    ) -> PyResult<Self> {
+        let train_data = borrow_names(&train_data);
+        let test_data = borrow_names(&test_data);
+        let key = CachedPredictions::inputs_key(&train_data, &test_data, params)?;
+        let saved = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| CachedPredictions::parse(&contents, &key));
+        if let Some(choices) = saved {
+            return Ok(CachedPredictions {
+                choices,
+                key,
+                loaded: true,
+            });
+        }
+        let cached = CachedPredictions::compute(&train_data, &test_data, params, key)?;
+        cached.save(path)?;
+        return Ok(cached);
+    }
+
+    /// Writes the choices to path, together with the digest of the inputs that
+    /// load_or_compute checks.
+    fn save(&self, path: std::path::PathBuf) -> PyResult<()> {
+        let mut contents = format!("{}\n{}\n", CACHED_PREDICTIONS_HEADER, self.key);
+        for choice in self.choices.iter() {
+            match choice {
+                // Debug formatting round-trips the probability exactly
+                Some((probability, correct)) => {
+                    contents.push_str(&format!("{:?} {}\n", probability, i32::from(*correct)))
+                }
+                None => contents.push_str("-\n"),
+            }
+        }
+        std::fs::write(&path, contents)?;
+        return Ok(());
+    }
+
+    /// Whether load_or_compute found these choices saved rather than computing them.
+    #[getter]
+    fn loaded(&self) -> bool {
+        return self.loaded;
+    }
+
+    /// The same result as evaluate_model with the params this was built from, except for
+    /// using cutoff as the probability_cutoff.
+    fn at_cutoff(&self, scoring_function: &ScoringFunction, cutoff: f64) -> ScoreInfo {
+        let mut counter = ScoreCounter::default();
+        for choice in self.choices.iter() {
+            match choice {
+                // Only correctness matters, so any pair of collections will do
+                Some((probability, correct)) if *probability > cutoff => {
+                    counter.record(Some(i32::from(!correct)), 0)
+                }
+                _ => counter.record(None, 0),
+            }
+        }
+        return counter.finish(scoring_function);
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "CachedPredictions(num_test_names={})",
+            self.choices.len()
+        ))
+    }
+}
+
+/// Parallel lists of the true and the predicted collection of each test name with a known
+/// collection, as used by scikit-learn's metrics. Names with no prediction get
+/// abstain_label.
+#[pyfunction]
+#[pyo3(signature = (train_data, test_data, params, abstain_label=-1))]
+fn to_label_arrays(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    abstain_label: i32,
+) -> PyResult<(Vec<i32>, Vec<i32>)> {
+    let train_data = borrow_names(&train_data);
+    let mut true_labels = Vec::with_capacity(test_data.len());
+    let mut predicted_labels = Vec::with_capacity(test_data.len());
+    for nam in borrow_names(&test_data) {
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        let prediction = get_top_choice_impl(nam, &train_data, params)?;
+        true_labels.push(true_collection);
+        predicted_labels.push(prediction.map_or(abstain_label, |(collection, _)| collection));
+    }
+    return Ok((true_labels, predicted_labels));
+}
+
+/// How well two labelled subsets predict each other: the fraction of each subset's names
+/// predicted correctly when training on the other, averaged over both directions.
+/// Names without a prediction count as disagreeing.
+#[pyfunction]
+fn cross_agreement(
+    subset_a: Vec<Bound<'_, NameData>>,
+    subset_b: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<f64> {
+    let subset_a = borrow_names(&subset_a);
+    let subset_b = borrow_names(&subset_b);
+    let scoring_function = ScoringFunction::new(0.0, 0.0, 0.0);
+    let mut rates: Vec<f64> = Vec::new();
+    for (train_data, test_data) in [(&subset_a, &subset_b), (&subset_b, &subset_a)] {
+        let info = evaluate_model_impl(train_data, test_data, &scoring_function, params)?;
+        let total = info.correct + info.incorrect + info.no_value;
+        if total > 0 {
+            rates.push((info.correct as f64) / (total as f64));
+        }
+    }
+    if rates.is_empty() {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "neither subset has names with a known collection",
+        ));
+    }
+    return Ok(rates.iter().sum::<f64>() / (rates.len() as f64));
+}
+
+/// The score of a pair of names regardless of their order. get_score is symmetric except
+/// when the author overlap is a proportion of the query's authors; then the pair gets the
+/// higher of its two scores, so that a name whose authors are all among another's counts as
+/// similar to it.
+fn symmetric_score(nam1: &NameData, nam2: &NameData, params: &Params) -> PyResult<f64> {
+    let score = get_score_impl(nam1, nam2, params)?;
+    if params.author_overlap_denominator == AuthorOverlapDenominator::Query {
+        return Ok(score.max(get_score_impl(nam2, nam1, params)?));
+    }
+    return Ok(score);
+}
+
+/// Pairs of names (as indices into data) whose symmetric_score exceeds threshold, with the
+/// score, scored in parallel.
+fn similar_pairs(
+    py: Python<'_>,
+    data: &[&NameData],
+    params: &Params,
+    threshold: f64,
+) -> PyResult<Vec<(usize, usize, f64)>> {
+    let indices: Vec<usize> = (0..data.len()).collect();
+    let rows = py.allow_threads(|| {
+        parallel_map(&indices, |index| -> PyResult<Vec<(usize, usize, f64)>> {
+            let nam1 = data[*index];
+            let mut pairs: Vec<(usize, usize, f64)> = Vec::new();
+            for (offset, nam2) in data[index + 1..].iter().enumerate() {
+                let score = symmetric_score(nam1, nam2, params)?;
+                if score > threshold {
+                    pairs.push((*index, index + 1 + offset, score));
+                }
+            }
+            return Ok(pairs);
+        })
+    });
+    let mut result: Vec<(usize, usize, f64)> = Vec::new();
+    for row in rows {
+        result.extend(row?);
+    }
+    return Ok(result);
+}
+
+/// Pairs of training names (by name_id) whose score against each other exceeds threshold,
+/// highest score first. Under AuthorOverlapDenominator.Query, where get_score depends on
+/// the order of the names, a pair's score is the higher of the two. This compares every
+/// pair of names, so it is slow for large inputs, though the comparisons run in parallel.
+#[pyfunction]
+fn find_near_duplicates(
+    py: Python<'_>,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    threshold: f64,
+) -> PyResult<Vec<(i32, i32, f64)>> {
+    let train_data = borrow_names(&train_data);
+    let mut result: Vec<(i32, i32, f64)> = similar_pairs(py, &train_data, params, threshold)?
+        .into_iter()
+        .map(|(index1, index2, score)| {
+            (
+                train_data[index1].name_id,
+                train_data[index2].name_id,
+                score,
+            )
+        })
+        .collect();
+    result.sort_by(|a, b| b.2.total_cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+    return Ok(result);
+}
+
+/// The root of index in a union-find forest, halving the path on the way.
+fn find_root(roots: &mut [usize], mut index: usize) -> usize {
+    while roots[index] != index {
+        roots[index] = roots[roots[index]];
+        index = roots[index];
+    }
+    return index;
+}
+
+/// Groups data into the connected components of the graph linking names whose score
+/// against each other (in either order, as in find_near_duplicates) exceeds threshold, as
+/// lists of name_ids. Names similar to nothing form their own groups. Groups and the names
+/// in them are in the order of data. Like find_near_duplicates, this scores every pair of
+/// names.
+#[pyfunction]
+fn cluster_by_similarity(
+    py: Python<'_>,
+    data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    threshold: f64,
+) -> PyResult<Vec<Vec<i32>>> {
+    let data = borrow_names(&data);
+    let mut roots: Vec<usize> = (0..data.len()).collect();
+    for (index1, index2, _) in similar_pairs(py, &data, params, threshold)? {
+        let root1 = find_root(&mut roots, index1);
+        let root2 = find_root(&mut roots, index2);
+        // Keeping the earlier index as the root keeps the groups in the order of data
+        roots[root1.max(root2)] = root1.min(root2);
+    }
+    let mut groups: Vec<Vec<i32>> = Vec::new();
+    let mut group_of_root: std::collections::HashMap<usize, usize> =
+        std::collections::HashMap::new();
+    for (index, nam) in data.iter().enumerate() {
+        let root = find_root(&mut roots, index);
+        let group = *group_of_root.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(nam.name_id);
+    }
+    return Ok(groups);
+}
+
+/// How often pairs of different training names match on country and on citation group
+/// (fully, as in FieldMatch.cg_match), to see whether the two boosts count the same thing
+/// twice. The keys are "country", "citation_group" and "both" for the fraction of pairs
+/// matching on each, plus "citation_group_given_country" and "country_given_citation_group"
+/// for the conditional rates, which are left out when nothing matches on the condition.
+#[pyfunction]
+fn feature_correlation(
+    py: Python<'_>,
+    train_data: Vec<Bound<'_, NameData>>,
+) -> std::collections::HashMap<String, f64> {
+    let train_data = borrow_names(&train_data);
+    let indices: Vec<usize> = (0..train_data.len()).collect();
+    // Per name, the number of later names matching on country, citation group and both
+    let rows = py.allow_threads(|| {
+        parallel_map(&indices, |index| {
+            let nam1 = train_data[*index];
+            let mut counts = (0u64, 0u64, 0u64);
+            for nam2 in &train_data[index + 1..] {
+                let country_match = nam1.tl_country == nam2.tl_country;
+                let cg_match =
+                    citation_group_overlap(&nam1.citation_groups, &nam2.citation_groups) == 1.0;
+                counts.0 += u64::from(country_match);
+                counts.1 += u64::from(cg_match);
+                counts.2 += u64::from(country_match && cg_match);
+            }
+            return counts;
+        })
+    });
+    let (country, citation_group, both) = rows.into_iter().fold((0, 0, 0), |total, counts| {
+        (total.0 + counts.0, total.1 + counts.1, total.2 + counts.2)
+    });
+    let num_pairs = (train_data.len() * train_data.len().saturating_sub(1) / 2) as f64;
+    let mut result = std::collections::HashMap::new();
+    if num_pairs > 0.0 {
+        result.insert("country".to_string(), country as f64 / num_pairs);
+        result.insert(
+            "citation_group".to_string(),
+            citation_group as f64 / num_pairs,
+        );
+        result.insert("both".to_string(), both as f64 / num_pairs);
+    }
+    if country > 0 {
+        result.insert(
+            "citation_group_given_country".to_string(),
+            both as f64 / country as f64,
+        );
+    }
+    if citation_group > 0 {
+        result.insert(
+            "country_given_citation_group".to_string(),
+            both as f64 / citation_group as f64,
+        );
+    }
+    return result;
+}
+
+/// The mutual information in bits between the collection of the training names with a
+/// known collection and each of "tl_country", "citation_group" (the first one) and
+/// "first_author". A missing citation group or author counts as a value of its own.
+#[pyfunction]
+fn feature_mutual_information(
+    train_data: Vec<Bound<'_, NameData>>,
+) -> std::collections::HashMap<String, f64> {
+    let labelled: Vec<(&NameData, i32)> = borrow_names(&train_data)
+        .into_iter()
+        .filter_map(|nam| Some((nam, nam.collection?)))
+        .collect();
+    let feature = |value: fn(&NameData) -> Option<i32>| {
+        mutual_information(
+            labelled
+                .iter()
+                .map(|(nam, collection)| (value(nam), *collection)),
+        )
+    };
+    let mut result = std::collections::HashMap::new();
+    result.insert(
+        "tl_country".to_string(),
+        feature(|nam| Some(nam.tl_country)),
+    );
+    result.insert(
+        "citation_group".to_string(),
+        feature(|nam| nam.citation_groups.first().copied()),
+    );
+    result.insert(
+        "first_author".to_string(),
+        feature(|nam| nam.authors.first().copied()),
+    );
+    return result;
+}
+
+/// The mutual information in bits between the two values of the pairs, from their joint
+/// frequencies. 0 for no pairs.
+fn mutual_information(pairs: impl Iterator<Item = (Option<i32>, i32)>) -> f64 {
+    let mut joint: std::collections::HashMap<(Option<i32>, i32), usize> =
+        std::collections::HashMap::new();
+    let mut first: std::collections::HashMap<Option<i32>, usize> = std::collections::HashMap::new();
+    let mut second: std::collections::HashMap<i32, usize> = std::collections::HashMap::new();
+    let mut total = 0;
+    for (value1, value2) in pairs {
+        *joint.entry((value1, value2)).or_insert(0) += 1;
+        *first.entry(value1).or_insert(0) += 1;
+        *second.entry(value2).or_insert(0) += 1;
+        total += 1;
+    }
+    if total == 0 {
+        return 0.0;
+    }
+    let total = total as f64;
+    return joint
+        .iter()
+        .map(|((value1, value2), count)| {
+            let count = *count as f64;
+            // p(x, y) log(p(x, y) / (p(x) p(y))), with the totals cancelled out
+            let expected = (first[value1] as f64) * (second[value2] as f64) / total;
+            (count / total) * (count / expected).log2()
+        })
+        .sum::<f64>()
+        .max(0.0);
+}
+
+/// The fraction of training names with a known collection for which the rest of the
+/// training data predicts a different collection, and the name_ids of those names, as an
+/// estimate of how noisy the labels are. Names left unpredicted are not suspects. Each
+/// name is scored against all of train_data, since get_score ignores a name's own entry.
+#[pyfunction]
+fn label_noise_estimate(
+    py: Python<'_>,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<(f64, Vec<i32>)> {
+    let train_data = borrow_names(&train_data);
+    let labelled: Vec<&NameData> = train_data
+        .iter()
+        .copied()
+        .filter(|nam| nam.collection.is_some())
+        .collect();
+    let predictions = py.allow_threads(|| {
+        parallel_map(&labelled, |nam| {
+            get_top_choice_impl(nam, &train_data, params)
+        })
+    });
+    let mut suspects: Vec<i32> = Vec::new();
+    for (nam, prediction) in labelled.iter().zip(predictions) {
+        if let Some((collection, _)) = prediction? {
+            if Some(collection) != nam.collection {
+                suspects.push(nam.name_id);
+            }
+        }
+    }
+    if labelled.is_empty() {
+        return Ok((0.0, suspects));
+    }
+    return Ok((suspects.len() as f64 / labelled.len() as f64, suspects));
+}
+
+/// Evaluates each name in data against only the other names from the same year or earlier,
+/// as if names had been classified as they were published. Names without a year cannot be
+/// placed in time, so they are neither evaluated nor used for training.
+#[pyfunction]
+fn evaluate_forward_only(
+    data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+) -> PyResult<ScoreInfo> {
+    let mut dated: Vec<&NameData> = borrow_names(&data)
+        .into_iter()
+        .filter(|nam| nam.year.is_some())
+        .collect();
+    dated.sort_by_key(|nam| nam.year);
+    let mut counter = ScoreCounter::default();
+    let mut end = 0;
+    for nam in dated.iter() {
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        while end < dated.len() && dated[end].year <= nam.year {
+            end += 1;
+        }
+        let train_data: Vec<&NameData> = dated[..end]
+            .iter()
+            .copied()
+            .filter(|train_datum| train_datum.name_id != nam.name_id)
+            .collect();
+        let top_choice = get_top_choice_impl(nam, &train_data, params)?;
+        counter.record(
+            top_choice.map(|(collection, _)| collection),
+            true_collection,
+        );
+    }
+    return Ok(counter.finish(scoring_function));
+}
+
+/// The F-beta score of the predictions made on test_data, where precision is the fraction of
+/// predictions that are correct and recall is the fraction of test names predicted correctly.
+#[pyfunction]
+fn f_beta(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    beta: f64,
+) -> PyResult<f64> {
+    let scoring_function = ScoringFunction::new(0.0, 0.0, 0.0);
+    let info = evaluate_model_impl(
+        &borrow_names(&train_data),
+        &borrow_names(&test_data),
+        &scoring_function,
+        params,
+    )?;
+    let predicted = info.correct + info.incorrect;
+    let total = predicted + info.no_value;
+    if info.correct == 0 {
+        return Ok(0.0);
+    }
+    let precision = (info.correct as f64) / (predicted as f64);
+    let recall = (info.correct as f64) / (total as f64);
+    let beta_squared = beta * beta;
+    return Ok((1.0 + beta_squared) * precision * recall / (beta_squared * precision + recall));
+}
+
+/// The false_positive_cost at which the predictions made on test_data are worth exactly
+/// nothing, that is correct − incorrect × cost = 0: the number of correct predictions per
+/// incorrect one. Infinite if no prediction is incorrect.
+#[pyfunction]
+fn break_even_cost(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<f64> {
+    let scoring_function = ScoringFunction::new(0.0, 0.0, 0.0);
+    let info = evaluate_model_impl(
+        &borrow_names(&train_data),
+        &borrow_names(&test_data),
+        &scoring_function,
+        params,
+    )?;
+    if info.incorrect == 0 {
+        return Ok(f64::INFINITY);
+    }
+    return Ok((info.correct as f64) / (info.incorrect as f64));
+}
+
+/// For each test name with a known collection and each collection in train_data (in
+/// order of id), the name's probability for the collection and whether it is the true
+/// one, for one-vs-rest ROC analysis. Collections that got no score have probability 0.
+#[pyfunction]
+fn ovr_scores(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<Vec<(i32, f64, bool)>> {
+    let train_data = borrow_names(&train_data);
+    let mut collections: Vec<i32> = train_data.iter().filter_map(|nam| nam.collection).collect();
+    collections.sort_unstable();
+    collections.dedup();
+    let mut result: Vec<(i32, f64, bool)> = Vec::new();
+    for nam in borrow_names(&test_data) {
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        let probs = get_probs_impl(nam, &train_data, params)?;
+        for collection in collections.iter() {
+            let prob = probs.get(collection).copied().unwrap_or(0.0);
+            result.push((*collection, prob, *collection == true_collection));
+        }
+    }
+    return Ok(result);
+}
+
+/// The fraction of test names whose collection is among the most probable collections
+/// that together reach probability p.
+#[pyfunction]
+fn nucleus_accuracy(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    p: f64,
+) -> PyResult<f64> {
+    let train_data = borrow_names(&train_data);
+    let mut correct = 0;
+    let mut total = 0;
+    for nam in test_data.iter() {
+        let nam = nam.get();
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        total += 1;
+        let probs = get_probs_impl(nam, &train_data, params)?;
+        let mut ranked: Vec<(i32, f64)> = probs.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        let mut cumulative = 0.0;
+        for (collection, prob) in ranked {
+            if collection == true_collection {
+                correct += 1;
+                break;
+            }
+            cumulative += prob;
+            if cumulative >= p {
+                break;
+            }
+        }
+    }
+    if total == 0 {
+        return Ok(0.0);
+    }
+    return Ok((correct as f64) / (total as f64));
+}
+
+/// Greedily removes training names whose removal does not change the number of correct
+/// and incorrect predictions on test_data, and returns the remaining names. This
+/// re-evaluates the test set once per training name, so it is slow for large inputs.
+#[pyfunction]
+fn prune_train<'py>(
+    train_data: Vec<Bound<'py, NameData>>,
+    test_data: Vec<Bound<'py, NameData>>,
+    params: &Params,
+) -> PyResult<Vec<Bound<'py, NameData>>> {
+    let scoring_function = ScoringFunction::new(0.0, 0.0, 0.0);
+    let all_train_data = borrow_names(&train_data);
+    let test_data = borrow_names(&test_data);
+    let baseline = evaluate_model_impl(&all_train_data, &test_data, &scoring_function, params)?;
+    let mut kept = vec![true; all_train_data.len()];
+    for index in 0..all_train_data.len() {
+        kept[index] = false;
+        let candidate: Vec<&NameData> = all_train_data
+            .iter()
+            .zip(kept.iter())
+            .filter(|(_, keep)| **keep)
+            .map(|(datum, _)| *datum)
+            .collect();
+        let info = evaluate_model_impl(&candidate, &test_data, &scoring_function, params)?;
+        if info.correct != baseline.correct || info.incorrect != baseline.incorrect {
+            kept[index] = true;
+        }
+    }
+    return Ok(train_data
+        .into_iter()
+        .zip(kept)
+        .filter(|(_, keep)| *keep)
+        .map(|(datum, _)| datum)
+        .collect());
+}
+
+/// The name_id of the training name whose removal most reduces the number of correct
+/// predictions on test_data, and by how much (negative if every removal helps). This
+/// re-evaluates the test set once per training name, spread across all cores, so it costs
+/// about len(train_data)² × len(test_data) score computations.
+#[pyfunction]
+fn most_influential_train_item(
+    py: Python<'_>,
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<(i32, i32)> {
+    if train_data.is_empty() {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "train_data must not be empty",
+        ));
+    }
+    let scoring_function = ScoringFunction::new(0.0, 0.0, 0.0);
+    let train_data = borrow_names(&train_data);
+    let test_data = borrow_names(&test_data);
+    let baseline = evaluate_model_impl(&train_data, &test_data, &scoring_function, params)?;
+    let indices: Vec<usize> = (0..train_data.len()).collect();
+    let reductions = py.allow_threads(|| {
+        parallel_map(&indices, |index| -> PyResult<i32> {
+            let mut candidate = train_data.clone();
+            candidate.remove(*index);
+            let info = evaluate_model_impl(&candidate, &test_data, &scoring_function, params)?;
+            return Ok(baseline.correct - info.correct);
+        })
+    });
+    let mut best: Option<(i32, i32)> = None;
+    for (train_datum, reduction) in train_data.iter().zip(reductions) {
+        let reduction = reduction?;
+        if best.is_none_or(|(_, best_reduction)| reduction > best_reduction) {
+            best = Some((train_datum.name_id, reduction));
+        }
+    }
+    return Ok(best.expect("train_data is not empty"));
+}
+
+/// A SHA-256 digest of the prediction for every test name, for detecting changes in
+/// predictions. Names are ordered by name_id, and ties between equally probable
+/// collections are broken deterministically, so the digest is stable across runs.
+#[pyfunction]
+fn predictions_digest(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+) -> PyResult<String> {
+    let train_data = borrow_names(&train_data);
+    let mut test_data = borrow_names(&test_data);
+    test_data.sort_by_key(|nam| nam.name_id);
+    let mut serialized = String::new();
+    for nam in test_data {
+        let prediction = get_top_choice_impl(nam, &train_data, params)?;
+        serialized.push_str(&format!(
+            "{}:{}\n",
+            nam.name_id,
+            format_optional(prediction.map(|(collection, _)| collection))
+        ));
+    }
+    return Ok(sha256_hex(serialized.as_bytes()));
+}
+
+/// Writes the top choice of every test name to a Parquet file at path, with the columns
+/// name_id, predicted_collection, probability and true_collection. The last three are null
+/// where there is no prediction or no known collection.
+#[cfg(feature = "parquet")]
+#[pyfunction]
+fn write_predictions_parquet(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    path: std::path::PathBuf,
+) -> PyResult<()> {
+    let train_data = borrow_names(&train_data);
+    let test_data = borrow_names(&test_data);
+    let mut name_ids = Vec::with_capacity(test_data.len());
+    let mut predicted_collections = Vec::with_capacity(test_data.len());
+    let mut probabilities = Vec::with_capacity(test_data.len());
+    let mut true_collections = Vec::with_capacity(test_data.len());
+    for nam in test_data {
+        let prediction = get_top_choice_impl(nam, &train_data, params)?;
+        name_ids.push(Some(nam.name_id));
+        predicted_collections.push(prediction.map(|(collection, _)| collection));
+        probabilities.push(prediction.map(|(_, probability)| probability));
+        true_collections.push(nam.collection);
+    }
+    let columns = [
+        ParquetColumn {
+            name: "name_id",
+            required: true,
+            values: ParquetValues::Int32(name_ids),
+        },
+        ParquetColumn {
+            name: "predicted_collection",
+            required: false,
+            values: ParquetValues::Int32(predicted_collections),
+        },
+        ParquetColumn {
+            name: "probability",
+            required: false,
+            values: ParquetValues::Double(probabilities),
+        },
+        ParquetColumn {
+            name: "true_collection",
+            required: false,
+            values: ParquetValues::Int32(true_collections),
+        },
+    ];
+    return std::fs::write(&path, parquet_file(&columns)).map_err(|err| {
+        pyo3::exceptions::PyOSError::new_err(format!(
+            "could not write predictions to {}: {}",
+            path.display(),
+            err
+        ))
+    });
+}
+
+/// For each predicted collection, the lowest probability such that predictions of that
+/// collection with at least this probability reach the target precision on test_data.
+/// Collections that never reach the target are omitted.
+#[pyfunction]
+fn per_collection_cutoffs(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    target_precision: f64,
+) -> PyResult<std::collections::HashMap<i32, f64>> {
+    let train_data = borrow_names(&train_data);
+    let mut predictions: std::collections::HashMap<i32, Vec<(f64, bool)>> =
+        std::collections::HashMap::new();
+    for nam in test_data.iter() {
+        let nam = nam.get();
+        let Some(true_collection) = nam.collection else {
+            continue;
+        };
+        let raw_scores = get_raw_scores_impl(nam, &train_data, params)?;
+        if let Some((collection, prob)) = raw_scores.best_choice(&raw_scores.normalized(), params) {
+            predictions
+                .entry(collection)
+                .or_default()
+                .push((prob, collection == true_collection));
+        }
+    }
+    let mut result: std::collections::HashMap<i32, f64> = std::collections::HashMap::new();
+    for (collection, mut items) in predictions.into_iter() {
+        items.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut correct = 0;
+        let mut index = 0;
+        while index < items.len() {
+            // Predictions with the same probability are accepted or rejected together
+            let prob = items[index].0;
+            while index < items.len() && items[index].0 == prob {
+                if items[index].1 {
+                    correct += 1;
+                }
+                index += 1;
+            }
+            if (correct as f64) / (index as f64) >= target_precision {
+                result.insert(collection, prob);
+            }
+        }
+    }
+    return Ok(result);
+}
+
+/// A lower and a higher value for each parameter in PARAM_NAMES, in order, with params
+/// changed to use it. Parameters are moved up and down by relative_step, or only up if
+/// they are 0.
+fn perturbations(params: &Params, relative_step: f64) -> PyResult<Vec<(f64, Params)>> {
+    // A step of 1 or more would move parameters down to 0 or below
+    if relative_step <= 0.0 || relative_step >= 1.0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "relative_step must be between 0 and 1",
+        ));
+    }
+    let mut perturbed: Vec<(f64, Params)> = Vec::new();
+    for name in PARAM_NAMES {
+        let value = params.get_value(name)?;
+        let (low, high) = if value == 0.0 {
+            (0.0, relative_step)
+        } else {
+            (value * (1.0 - relative_step), value * (1.0 + relative_step))
+        };
+        perturbed.push((low, params.with_value(name, low)?));
+        perturbed.push((high, params.with_value(name, high)?));
+    }
+    return Ok(perturbed);
+}
+
+/// Whether query's top choice (including whether there is one) stays the same when any
+/// single parameter is moved up or down by relative_step, as in sensitivity.
+#[pyfunction]
+fn prediction_stability(
+    query: &NameData,
+    train_data: Vec<Bound<'_, NameData>>,
+    params: &Params,
+    relative_step: f64,
+) -> PyResult<bool> {
+    let train_data = borrow_names(&train_data);
+    let top_collection = |params: &Params| {
+        return get_top_choice_impl(query, &train_data, params)
+            .map(|choice| choice.map(|(collection, _)| collection));
+    };
+    let baseline = top_collection(params)?;
+    for (_, perturbed) in perturbations(params, relative_step)? {
+        if top_collection(&perturbed)? != baseline {
+            return Ok(false);
+        }
+    }
+    return Ok(true);
+}
+
+/// For each parameter, the magnitude of the change in evaluation score per unit change of
+/// the parameter, estimated by moving it up and down by relative_step, which must be between
+/// 0 and 1. Parameters that are 0 are moved up by relative_step instead.
+#[pyfunction]
+fn sensitivity(
+    py: Python<'_>,
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    relative_step: f64,
+) -> PyResult<std::collections::HashMap<String, f64>> {
+    let train_data = borrow_names(&train_data);
+    let test_data = borrow_names(&test_data);
+    let perturbed = perturbations(params, relative_step)?;
+    let scores = py
+        .allow_threads(|| {
+            parallel_map(&perturbed, |(_, params)| {
+                evaluate_model_impl(&train_data, &test_data, scoring_function, params)
+                    .map(|info| info.score)
+            })
+        })
+        .into_iter()
+        .collect::<PyResult<Vec<f64>>>()?;
+    let mut result: std::collections::HashMap<String, f64> = std::collections::HashMap::new();
+    for (index, name) in PARAM_NAMES.iter().enumerate() {
+        let low_score = scores[2 * index];
+        let high_score = scores[2 * index + 1];
+        let step = perturbed[2 * index + 1].0 - perturbed[2 * index].0;
+        result.insert(
+            name.to_string(),
+            (high_score - low_score).abs() / step.abs(),
+        );
+    }
+    return Ok(result);
+}
+
+/// Improves params by coordinate search: each parameter in turn is scaled by 1 + step and
+/// 1 - step (or set to step if it is 0), keeping any change that raises the score, until a
+/// round makes no change or max_rounds rounds have run. step must be between 0 and 1.
+/// Parameters named in pinned keep their values.
+fn coordinate_search(
+    params: &Params,
+    step: f64,
+    max_rounds: usize,
+    pinned: &[String],
+    evaluate: impl Fn(&Params) -> PyResult<f64>,
+) -> PyResult<Params> {
+    // A step of 1 or more would move parameters down to 0 or below
+    if step <= 0.0 || step >= 1.0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "step must be between 0 and 1",
+        ));
+    }
+    for name in pinned {
+        params.get_value(name)?;
+    }
+    let mut best = params.clone();
+    let mut best_score = evaluate(&best)?;
+    for _ in 0..max_rounds {
+        let mut improved = false;
+        for name in PARAM_NAMES {
+            if pinned.iter().any(|pinned_name| pinned_name == name) {
+                continue;
+            }
+            let value = best.get_value(name)?;
+            let candidates = if value == 0.0 {
+                vec![step]
+            } else {
+                vec![value * (1.0 + step), value * (1.0 - step)]
+            };
+            for candidate in candidates {
+                let candidate_params = best.with_value(name, candidate)?;
+                let score = evaluate(&candidate_params)?;
+                if score > best_score {
+                    best = candidate_params;
+                    best_score = score;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+    return Ok(best);
+}
+
+/// Optimizes params for evaluate_model's score on test_data by coordinate search.
+#[pyfunction]
+#[pyo3(signature = (
+    train_data,
+    test_data,
+    scoring_function,
+    params,
+    step=0.1,
+    max_rounds=10,
+    pinned=Vec::new(),
+))]
+fn optimize_params(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    step: f64,
+    max_rounds: usize,
+    pinned: Vec<String>,
+) -> PyResult<Params> {
+    let train_data = borrow_names(&train_data);
+    let test_data = borrow_names(&test_data);
+    return coordinate_search(params, step, max_rounds, &pinned, |params| {
+        return Ok(evaluate_model_impl(&train_data, &test_data, scoring_function, params)?.score);
+    });
+}
+
+/// The features of every pair of a labelled test name and a labelled training name, so
+/// that the test set can be evaluated for many parameter values without comparing names.
+/// This uses memory proportional to the number of test names times the number of
+/// training names.
+#[pyclass(frozen)]
+struct PrecomputedEvaluator {
+    // For each test name, its collection and the (collection, features) of each training name
+    items: Vec<(i32, Vec<(i32, PairFeatures)>)>,
+}
+
+impl PrecomputedEvaluator {
+    fn from_names(train_data: &[&NameData], test_data: &[&NameData]) -> Self {
+        let items = test_data
+            .iter()
+            .filter_map(|nam| {
+                let true_collection = nam.collection?;
+                let pairs = train_data
+                    .iter()
+                    .filter_map(|train_datum| {
+                        Some((train_datum.collection?, pair_features(nam, train_datum)))
+                    })
+                    .collect();
+                Some((true_collection, pairs))
+            })
+            .collect();
+        PrecomputedEvaluator { items }
+    }
+}
+
+#[pymethods]
+impl PrecomputedEvaluator {
+    #[new]
+    fn new(train_data: Vec<Bound<'_, NameData>>, test_data: Vec<Bound<'_, NameData>>) -> Self {
+        return PrecomputedEvaluator::from_names(
+            &borrow_names(&train_data),
+            &borrow_names(&test_data),
+        );
+    }
+
+    /// The same result as evaluate_model on the names this was built from.
+    fn evaluate(&self, scoring_function: &ScoringFunction, params: &Params) -> ScoreInfo {
+        let mut counter = ScoreCounter::default();
+        for (true_collection, pairs) in self.items.iter() {
+            let contributions = pairs
+                .iter()
+                .filter(|(_, features)| {
+                    !(params.exclude_same_citation_group && features.shares_citation_group)
+                })
+                .map(|(collection, features)| (*collection, combine_features(features, params)))
+                .collect();
+            let mut raw_scores = RawScores::new(params);
+            raw_scores.add_all(contributions, params);
+            let top_choice = raw_scores.top_choice(params);
+            counter.record(
+                top_choice.map(|(collection, _)| collection),
+                *true_collection,
+            );
+        }
+        return counter.finish(scoring_function);
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "PrecomputedEvaluator(num_test_names={}, num_pairs={})",
+            self.items.len(),
+            self.items
+                .iter()
+                .map(|(_, pairs)| pairs.len())
+                .sum::<usize>()
+        ))
+    }
+}
+
+/// Like optimize_params, but compares every pair of names once up front and evaluates each
+/// candidate with a PrecomputedEvaluator.
+#[pyfunction]
+#[pyo3(signature = (
+    train_data,
+    test_data,
+    scoring_function,
+    params,
+    step=0.1,
+    max_rounds=10,
+    pinned=Vec::new(),
+))]
+fn optimize_params_cached(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    step: f64,
+    max_rounds: usize,
+    pinned: Vec<String>,
+) -> PyResult<Params> {
+    let precomputed =
+        PrecomputedEvaluator::from_names(&borrow_names(&train_data), &borrow_names(&test_data));
+    return coordinate_search(params, step, max_rounds, &pinned, |params| {
+        return Ok(precomputed.evaluate(scoring_function, params).score);
+    });
+}
+
+/// The evaluation for each of values of the parameter param_name, with the other
+/// parameters as in params, for plotting how the score moves along that parameter. The
+/// pairs of names are compared once, as in PrecomputedEvaluator.
+#[pyfunction]
+fn scan_parameter(
+    py: Python<'_>,
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    param_name: &str,
+    values: Vec<f64>,
+) -> PyResult<Vec<(f64, ScoreInfo)>> {
+    let candidates = values
+        .iter()
+        .map(|value| params.with_value(param_name, *value))
+        .collect::<PyResult<Vec<Params>>>()?;
+    let precomputed =
+        PrecomputedEvaluator::from_names(&borrow_names(&train_data), &borrow_names(&test_data));
+    let infos = py.allow_threads(|| {
+        parallel_map(&candidates, |params| {
+            precomputed.evaluate(scoring_function, params)
+        })
+    });
+    return Ok(values.into_iter().zip(infos).collect());
+}
+
+/// The outcome of grid_search.
+#[pyclass(get_all, frozen)]
+struct GridSearchResult {
+    num_combinations: usize,
+    // Measured on the starting params in a dry run, and averaged over the grid otherwise
+    seconds_per_evaluation: f64,
+    // None in a dry run
+    best_params: Option<Params>,
+    best_info: Option<ScoreInfo>,
+}
+
+#[pymethods]
+impl GridSearchResult {
+    /// The expected time to evaluate the whole grid, assuming evaluations take equally long.
+    #[getter]
+    fn estimated_seconds(&self) -> f64 {
+        return self.seconds_per_evaluation * (self.num_combinations as f64);
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "GridSearchResult(num_combinations={}, seconds_per_evaluation={}, best_params={}, best_info={})",
+            self.num_combinations,
+            self.seconds_per_evaluation,
+            match &self.best_params {
+                Some(params) => params.__repr__()?,
+                None => "None".to_string(),
+            },
+            match &self.best_info {
+                Some(info) => info.__repr__()?,
+                None => "None".to_string(),
+            }
+        ))
+    }
+}
+
+/// Evaluates every combination of the values in grid, a mapping from parameter name to the
+/// values to try, with the other parameters taken from params. With dry_run, only counts
+/// the combinations and times a single evaluation. Ties go to the combination found first,
+/// with parameters varied in alphabetical order and the last name varying fastest.
+/// Parameters named in pinned keep their values from params even if grid lists them.
+#[pyfunction]
+#[pyo3(signature = (
+    train_data,
+    test_data,
+    scoring_function,
+    params,
+    grid,
+    dry_run=false,
+    pinned=Vec::new(),
+))]
+#[allow(clippy::too_many_arguments)]
+fn grid_search(
+    py: Python<'_>,
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    mut grid: std::collections::HashMap<String, Vec<f64>>,
+    dry_run: bool,
+    pinned: Vec<String>,
+) -> PyResult<GridSearchResult> {
+    for name in pinned.iter() {
+        params.get_value(name)?;
+        grid.remove(name);
+    }
+    let (grid, num_combinations) = sorted_grid(params, grid)?;
+    let train_data = borrow_names(&train_data);
+    let test_data = borrow_names(&test_data);
+    let start = std::time::Instant::now();
+    if dry_run {
+        evaluate_model_impl(&train_data, &test_data, scoring_function, params)?;
+        return Ok(GridSearchResult {
+            num_combinations,
+            seconds_per_evaluation: start.elapsed().as_secs_f64(),
+            best_params: None,
+            best_info: None,
+        });
+    }
+    let combinations = grid_combinations(params, &grid, num_combinations)?;
+    let infos = py
+        .allow_threads(|| {
+            parallel_map(&combinations, |params| {
+                evaluate_model_impl(&train_data, &test_data, scoring_function, params)
+            })
+        })
+        .into_iter()
+        .collect::<PyResult<Vec<ScoreInfo>>>()?;
+    let seconds_per_evaluation = start.elapsed().as_secs_f64() / (num_combinations as f64);
+    let mut best: Option<(Params, ScoreInfo)> = None;
+    for (combination, info) in combinations.into_iter().zip(infos) {
+        if best
+            .as_ref()
+            .is_none_or(|(_, best_info)| info.score > best_info.score)
+        {
+            best = Some((combination, info));
+        }
+    }
+    let (best_params, best_info) = best.unzip();
+    return Ok(GridSearchResult {
+        num_combinations,
+        seconds_per_evaluation,
+        best_params,
+        best_info,
+    });
+}
+
+/// The log-score of a pair as a linear model in the logs of country_boost, cg_boost,
+/// author_boost and year_boost: the feature values those weights multiply, and the log of
+/// the factors that do not depend on them. None for a name compared with itself, and for
+/// pairs whose other factors are 0.
+fn log_linear_features(features: &PairFeatures, params: &Params) -> Option<([f64; 4], f64)> {
+    if features.same_name {
+        return None;
+    }
+    // The sentinels turn off the boosts, so reuse score_factors to see whether they apply
+    let factors = score_factors(features, &params.with_boosts([2.0; 4]));
+    let country = if factors[0] == 2.0 { 1.0 } else { 0.0 };
+    let citation_group = factors[1].log2();
+    let author = if features.authors_equal || features.shared_authors > 0 {
+        1.0
+    } else {
+        0.0
+    };
+    let mut offset = factors[3].ln();
+    if !features.authors_equal && features.shared_authors > 0 {
+        offset += (1.0 - params.length_mismatch_penalty).ln()
+            * (features.author_length_difference as f64);
+    }
+    if !offset.is_finite() {
+        return None;
+    }
+    return Some(([country, citation_group, author, 1.0], offset));
+}
+
+/// The mean log-loss of predicting whether each pair is in the same collection as the
+/// logistic function of its log-score, and its gradient with respect to the log boosts.
+fn log_loss_gradient(pairs: &[([f64; 4], f64, bool)], weights: &[f64; 4]) -> (f64, [f64; 4]) {
+    let mut loss = 0.0;
+    let mut gradient = [0.0; 4];
+    for (values, offset, same_collection) in pairs {
+        let logit = offset
+            + values
+                .iter()
+                .zip(weights)
+                .map(|(value, weight)| value * weight)
+                .sum::<f64>();
+        let target = if *same_collection { 1.0 } else { 0.0 };
+        // log(1 + e^logit), computed without overflow
+        let softplus = logit.max(0.0) + (-logit.abs()).exp().ln_1p();
+        loss += softplus - target * logit;
+        let error = 1.0 / (1.0 + (-logit).exp()) - target;
+        for (slope, value) in gradient.iter_mut().zip(values) {
+            *slope += error * value;
+        }
+    }
+    let count = pairs.len() as f64;
+    return (loss / count, gradient.map(|slope| slope / count));
+}
+
+/// Fits country_boost, cg_boost, author_boost and year_boost by gradient descent on the
+/// log-loss of predicting, for each pair of a labelled test name and a labelled training
+/// name, whether they are in the same collection. The log-score of a pair is treated as a
+/// linear model in the logs of the boosts, so this requires the multiplicative combiner;
+/// year_boost acts as the intercept. Returns the fitted params and the loss after each
+/// epoch.
+#[pyfunction]
+fn fit_boosts(
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    initial: &Params,
+    learning_rate: f64,
+    epochs: usize,
+) -> PyResult<(Params, Vec<f64>)> {
+    if initial.combiner != Combiner::Multiplicative || initial.score_mode != ScoreMode::Boost {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "fit_boosts requires the boost score mode and the multiplicative combiner",
+        ));
+    }
+    if learning_rate <= 0.0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "learning_rate must be positive",
+        ));
+    }
+    let boosts = initial.boosts();
+    if boosts.iter().any(|boost| *boost <= 0.0) {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "boosts must be positive to be fitted in log space",
+        ));
+    }
+    let precomputed =
+        PrecomputedEvaluator::from_names(&borrow_names(&train_data), &borrow_names(&test_data));
+    let mut pairs: Vec<([f64; 4], f64, bool)> = Vec::new();
+    for (true_collection, train_pairs) in precomputed.items.iter() {
+        for (collection, features) in train_pairs {
+            if initial.exclude_same_citation_group && features.shares_citation_group {
+                continue;
+            }
+            if let Some((values, offset)) = log_linear_features(features, initial) {
+                pairs.push((values, offset, collection == true_collection));
+            }
+        }
+    }
+    if pairs.is_empty() {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "no pairs of labelled names to fit on",
+        ));
+    }
+    let mut weights = boosts.map(f64::ln);
+    let (_, mut gradient) = log_loss_gradient(&pairs, &weights);
+    let mut losses = Vec::with_capacity(epochs);
+    for _ in 0..epochs {
+        for (weight, slope) in weights.iter_mut().zip(gradient) {
+            *weight -= learning_rate * slope;
+        }
+        let (loss, new_gradient) = log_loss_gradient(&pairs, &weights);
+        losses.push(loss);
+        gradient = new_gradient;
+    }
+    return Ok((initial.with_boosts(weights.map(f64::exp)), losses));
+}
+
+// Parameter names with the values to try for each.
+type ParamGrid = Vec<(String, Vec<f64>)>;
+
+/// The grid sorted by parameter name, with the number of combinations it has, checking
+/// that every name is a parameter with at least one value.
+fn sorted_grid(
+    params: &Params,
+    grid: std::collections::HashMap<String, Vec<f64>>,
+) -> PyResult<(ParamGrid, usize)> {
+    let mut grid: ParamGrid = grid.into_iter().collect();
+    grid.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut num_combinations: usize = 1;
+    for (name, values) in grid.iter() {
+        params.get_value(name)?;
+        if values.is_empty() {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "no values given for {}",
+                name
+            )));
+        }
+        num_combinations = num_combinations.checked_mul(values.len()).ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err("too many parameter combinations")
+        })?;
+    }
+    return Ok((grid, num_combinations));
+}
+
+/// Every combination of the values in a sorted grid, with the other parameters taken from
+/// params and the last name varying fastest.
+fn grid_combinations(
+    params: &Params,
+    grid: &[(String, Vec<f64>)],
+    num_combinations: usize,
+) -> PyResult<Vec<Params>> {
+    let mut combinations: Vec<Params> = Vec::with_capacity(num_combinations);
+    for index in 0..num_combinations {
+        let mut combination = params.clone();
+        let mut remainder = index;
+        for (name, values) in grid.iter().rev() {
+            combination = combination.with_value(name, values[remainder % values.len()])?;
+            remainder /= values.len();
+        }
+        combinations.push(combination);
+    }
+    return Ok(combinations);
+}
+
+// The parameters Params requires; the others have defaults.
+const REQUIRED_PARAM_NAMES: [&str; 7] = [
+    "country_boost",
+    "cg_boost",
+    "author_boost",
+    "year_factor",
+    "year_boost",
+    "score_cutoff",
+    "probability_cutoff",
+];
+
+/// Params for every combination of the values in grids, a mapping from parameter name to
+/// the values to try, in the order grid_search evaluates them. The seven parameters Params
+/// requires must be in grids; the others take their defaults when left out.
+#[pyfunction]
+fn build_params_grid(
+    py: Python<'_>,
+    grids: std::collections::HashMap<String, Vec<f64>>,
+) -> PyResult<Vec<Params>> {
+    let missing: Vec<&str> = REQUIRED_PARAM_NAMES
+        .into_iter()
+        .filter(|name| !grids.contains_key(*name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "grids is missing values for {}",
+            missing.join(", ")
+        )));
+    }
+    // Construct through Python so that the defaults are those of Params' signature
+    let defaults: Params = py
+        .get_type_bound::<Params>()
+        .call1((0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0))?
+        .extract()?;
+    let (grid, num_combinations) = sorted_grid(&defaults, grids)?;
+    return grid_combinations(&defaults, &grid, num_combinations);
+}
+
+/// For each parameter in candidates, the best of its candidate values with the others
+/// fixed at params, and of those the parameter whose best value raises evaluate_model's
+/// score the most over params: its name, that value and the resulting ScoreInfo. Ties go
+/// to the parameter first in alphabetical order, then to the value listed first.
+#[pyfunction]
+fn bottleneck_param(
+    py: Python<'_>,
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    candidates: std::collections::HashMap<String, Vec<f64>>,
+) -> PyResult<(String, f64, ScoreInfo)> {
+    if candidates.is_empty() {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "candidates must not be empty",
+        ));
+    }
+    let (grid, _) = sorted_grid(params, candidates)?;
+    let mut trials: Vec<(&str, f64, Params)> = Vec::new();
+    for (name, values) in grid.iter() {
+        for value in values {
+            trials.push((name, *value, params.with_value(name, *value)?));
+        }
+    }
+    let train_data = borrow_names(&train_data);
+    let test_data = borrow_names(&test_data);
+    let infos = py
+        .allow_threads(|| {
+            parallel_map(&trials, |(_, _, params)| {
+                evaluate_model_impl(&train_data, &test_data, scoring_function, params)
+            })
+        })
+        .into_iter()
+        .collect::<PyResult<Vec<ScoreInfo>>>()?;
+    // Every parameter is compared with the same baseline, so the largest gain is the
+    // highest score
+    let mut best: Option<(&str, f64, ScoreInfo)> = None;
+    for ((name, value, _), info) in trials.into_iter().zip(infos) {
+        if best
+            .as_ref()
+            .is_none_or(|(_, _, best_info)| info.score > best_info.score)
+        {
+            best = Some((name, value, info));
+        }
+    }
+    let (name, value, info) = best.expect("candidates has at least one value");
+    return Ok((name.to_string(), value, info));
+}
+
+#[pyfunction]
+fn cross_validate(
+    py: Python<'_>,
+    data: Vec<Bound<'_, NameData>>,
+    num_folds: usize,
+    seed: u64,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+) -> PyResult<Vec<ScoreInfo>> {
+    if num_folds < 2 || num_folds > data.len() {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "num_folds must be between 2 and the number of names ({}), got {}",
+            data.len(),
+            num_folds
+        )));
+    }
+    let data = borrow_names(&data);
+    // Assign folds before going parallel so the split only depends on the seed.
+    let mut order: Vec<usize> = (0..data.len()).collect();
+    SeededRng::new(seed).shuffle(&mut order);
+    let mut fold_of = vec![0; data.len()];
+    for (position, index) in order.iter().enumerate() {
+        fold_of[*index] = position % num_folds;
+    }
+    let folds: Vec<usize> = (0..num_folds).collect();
+    let results = py.allow_threads(|| {
+        parallel_map(&folds, |fold| {
+            let mut train_data: Vec<&NameData> = Vec::new();
+            let mut test_data: Vec<&NameData> = Vec::new();
+            for (index, datum) in data.iter().enumerate() {
+                if fold_of[index] == *fold {
+                    test_data.push(datum);
+                } else {
+                    train_data.push(datum);
+                }
+            }
+            evaluate_model_impl(&train_data, &test_data, scoring_function, params)
+        })
+    });
+    return results.into_iter().collect();
+}
+
+/// The evaluation of test_data when training on a random subsample of each fraction of
+/// train_data, for seeing whether more training data would help. Subsamples are
+/// stratified by collection, taking the fraction (rounded) of each collection's names, and
+/// depend only on seed.
+#[pyfunction]
+fn learning_curve(
+    py: Python<'_>,
+    train_data: Vec<Bound<'_, NameData>>,
+    test_data: Vec<Bound<'_, NameData>>,
+    scoring_function: &ScoringFunction,
+    params: &Params,
+    fractions: Vec<f64>,
+    seed: u64,
+) -> PyResult<Vec<(f64, ScoreInfo)>> {
+    if fractions
+        .iter()
+        .any(|fraction| !(0.0..=1.0).contains(fraction))
+    {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "fractions must be between 0 and 1",
+        ));
+    }
+    let train_data = borrow_names(&train_data);
+    let test_data = borrow_names(&test_data);
+    let mut strata: std::collections::HashMap<Option<i32>, Vec<&NameData>> =
+        std::collections::HashMap::new();
+    for nam in train_data.iter().copied() {
+        strata.entry(nam.collection).or_default().push(nam);
+    }
+    // Shuffle the strata in a fixed order so the subsamples only depend on the seed
+    let mut strata: Vec<(Option<i32>, Vec<&NameData>)> = strata.into_iter().collect();
+    strata.sort_by_key(|(collection, _)| *collection);
+    let mut rng = SeededRng::new(seed);
+    for (_, names) in strata.iter_mut() {
+        rng.shuffle(names);
+    }
+    let results = py.allow_threads(|| {
+        parallel_map(&fractions, |fraction| {
+            let subsample: Vec<&NameData> = strata
+                .iter()
+                .flat_map(|(_, names)| {
+                    let size = (names.len() as f64 * fraction).round() as usize;
+                    names[..size].iter().copied()
+                })
+                .collect();
+            let info = evaluate_model_impl(&subsample, &test_data, scoring_function, params)?;
+            return Ok((*fraction, info));
+        })
+    });
+    return results.into_iter().collect();
+}
+
+/// Applies `func` to each item across all available cores, preserving order.
+fn parallel_map<T: Sync, R: Send>(items: &[T], func: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let num_threads = std::thread::available_parallelism()
+        .map_or(1, |threads| threads.get())
+        .min(items.len())
+        .max(1);
+    let chunk_size = items.len().div_ceil(num_threads).max(1);
+    return std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&func).collect::<Vec<R>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("worker thread panicked"))
+            .collect()
+    });
+}
+
+const SHA256_ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// The hex SHA-256 digest of data. Implemented here because no hashing crate is a
+/// dependency; the digest has to be stable across platforms and Rust versions.
+fn sha256_hex(data: &[u8]) -> String {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut schedule = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            schedule[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = schedule[i - 15].rotate_right(7)
+                ^ schedule[i - 15].rotate_right(18)
+                ^ (schedule[i - 15] >> 3);
+            let s1 = schedule[i - 2].rotate_right(17)
+                ^ schedule[i - 2].rotate_right(19)
+                ^ (schedule[i - 2] >> 10);
+            schedule[i] = schedule[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(schedule[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(SHA256_ROUND_CONSTANTS[i])
+                .wrapping_add(schedule[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+    return state.iter().map(|word| format!("{:08x}", word)).collect();
+}
+
+/// A column for parquet_file. The values of a required column are all Some.
+#[cfg(feature = "parquet")]
+struct ParquetColumn {
+    name: &'static str,
+    required: bool,
+    values: ParquetValues,
+}
+
+#[cfg(feature = "parquet")]
+enum ParquetValues {
+    Int32(Vec<Option<i32>>),
+    Double(Vec<Option<f64>>),
+}
+
+#[cfg(feature = "parquet")]
+impl ParquetValues {
+    // The Type enum of the Parquet format
+    fn physical_type(&self) -> i32 {
+        return match self {
+            ParquetValues::Int32(_) => 1,
+            ParquetValues::Double(_) => 5,
+        };
+    }
+
+    fn len(&self) -> usize {
+        return match self {
+            ParquetValues::Int32(values) => values.len(),
+            ParquetValues::Double(values) => values.len(),
+        };
+    }
+
+    fn is_present(&self) -> Vec<bool> {
+        return match self {
+            ParquetValues::Int32(values) => values.iter().map(Option::is_some).collect(),
+            ParquetValues::Double(values) => values.iter().map(Option::is_some).collect(),
+        };
+    }
+
+    // The PLAIN encoding of the values that are not null
+    fn plain(&self) -> Vec<u8> {
+        return match self {
+            ParquetValues::Int32(values) => values
+                .iter()
+                .flatten()
+                .flat_map(|value| value.to_le_bytes())
+                .collect(),
+            ParquetValues::Double(values) => values
+                .iter()
+                .flatten()
+                .flat_map(|value| value.to_le_bytes())
+                .collect(),
+        };
+    }
+}
+
+/// Writes Thrift's compact protocol, in which the Parquet metadata is stored. Fields must
+/// be written in increasing order of id within each struct.
+#[cfg(feature = "parquet")]
+struct ThriftWriter {
+    bytes: Vec<u8>,
+    // The id of the last field written in each struct being written
+    last_field_ids: Vec<i16>,
+}
+
+#[cfg(feature = "parquet")]
+impl ThriftWriter {
+    const I32: u8 = 5;
+    const I64: u8 = 6;
+    const BINARY: u8 = 8;
+    const LIST: u8 = 9;
+    const STRUCT: u8 = 12;
+
+    fn new() -> Self {
+        return ThriftWriter {
+            bytes: Vec::new(),
+            last_field_ids: vec![0],
+        };
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn zigzag(&mut self, value: i64) {
+        self.varint(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    fn field_header(&mut self, id: i16, field_type: u8) {
+        let last_id = self.last_field_ids.last_mut().expect("a struct is open");
+        let delta = id - *last_id;
+        *last_id = id;
+        if (1..=15).contains(&delta) {
+            self.bytes.push(((delta as u8) << 4) | field_type);
+        } else {
+            self.bytes.push(field_type);
+            self.zigzag(i64::from(id));
+        }
+    }
+
+    fn list_header(&mut self, len: usize, element_type: u8) {
+        if len < 15 {
+            self.bytes.push(((len as u8) << 4) | element_type);
+        } else {
+            self.bytes.push(0xf0 | element_type);
+            self.varint(len as u64);
+        }
+    }
+
+    fn i32_field(&mut self, id: i16, value: i32) {
+        self.field_header(id, ThriftWriter::I32);
+        self.zigzag(i64::from(value));
+    }
+
+    fn i64_field(&mut self, id: i16, value: i64) {
+        self.field_header(id, ThriftWriter::I64);
+        self.zigzag(value);
+    }
+
+    fn binary(&mut self, value: &[u8]) {
+        self.varint(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    fn string_field(&mut self, id: i16, value: &str) {
+        self.field_header(id, ThriftWriter::BINARY);
+        self.binary(value.as_bytes());
+    }
+
+    fn list_field(&mut self, id: i16, len: usize, element_type: u8) {
+        self.field_header(id, ThriftWriter::LIST);
+        self.list_header(len, element_type);
+    }
+
+    // Starts a struct that is a field or, without an id, a list element
+    fn begin_struct(&mut self, id: Option<i16>) {
+        if let Some(id) = id {
+            self.field_header(id, ThriftWriter::STRUCT);
+        }
+        self.last_field_ids.push(0);
+    }
+
+    fn end_struct(&mut self) {
+        self.bytes.push(0);
+        self.last_field_ids.pop();
+    }
+}
+
+/// The bytes of a Parquet file holding columns as a single row group, with one
+/// uncompressed, PLAIN-encoded data page per column. Implemented here because no Parquet
+/// crate is a dependency, so this covers only what write_predictions_parquet needs.
+#[cfg(feature = "parquet")]
+fn parquet_file(columns: &[ParquetColumn]) -> Vec<u8> {
+    // The Encoding and CompressionCodec enums of the Parquet format
+    const PLAIN: i32 = 0;
+    const RLE: i32 = 3;
+    const UNCOMPRESSED: i32 = 0;
+    let num_rows = columns.first().map_or(0, |column| column.values.len());
+    let mut file = b"PAR1".to_vec();
+    let mut chunks: Vec<(i64, i64)> = Vec::with_capacity(columns.len());
+    for column in columns {
+        let mut page = Vec::new();
+        if !column.required {
+            // Definition levels in the RLE hybrid encoding, as runs of 1 (present) or 0
+            let mut levels = ThriftWriter::new();
+            let is_present = column.values.is_present();
+            for run in is_present.chunk_by(|a, b| a == b) {
+                levels.varint((run.len() as u64) << 1);
+                levels.bytes.push(u8::from(run[0]));
+            }
+            page.extend_from_slice(&(levels.bytes.len() as u32).to_le_bytes());
+            page.extend_from_slice(&levels.bytes);
+        }
+        page.extend_from_slice(&column.values.plain());
+        let mut header = ThriftWriter::new();
+        header.i32_field(1, 0);
+        header.i32_field(2, page.len() as i32);
+        header.i32_field(3, page.len() as i32);
+        header.begin_struct(Some(5));
+        header.i32_field(1, num_rows as i32);
+        header.i32_field(2, PLAIN);
+        header.i32_field(3, RLE);
+        header.i32_field(4, RLE);
+        header.end_struct();
+        header.end_struct();
+        let offset = file.len() as i64;
+        file.extend_from_slice(&header.bytes);
+        file.extend_from_slice(&page);
+        chunks.push((offset, file.len() as i64 - offset));
+    }
+    let mut metadata = ThriftWriter::new();
+    metadata.i32_field(1, 1);
+    metadata.list_field(2, columns.len() + 1, ThriftWriter::STRUCT);
+    metadata.begin_struct(None);
+    metadata.string_field(4, "schema");
+    metadata.i32_field(5, columns.len() as i32);
+    metadata.end_struct();
+    for column in columns {
+        metadata.begin_struct(None);
+        metadata.i32_field(1, column.values.physical_type());
+        // The FieldRepetitionType enum: REQUIRED or OPTIONAL
+        metadata.i32_field(3, if column.required { 0 } else { 1 });
+        metadata.string_field(4, column.name);
+        metadata.end_struct();
+    }
+    metadata.i64_field(3, num_rows as i64);
+    metadata.list_field(4, 1, ThriftWriter::STRUCT);
+    metadata.begin_struct(None);
+    metadata.list_field(1, columns.len(), ThriftWriter::STRUCT);
+    for (column, (offset, size)) in columns.iter().zip(chunks.iter()) {
+        metadata.begin_struct(None);
+        metadata.i64_field(2, *offset);
+        metadata.begin_struct(Some(3));
+        metadata.i32_field(1, column.values.physical_type());
+        metadata.list_field(2, 2, ThriftWriter::I32);
+        metadata.zigzag(i64::from(PLAIN));
+        metadata.zigzag(i64::from(RLE));
+        metadata.list_field(3, 1, ThriftWriter::BINARY);
+        metadata.binary(column.name.as_bytes());
+        metadata.i32_field(4, UNCOMPRESSED);
+        metadata.i64_field(5, num_rows as i64);
+        metadata.i64_field(6, *size);
+        metadata.i64_field(7, *size);
+        metadata.i64_field(9, *offset);
+        metadata.end_struct();
+        metadata.end_struct();
+    }
+    metadata.i64_field(2, chunks.iter().map(|(_, size)| size).sum());
+    metadata.i64_field(3, num_rows as i64);
+    metadata.end_struct();
+    metadata.string_field(6, "repoguess");
+    metadata.end_struct();
+    file.extend_from_slice(&metadata.bytes);
+    file.extend_from_slice(&(metadata.bytes.len() as u32).to_le_bytes());
+    file.extend_from_slice(b"PAR1");
+    return file;
+}
+
+/// The random number generator used by every function that takes a seed (xoshiro256**,
+/// seeded through SplitMix64). It is implemented here rather than taken from a crate so
+/// that the output for a given seed never changes across platforms or library versions.
+struct SeededRng {
+    state: [u64; 4],
+}
+
+impl SeededRng {
+    fn new(seed: u64) -> Self {
+        let mut splitmix_state = seed;
+        let mut state = [0; 4];
+        for word in state.iter_mut() {
+            splitmix_state = splitmix_state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = splitmix_state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            *word = z ^ (z >> 31);
+        }
+        SeededRng { state }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.state[1] << 17;
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(45);
+        return result;
+    }
+
+    /// Returns a number in 0..bound.
+    fn below(&mut self, bound: usize) -> usize {
+        return ((self.next_u64() as u128 * bound as u128) >> 64) as usize;
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+#[pymodule]
+fn repoguess(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(get_score, m)?)?;
+    m.add_function(wrap_pyfunction!(benchmark_scoring, m)?)?;
+    m.add_function(wrap_pyfunction!(get_probs, m)?)?;
+    m.add_function(wrap_pyfunction!(get_raw_scores, m)?)?;
+    m.add_function(wrap_pyfunction!(contribution_counts, m)?)?;
+    m.add_function(wrap_pyfunction!(score_histogram, m)?)?;
+    m.add_function(wrap_pyfunction!(ensemble_probs, m)?)?;
+    m.add_function(wrap_pyfunction!(get_top_choice, m)?)?;
+    m.add_function(wrap_pyfunction!(get_top_choice_early, m)?)?;
+    m.add_function(wrap_pyfunction!(top_choice_explained, m)?)?;
+    m.add_function(wrap_pyfunction!(evaluate_model, m)?)?;
+    m.add_function(wrap_pyfunction!(f_beta, m)?)?;
+    m.add_function(wrap_pyfunction!(nucleus_accuracy, m)?)?;
+    m.add_function(wrap_pyfunction!(prune_train, m)?)?;
+    m.add_function(wrap_pyfunction!(predictions_digest, m)?)?;
+    #[cfg(feature = "parquet")]
+    m.add_function(wrap_pyfunction!(write_predictions_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(per_collection_cutoffs, m)?)?;
+    m.add_function(wrap_pyfunction!(sensitivity, m)?)?;
+    m.add_function(wrap_pyfunction!(cross_validate, m)?)?;
+    m.add_function(wrap_pyfunction!(evaluate_detailed, m)?)?;
+    m.add_function(wrap_pyfunction!(most_influential, m)?)?;
+    m.add_function(wrap_pyfunction!(optimize_params, m)?)?;
+    m.add_function(wrap_pyfunction!(optimize_params_cached, m)?)?;
+    m.add_function(wrap_pyfunction!(author_overlap, m)?)?;
+    m.add_function(wrap_pyfunction!(evaluate_full, m)?)?;
+    m.add_function(wrap_pyfunction!(field_match, m)?)?;
+    m.add_class::<FieldMatch>()?;
+    m.add_function(wrap_pyfunction!(misclassification_report, m)?)?;
+    m.add_class::<ConfusionExplanation>()?;
+    m.add_class::<Aggregation>()?;
+    m.add_function(wrap_pyfunction!(best_cutoff, m)?)?;
+    m.add_function(wrap_pyfunction!(prediction_stability, m)?)?;
+    m.add_function(wrap_pyfunction!(to_label_arrays, m)?)?;
+    m.add_function(wrap_pyfunction!(score_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_author_frequencies, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_citation_group_sizes, m)?)?;
+    m.add_function(wrap_pyfunction!(grid_search, m)?)?;
+    m.add_class::<GridSearchResult>()?;
+    m.add_function(wrap_pyfunction!(cross_agreement, m)?)?;
+    m.add_function(wrap_pyfunction!(score_matrix_by_collection, m)?)?;
+    m.add_function(wrap_pyfunction!(find_near_duplicates, m)?)?;
+    m.add_class::<Combiner>()?;
+    m.add_function(wrap_pyfunction!(evaluate_forward_only, m)?)?;
+    m.add_function(wrap_pyfunction!(nearest_neighbor_choice, m)?)?;
+    m.add_class::<AuthorOverlapDenominator>()?;
+    m.add_function(wrap_pyfunction!(expected_abstentions, m)?)?;
+    m.add_function(wrap_pyfunction!(abstentions_by_cutoff, m)?)?;
+    m.add_function(wrap_pyfunction!(get_score_verbose, m)?)?;
+    m.add_function(wrap_pyfunction!(most_influential_train_item, m)?)?;
+    m.add_function(wrap_pyfunction!(best_over_params, m)?)?;
+    m.add_function(wrap_pyfunction!(feature_correlation, m)?)?;
+    m.add_function(wrap_pyfunction!(get_top_k, m)?)?;
+    m.add_function(wrap_pyfunction!(break_even_cost, m)?)?;
+    m.add_function(wrap_pyfunction!(accuracy_by_year, m)?)?;
+    m.add_function(wrap_pyfunction!(counterfactual_country, m)?)?;
+    m.add_function(wrap_pyfunction!(intern_authors, m)?)?;
+    m.add_function(wrap_pyfunction!(evaluate_confident, m)?)?;
+    m.add_function(wrap_pyfunction!(plausible_collection_count, m)?)?;
+    m.add_function(wrap_pyfunction!(bagged_top_choice, m)?)?;
+    m.add_function(wrap_pyfunction!(zero_error_cutoff, m)?)?;
+    m.add_function(wrap_pyfunction!(export_scoring_table, m)?)?;
+    m.add_function(wrap_pyfunction!(label_noise_estimate, m)?)?;
+    m.add_function(wrap_pyfunction!(predict_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(get_probs_parallel, m)?)?;
+    m.add_function(wrap_pyfunction!(learning_curve, m)?)?;
+    m.add_function(wrap_pyfunction!(confusion_matrix, m)?)?;
+    m.add_function(wrap_pyfunction!(merge_candidates, m)?)?;
+    m.add_function(wrap_pyfunction!(ovr_scores, m)?)?;
+    m.add_function(wrap_pyfunction!(benchmark_queries, m)?)?;
+    m.add_class::<LatencyStats>()?;
+    m.add_function(wrap_pyfunction!(validate_dataset, m)?)?;
+    m.add_class::<CachedPredictions>()?;
+    m.add_function(wrap_pyfunction!(model_vs_nn_agreement, m)?)?;
+    m.add_function(wrap_pyfunction!(fit_boosts, m)?)?;
+    m.add_function(wrap_pyfunction!(prediction_diversity, m)?)?;
+    m.add_function(wrap_pyfunction!(accuracy_by_collection, m)?)?;
+    m.add_class::<CollectionAccuracy>()?;
+    m.add_function(wrap_pyfunction!(top_contributor_sensitivity, m)?)?;
+    m.add_function(wrap_pyfunction!(feature_mutual_information, m)?)?;
+    m.add_function(wrap_pyfunction!(gated_top_choice, m)?)?;
+    m.add_function(wrap_pyfunction!(fit_novelty_threshold, m)?)?;
+    m.add_function(wrap_pyfunction!(build_params_grid, m)?)?;
+    m.add_function(wrap_pyfunction!(explain_prediction, m)?)?;
+    m.add_function(wrap_pyfunction!(evaluate_calibrated, m)?)?;
+    m.add_function(wrap_pyfunction!(count_ties, m)?)?;
+    m.add_function(wrap_pyfunction!(top_prob_distribution, m)?)?;
+    m.add_function(wrap_pyfunction!(accuracy_by_author_count, m)?)?;
+    m.add_function(wrap_pyfunction!(bottleneck_param, m)?)?;
+    m.add_function(wrap_pyfunction!(simulate_new_collection, m)?)?;
+    m.add_function(wrap_pyfunction!(winning_contributions, m)?)?;
+    m.add_function(wrap_pyfunction!(expected_score, m)?)?;
+    m.add_function(wrap_pyfunction!(cluster_by_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_parameter, m)?)?;
+    m.add_function(wrap_pyfunction!(best_cutoff_balanced, m)?)?;
+    m.add_function(wrap_pyfunction!(get_distance, m)?)?;
+    m.add_class::<NameData>()?;
+    m.add_class::<Params>()?;
+    m.add_class::<PrecomputedEvaluator>()?;
+    m.add_class::<PredictionExplanation>()?;
+    m.add_class::<PredictionOutcome>()?;
+    m.add_class::<PredictionRecord>()?;
+    m.add_class::<RawScores>()?;
+    m.add_class::<ScoreBreakdown>()?;
+    m.add_class::<ScoreInfo>()?;
+    m.add_class::<ScoreMode>()?;
+    m.add_class::<ScoreStats>()?;
+    m.add_class::<ScoringFunction>()?;
+    m.add_class::<ScoringTable>()?;
+    m.add_class::<TieBreak>()?;
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(
+        collection: Option<i32>,
+        tl_country: i32,
+        year: Option<i32>,
+        authors: &[i32],
+        citation_group: i32,
+        name_id: i32,
+    ) -> NameData {
+        return NameData::new(
+            collection,
+            tl_country,
+            year,
+            authors.to_vec(),
+            CitationGroups::Single(citation_group),
+            name_id,
+        );
+    }
+
+    fn params() -> Params {
+        return Params::new(
+            2.0,
+            2.0,
+            3.0,
+            1.1,
+            1.0,
+            0.5,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            None,
+            true,
+            Aggregation::Sum,
+            1,
+            false,
+            0.0,
+            None,
+            Combiner::Multiplicative,
+            None,
+            0.0,
+            AuthorOverlapDenominator::Union,
+            None,
+            None,
+            0,
+            1.0,
+            false,
+            TieBreak::LowestId,
+            ScoreMode::Boost,
+            1.0,
+            0.1,
+            0.0,
+        )
+        .unwrap();
+    }
+
+    // Collections 1 and 2 tie on probability, but collection 2 has the best single match
+    fn tie_fixture() -> (NameData, Vec<NameData>) {
+        let query = name(Some(2), 1, Some(1900), &[1, 2], 1, 0);
+        let train_data = vec![
+            name(Some(1), 1, None, &[3], 1, 1),
+            name(Some(1), 1, None, &[3], 1, 2),
+            name(Some(2), 1, None, &[1, 2], 2, 3),
+            name(Some(2), 1, None, &[3], 2, 4),
+        ];
+        return (query, train_data);
+    }
+
+    // Names with a known collection, generated as for benchmark_scoring
+    fn synthetic_data(first_id: i32, count: i32) -> Vec<NameData> {
+        return (first_id..first_id + count).map(synthetic_name).collect();
+    }
+
+    // Names in four collections whose fields mostly follow the collection, so that some but
+    // not all of them are predicted correctly
+    fn clustered_data(first_id: i32, count: i32) -> Vec<NameData> {
+        return synthetic_data(first_id, count)
+            .into_iter()
+            .map(|nam| {
+                let collection = nam.collection.unwrap() % 4 + 1;
+                let tl_country = if nam.tl_country < 6 {
+                    collection
+                } else {
+                    nam.tl_country
+                };
+                let citation_group = if nam.citation_groups[0] < 20 {
+                    collection
+                } else {
+                    nam.citation_groups[0]
+                };
+                let authors: Vec<i32> = nam
+                    .authors
+                    .iter()
+                    .map(|author| match author % 2 {
+                        0 => collection * 100 + author % 6,
+                        _ => 1000 + author,
+                    })
+                    .collect();
+                return name(
+                    Some(collection),
+                    tl_country,
+                    nam.year,
+                    &authors,
+                    citation_group,
+                    nam.name_id,
+                );
+            })
+            .collect();
+    }
+
+    fn bound<'py>(py: Python<'py>, names: &[NameData]) -> Vec<Bound<'py, NameData>> {
+        return names
+            .iter()
+            .map(|nam| Bound::new(py, nam.clone()).unwrap())
+            .collect();
+    }
+
+    // For the pyfunctions, which take names as Python objects
+    fn with_names<R>(
+        names: &[NameData],
+        func: impl FnOnce(Python<'_>, Vec<Bound<'_, NameData>>) -> R,
+    ) -> R {
+        pyo3::prepare_freethreaded_python();
+        return Python::with_gil(|py| func(py, bound(py, names)));
+    }
+
+    // The counts and score of an evaluation, for comparing two of them
+    fn summary(info: &ScoreInfo) -> (f64, i32, i32, i32) {
+        return (info.score, info.correct, info.incorrect, info.no_value);
+    }
+
+    // Probabilities computed twice can differ in the last bits, since the scores are summed
+    // in HashMap order
+    fn assert_probs_close(
+        probs: &std::collections::HashMap<i32, f64>,
+        expected: &std::collections::HashMap<i32, f64>,
+    ) {
+        assert_eq!(probs.len(), expected.len());
+        for (collection, prob) in expected {
+            assert!((probs[collection] - prob).abs() < 1e-12);
+        }
+    }
+
+    fn same_choice(choice: Option<(i32, f64)>, expected: Option<(i32, f64)>) -> bool {
+        return match (choice, expected) {
+            (Some((collection1, prob1)), Some((collection2, prob2))) => {
+                collection1 == collection2 && (prob1 - prob2).abs() < 1e-12
+            }
+            (choice, expected) => choice.is_none() && expected.is_none(),
+        };
+    }
+
+    #[test]
+    fn ensemble_of_identical_models_is_the_model() {
+        let train_data = synthetic_data(0, 50);
+        let query = synthetic_name(100);
+        let params = params();
+        let single =
+            get_probs_impl(&query, &train_data.iter().collect::<Vec<_>>(), &params).unwrap();
+        with_names(&train_data, |_, names| {
+            let ensemble =
+                ensemble_probs(&query, vec![names.clone(), names], &params, None).unwrap();
+            assert_eq!(ensemble.len(), single.len());
+            for (collection, prob) in single.iter() {
+                assert!((ensemble[collection] - prob).abs() < 1e-12);
+            }
+        });
+    }
+
+    #[test]
+    fn heavily_weighted_model_dominates_ensemble() {
+        let query = name(None, 1, Some(1900), &[1, 2], 1, 0);
+        let model1 = [name(Some(1), 1, Some(1900), &[1, 2], 1, 1)];
+        let model2 = [name(Some(2), 1, Some(1900), &[1, 2], 1, 2)];
+        let params = params();
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let train_data_sets = vec![bound(py, &model1), bound(py, &model2)];
+            let even = ensemble_probs(
+                &query,
+                train_data_sets.clone(),
+                &params,
+                Some(vec![1.0, 1.0]),
+            )
+            .unwrap();
+            assert_eq!(even[&1], even[&2]);
+            let weighted =
+                ensemble_probs(&query, train_data_sets, &params, Some(vec![99.0, 1.0])).unwrap();
+            assert!(weighted[&1] > 10.0 * weighted[&2]);
+            assert!((weighted.values().sum::<f64>() - 1.0).abs() < 1e-12);
+        });
+    }
+
+    #[test]
+    fn benchmark_scoring_times_every_case() {
+        pyo3::prepare_freethreaded_python();
+        let timings = Python::with_gil(|py| benchmark_scoring(py, &params(), 10, 5).unwrap());
+        for case in [
+            "short_authors_matching",
+            "short_authors_different",
+            "long_authors_matching",
+            "long_authors_different",
+            "get_probs",
+        ] {
+            assert!(timings[case] >= 0.0);
+        }
+    }
+
+    #[test]
+    fn count_shared_paths_agree() {
+        let mut rng = SeededRng::new(3);
+        for len1 in [1, 5, SET_LOOKUP_THRESHOLD, SET_LOOKUP_THRESHOLD + 1, 40] {
+            for len2 in [0, 3, SET_LOOKUP_THRESHOLD, SET_LOOKUP_THRESHOLD + 1, 50] {
+                // A small range makes shared and repeated items likely
+                let items1: Vec<i32> = (0..len1).map(|_| rng.below(30) as i32).collect();
+                let items2: Vec<i32> = (0..len2).map(|_| rng.below(30) as i32).collect();
+                let linear = items1.iter().filter(|item| items2.contains(item)).count();
+                assert_eq!(count_shared(&items1, &items2), linear);
+            }
+        }
+    }
+
+    #[test]
+    fn top_choice_explained_gives_the_reason_for_abstaining() {
+        let query = name(None, 1, Some(1900), &[1], 1, 0);
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(1), 1, Some(1910), &[1], 1, 2),
+            name(Some(2), 2, Some(1950), &[2], 2, 3),
+        ];
+        let outcome = |params: &Params| {
+            return with_names(&train_data, |_, train_data| {
+                return top_choice_explained(&query, train_data, params).unwrap();
+            });
+        };
+        match outcome(&params()) {
+            PredictionOutcome::Predicted { collection, .. } => assert_eq!(collection, 1),
+            _ => panic!("expected a prediction"),
+        }
+        let cautious = Params {
+            probability_cutoff: 0.99,
+            ..params()
+        };
+        assert!(matches!(
+            outcome(&cautious),
+            PredictionOutcome::BelowCutoff { .. }
+        ));
+        let strict = Params {
+            score_cutoff: 1e9,
+            ..params()
+        };
+        assert!(matches!(
+            outcome(&strict),
+            PredictionOutcome::NoCandidates {}
+        ));
+    }
+
+    #[test]
+    fn missing_year_gives_no_year_decay() {
+        let train_datum = name(Some(1), 1, Some(1900), &[1], 1, 1);
+        let score = |year: Option<i32>| {
+            let query = name(None, 1, year, &[1], 1, 0);
+            return get_score_impl(&query, &train_datum, &params()).unwrap();
+        };
+        assert_eq!(score(None), score(Some(1900)));
+        assert!(score(Some(1950)) < score(None));
+        let query = name(None, 1, None, &[1], 1, 0);
+        assert!(query.__repr__().unwrap().contains("year=None"));
+    }
+
+    #[test]
+    fn cross_validate_tests_every_name_once() {
+        let data = synthetic_data(15000, 30);
+        let scoring_function = ScoringFunction::new(1.0, 0.0, 0.0);
+        let (folds, errors) = with_names(&data, |py, data| {
+            let cross_validate_with = |num_folds: usize| {
+                return cross_validate(
+                    py,
+                    data.clone(),
+                    num_folds,
+                    7,
+                    &scoring_function,
+                    &params(),
+                );
+            };
+            return (
+                cross_validate_with(3).unwrap(),
+                [
+                    cross_validate_with(1).is_err(),
+                    cross_validate_with(31).is_err(),
+                ],
+            );
+        });
+        assert_eq!(folds.len(), 3);
+        let tested: i32 = folds
+            .iter()
+            .map(|info| info.correct + info.incorrect + info.no_value)
+            .sum();
+        assert_eq!(tested, 30);
+        assert_eq!(errors, [true, true]);
+    }
+
+    #[test]
+    fn per_collection_cutoffs_reach_the_target_precision() {
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(1), 1, Some(1900), &[1], 1, 2),
+            name(Some(2), 2, Some(1900), &[2], 2, 3),
+        ];
+        // Both are predicted as collection 1, the wrong one less confidently
+        let test_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 10),
+            name(Some(2), 2, Some(1900), &[1], 2, 11),
+        ];
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let probability = |nam: &NameData| {
+            let choice = get_top_choice_impl(nam, &train_names, &params()).unwrap();
+            assert_eq!(choice.map(|(collection, _)| collection), Some(1));
+            return choice.unwrap().1;
+        };
+        let (correct, incorrect) = (probability(&test_data[0]), probability(&test_data[1]));
+        assert!(incorrect < correct);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let cutoffs = |target_precision: f64| {
+                return per_collection_cutoffs(
+                    bound(py, &train_data),
+                    bound(py, &test_data),
+                    &params(),
+                    target_precision,
+                )
+                .unwrap();
+            };
+            assert_probs_close(
+                &cutoffs(1.0),
+                &std::collections::HashMap::from([(1, correct)]),
+            );
+            assert_probs_close(
+                &cutoffs(0.5),
+                &std::collections::HashMap::from([(1, incorrect)]),
+            );
+        });
+    }
+
+    #[test]
+    fn stub_declares_every_function_and_class() {
+        let stub = include_str!("../repoguess.pyi");
+        for line in include_str!("lib.rs").lines() {
+            let line = line.trim();
+            let declaration =
+                if let Some(rest) = line.strip_prefix("m.add_function(wrap_pyfunction!(") {
+                    format!("def {}(", rest.split(',').next().unwrap())
+                } else if let Some(rest) = line.strip_prefix("m.add_class::<") {
+                    format!("class {}", rest.split('>').next().unwrap())
+                } else {
+                    continue;
+                };
+            assert!(stub.contains(&declaration), "{} is missing", declaration);
+        }
+    }
+
+    #[test]
+    fn f_beta_extremes_are_f1_and_precision() {
+        let data = synthetic_data(1000, 400);
+        let (train_data, test_data) = data.split_at(300);
+        let params = Params {
+            probability_cutoff: 0.1,
+            ..params()
+        };
+        let info = evaluate_model_impl(
+            &train_data.iter().collect::<Vec<_>>(),
+            &test_data.iter().collect::<Vec<_>>(),
+            &ScoringFunction::new(0.0, 0.0, 0.0),
+            &params,
+        )
+        .unwrap();
+        assert!(info.correct > 0 && info.incorrect > 0 && info.no_value > 0);
+        let precision = (info.correct as f64) / ((info.correct + info.incorrect) as f64);
+        let recall =
+            (info.correct as f64) / ((info.correct + info.incorrect + info.no_value) as f64);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let f = |beta: f64| {
+                return f_beta(bound(py, train_data), bound(py, test_data), &params, beta).unwrap();
+            };
+            let f1 = 2.0 * precision * recall / (precision + recall);
+            assert!((f(1.0) - f1).abs() < 1e-12);
+            assert!((f(0.0) - precision).abs() < 1e-12);
+        });
+    }
+
+    #[test]
+    fn seeded_functions_repeat_for_the_same_seed() {
+        // xoshiro256** seeded through SplitMix64, as in other implementations
+        let mut rng = SeededRng::new(0);
+        let outputs = [rng.next_u64(), rng.next_u64(), rng.next_u64()];
+        assert_eq!(
+            outputs,
+            [0x99ec5f36cb75f2b4, 0xbf6e1f784956452a, 0x1a5f849d4933e6e0]
+        );
+        let data = clustered_data(12000, 60);
+        let (train_data, test_data) = data.split_at(45);
+        let scoring_function = ScoringFunction::new(1.0, 0.2, 0.0);
+        let params = Params {
+            probability_cutoff: 0.3,
+            ..params()
+        };
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let folds = |seed: u64| {
+                let folds =
+                    cross_validate(py, bound(py, &data), 4, seed, &scoring_function, &params);
+                return folds.unwrap().iter().map(summary).collect::<Vec<_>>();
+            };
+            assert_eq!(folds(1), folds(1));
+            assert_ne!(folds(1), folds(2));
+            let bagged = |seed: u64| {
+                return bagged_top_choice(
+                    py,
+                    &test_data[0],
+                    bound(py, train_data),
+                    &params,
+                    9,
+                    seed,
+                )
+                .unwrap();
+            };
+            assert_eq!(bagged(1), bagged(1));
+            let curve = |seed: u64| {
+                let curve = learning_curve(
+                    py,
+                    bound(py, train_data),
+                    bound(py, test_data),
+                    &scoring_function,
+                    &params,
+                    vec![0.3, 0.6],
+                    seed,
+                );
+                return curve
+                    .unwrap()
+                    .iter()
+                    .map(|(fraction, info)| (*fraction, summary(info)))
+                    .collect::<Vec<_>>();
+            };
+            assert_eq!(curve(1), curve(1));
+        });
+    }
+
+    #[test]
+    fn contribution_counts_skip_names_below_the_score_cutoff() {
+        let query = name(None, 1, Some(1900), &[1], 1, 0);
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(1), 2, Some(1990), &[2], 2, 2),
+            name(Some(2), 1, Some(1900), &[1], 1, 3),
+        ];
+        let params = Params {
+            score_cutoff: get_score_impl(&query, &train_data[1], &params()).unwrap(),
+            ..params()
+        };
+        let counts = with_names(&train_data, |_, train_data| {
+            return contribution_counts(&query, train_data, &params).unwrap();
+        });
+        assert_eq!(counts, std::collections::HashMap::from([(1, 1), (2, 1)]));
+    }
+
+    #[test]
+    fn early_top_choice_matches_exact() {
+        let data = synthetic_data(2000, 400);
+        let (train_data, queries) = data.split_at(360);
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        let variants = [
+            params(),
+            Params {
+                author_year_interaction: 0.5,
+                probability_cutoff: 0.1,
+                ..params()
+            },
+            Params {
+                below_cutoff_factor: 0.5,
+                unknown_bucket_score: Some(2.0),
+                ..params()
+            },
+            Params {
+                combiner: Combiner::LogAdditive,
+                year_curve: Some(vec![1.5, 1.2, 1.0]),
+                ..params()
+            },
+        ];
+        for params in variants.iter() {
+            for query in queries {
+                let early = get_top_choice_early_impl(query, &train_data, params).unwrap();
+                let exact = get_top_choice_impl(query, &train_data, params).unwrap();
+                assert_eq!(early.map(|choice| choice.0), exact.map(|choice| choice.0));
+                // Stopping early gives a lower bound on the probability
+                if let (Some((_, early)), Some((_, exact))) = (early, exact) {
+                    assert!(early <= exact + 1e-12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn names_without_a_collection_are_not_evidence() {
+        let query = name(None, 1, Some(1900), &[1], 1, 0);
+        let known = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(2), 2, Some(1950), &[2], 2, 2),
+        ];
+        let unknown = name(None, 1, Some(1900), &[1], 1, 3);
+        let train_data: Vec<&NameData> = known.iter().collect();
+        let with_unknown = vec![&known[0], &unknown, &known[1]];
+        let params = params();
+        assert_probs_close(
+            &get_probs_impl(&query, &with_unknown, &params).unwrap(),
+            &get_probs_impl(&query, &train_data, &params).unwrap(),
+        );
+        // Only the test name with a known collection is evaluated
+        let info = evaluate_model_impl(
+            &train_data,
+            &[&query, &unknown, &known[0]],
+            &ScoringFunction::new(1.0, 0.0, 0.0),
+            &params,
+        )
+        .unwrap();
+        assert_eq!(info.correct + info.incorrect + info.no_value, 1);
+    }
+
+    #[test]
+    fn nucleus_accuracy_grows_with_the_mass() {
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(1), 1, Some(1900), &[1], 1, 2),
+            name(Some(2), 2, Some(1900), &[2], 2, 3),
+        ];
+        // The second test name is predicted as collection 1
+        let test_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 10),
+            name(Some(2), 1, Some(1900), &[1, 2], 1, 11),
+        ];
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let accuracy = |test_data: &[NameData], p: f64| {
+                return nucleus_accuracy(
+                    bound(py, &train_data),
+                    bound(py, test_data),
+                    &params(),
+                    p,
+                )
+                .unwrap();
+            };
+            assert_eq!(accuracy(&test_data, 0.0), 0.5);
+            assert_eq!(accuracy(&test_data, 1.0), 1.0);
+            assert_eq!(accuracy(&[], 1.0), 0.0);
+        });
+    }
+
+    #[test]
+    fn author_agreement_softens_year_decay() {
+        let query = name(None, 1, Some(1900), &[1, 2, 3, 4], 1, 0);
+        let high_overlap = name(Some(1), 1, Some(1950), &[1, 2, 3, 4], 1, 1);
+        let low_overlap = name(Some(1), 1, Some(1950), &[1, 5, 6, 7], 1, 2);
+        let year_factor = |train_datum: &NameData, author_year_interaction: f64| {
+            let params = Params {
+                author_year_interaction,
+                ..params()
+            };
+            return score_factors(&pair_features(&query, train_datum), &params)[3];
+        };
+        let decay = 1.1f64.powi(-50);
+        // No interaction leaves the plain decay, whatever the overlap
+        assert!((year_factor(&high_overlap, 0.0) - decay).abs() < 1e-12);
+        assert!((year_factor(&low_overlap, 0.0) - decay).abs() < 1e-12);
+        // Full interaction and identical authors remove the decay entirely
+        assert!((year_factor(&high_overlap, 1.0) - 1.0).abs() < 1e-12);
+        assert!(year_factor(&low_overlap, 1.0) > decay);
+        assert!(year_factor(&low_overlap, 1.0) < year_factor(&high_overlap, 1.0));
+    }
+
+    #[test]
+    fn length_mismatch_penalty_shrinks_author_factor() {
+        let query = name(None, 1, None, &[1, 2], 1, 0);
+        let equal_length = name(Some(1), 1, None, &[1, 3], 1, 1);
+        let very_different = name(Some(1), 1, None, &[1, 3, 4, 5, 6, 7, 8, 9, 10, 11], 1, 2);
+        let author_factor = |train_datum: &NameData, length_mismatch_penalty: f64| {
+            let params = Params {
+                length_mismatch_penalty,
+                ..params()
+            };
+            return score_factors(&pair_features(&query, train_datum), &params)[2];
+        };
+        // No penalty keeps the full boost whatever the lengths
+        assert_eq!(author_factor(&equal_length, 0.0), 3.0);
+        assert_eq!(author_factor(&very_different, 0.0), 3.0);
+        assert_eq!(author_factor(&equal_length, 0.1), 3.0);
+        assert!((author_factor(&very_different, 0.1) - 3.0 * 0.9f64.powi(8)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sensitivity_needs_a_step_below_one() {
+        let data = synthetic_data(5000, 40);
+        let (train_data, test_data) = data.split_at(30);
+        let scoring_function = ScoringFunction::new(1.0, 0.0, 0.0);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let sensitivity_with = |relative_step: f64| {
+                return sensitivity(
+                    py,
+                    bound(py, train_data),
+                    bound(py, test_data),
+                    &scoring_function,
+                    &params(),
+                    relative_step,
+                );
+            };
+            let result = sensitivity_with(0.5).unwrap();
+            assert_eq!(result.len(), PARAM_NAMES.len());
+            assert!(result
+                .values()
+                .all(|value| value.is_finite() && *value >= 0.0));
+            // Only distance mode uses the year distance scale
+            assert_eq!(result["year_distance_scale"], 0.0);
+            assert!(sensitivity_with(1.0).is_err());
+            assert!(sensitivity_with(0.0).is_err());
+        });
+    }
+
+    #[test]
+    fn update_matches_full_recompute() {
+        let (query, train_data) = tie_fixture();
+        let (old_data, new_data) = train_data.split_at(2);
+        let params = params();
+        let old_names: Vec<&NameData> = old_data.iter().collect();
+        let all_names: Vec<&NameData> = train_data.iter().collect();
+        let raw_scores = get_raw_scores_impl(&query, &old_names, &params).unwrap();
+        with_names(new_data, |_, new_names| {
+            let updated = raw_scores
+                .update(&query, new_names.clone(), &params)
+                .unwrap();
+            let full = get_raw_scores_impl(&query, &all_names, &params).unwrap();
+            assert_eq!(updated.totals(), full.totals());
+            let other_params = Params {
+                author_boost: 5.0,
+                ..params.clone()
+            };
+            assert!(raw_scores.update(&query, new_names, &other_params).is_err());
+        });
+    }
+
+    #[test]
+    fn identical_scores_make_a_single_bin() {
+        let query = name(None, 1, None, &[1], 1, 0);
+        let same = [
+            name(Some(1), 1, None, &[1], 1, 1),
+            name(Some(2), 1, None, &[1], 1, 2),
+            name(Some(3), 1, None, &[1], 1, 3),
+        ];
+        let histogram = with_names(&same, |_, train_data| {
+            return score_histogram(&query, train_data, &params(), 4).unwrap();
+        });
+        assert_eq!(histogram, vec![(12.0, 12.0, 3)]);
+        // Scores of 12, 1 and 3
+        let spread = [
+            name(Some(1), 1, None, &[1], 1, 1),
+            name(Some(1), 2, None, &[2], 2, 2),
+            name(Some(2), 2, None, &[1], 2, 3),
+        ];
+        with_names(&spread, |py, train_data| {
+            let histogram = score_histogram(&query, train_data, &params(), 2).unwrap();
+            assert_eq!(histogram, vec![(1.0, 6.5, 2), (6.5, 12.0, 1)]);
+            assert!(score_histogram(&query, bound(py, &spread), &params(), 0).is_err());
+        });
+    }
+
+    #[test]
+    fn scores_are_finite_and_non_negative() {
+        let mut rng = SeededRng::new(6);
+        // A value between 0 and scale, in steps of a thousandth of it
+        let mut uniform = |scale: f64| (rng.below(1001) as f64) / 1000.0 * scale;
+        let mut names: Vec<NameData> = Vec::new();
+        let mut draw = SeededRng::new(7);
+        for name_id in 0..60 {
+            let num_authors = draw.below(25);
+            let authors = (0..num_authors).map(|_| draw.below(40) as i32).collect();
+            let num_citation_groups = draw.below(4);
+            let citation_groups = (0..num_citation_groups)
+                .map(|_| draw.below(5) as i32)
+                .collect();
+            let year = match draw.below(3) {
+                0 => None,
+                _ => Some(draw.below(8000) as i32 - 5000),
+            };
+            names.push(NameData::new(
+                Some(draw.below(3) as i32),
+                draw.below(3) as i32,
+                year,
+                authors,
+                CitationGroups::Multiple(citation_groups),
+                name_id,
+            ));
+        }
+        for round in 0..200 {
+            let params = Params {
+                country_boost: uniform(10.0),
+                cg_boost: uniform(10.0),
+                author_boost: uniform(10.0),
+                year_factor: 0.5 + uniform(3.0),
+                year_boost: uniform(10.0),
+                author_year_interaction: uniform(1.0),
+                length_mismatch_penalty: uniform(1.0),
+                author_overlap_exponent: 0.001 + uniform(3.0),
+                ordered_author_overlap: round % 2 == 0,
+                author_overlap_denominator: if round % 3 == 0 {
+                    AuthorOverlapDenominator::Query
+                } else {
+                    AuthorOverlapDenominator::Union
+                },
+                combiner: [
+                    Combiner::Multiplicative,
+                    Combiner::LogAdditive,
+                    Combiner::MaxFactor,
+                ][round % 3],
+                score_mode: if round % 5 == 0 {
+                    ScoreMode::Distance
+                } else {
+                    ScoreMode::Boost
+                },
+                mismatch_penalty: uniform(5.0),
+                year_distance_scale: uniform(1.0),
+                ..params()
+            };
+            for nam1 in names.iter().step_by(7) {
+                for nam2 in names.iter() {
+                    let score = get_score_impl(nam1, nam2, &params).unwrap();
+                    assert!(score.is_finite() && score >= 0.0, "{}", score);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pruned_training_set_keeps_the_score_info() {
+        let data = synthetic_data(3000, 250);
+        let (train_data, test_data) = data.split_at(200);
+        let params = Params {
+            probability_cutoff: 0.1,
+            ..params()
+        };
+        let scoring_function = ScoringFunction::new(0.0, 0.0, 0.0);
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let evaluate = |train_data: &[&NameData]| {
+            return evaluate_model_impl(train_data, &test_names, &scoring_function, &params)
+                .unwrap();
+        };
+        let full = evaluate(&train_data.iter().collect::<Vec<_>>());
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let pruned = prune_train(bound(py, train_data), bound(py, test_data), &params).unwrap();
+            assert!(pruned.len() < train_data.len());
+            let pruned = evaluate(&borrow_names(&pruned));
+            assert_eq!(
+                (pruned.correct, pruned.incorrect, pruned.no_value),
+                (full.correct, full.incorrect, full.no_value)
+            );
+        });
+    }
+
+    #[test]
+    fn citation_group_boost_follows_overlap() {
+        let with_groups = |citation_groups: &[i32], name_id: i32| {
+            return NameData::new(
+                Some(1),
+                1,
+                None,
+                vec![1],
+                CitationGroups::Multiple(citation_groups.to_vec()),
+                name_id,
+            );
+        };
+        let query = with_groups(&[1, 2], 0);
+        let citation_group_factor = |train_datum: &NameData| {
+            return score_factors(&pair_features(&query, train_datum), &params())[1];
+        };
+        assert_eq!(citation_group_factor(&with_groups(&[1, 2], 1)), 2.0);
+        // One shared group out of a union of three
+        let partial = citation_group_factor(&with_groups(&[2, 3], 2));
+        assert!((partial - 2.0f64.powf(1.0 / 3.0)).abs() < 1e-12);
+        assert_eq!(citation_group_factor(&with_groups(&[3, 4], 3)), 1.0);
+    }
+
+    #[test]
+    fn predictions_digest_depends_only_on_the_predictions() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        let (query, train_data) = tie_fixture();
+        let test_data = [query, name(Some(1), 1, None, &[3], 1, 5)];
+        let reversed = [test_data[1].clone(), test_data[0].clone()];
+        let best_match = Params {
+            tie_break: TieBreak::BestMatch,
+            ..params()
+        };
+        with_names(&train_data, |py, train_data| {
+            let digest = |test_data: &[NameData], params: &Params| {
+                return predictions_digest(train_data.clone(), bound(py, test_data), params)
+                    .unwrap();
+            };
+            assert_eq!(digest(&test_data, &params()), digest(&reversed, &params()));
+            assert_ne!(
+                digest(&test_data, &params()),
+                digest(&test_data, &best_match)
+            );
+        });
+    }
+
+    #[test]
+    fn negative_years_decay_with_the_absolute_difference() {
+        let score = |year1: i32, year2: i32| {
+            let query = name(None, 1, Some(year1), &[1], 1, 0);
+            let train_datum = name(Some(1), 1, Some(year2), &[2], 1, 1);
+            return get_score_impl(&query, &train_datum, &params()).unwrap();
+        };
+        let decay = score(1900, 1950) / score(1900, 1900);
+        assert!((decay - 1.1f64.powi(-50)).abs() < 1e-12);
+        assert_eq!(score(-100, -50), score(1900, 1950));
+        assert_eq!(score(-50, -100), score(1900, 1950));
+        assert_eq!(score(-25, 25), score(1900, 1950));
+    }
+
+    #[test]
+    fn zero_below_cutoff_factor_is_the_hard_cutoff() {
+        let data = synthetic_data(4000, 300);
+        let (query, train_data) = data.split_first().unwrap();
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        // A constant unknown bucket, so names below the cutoff cannot enter through it
+        let params = |below_cutoff_factor: f64| Params {
+            score_cutoff: 20.0,
+            below_cutoff_factor,
+            unknown_bucket_score: Some(5.0),
+            ..params()
+        };
+        let probs = |train_data: &[&NameData], below_cutoff_factor: f64| {
+            return get_probs_impl(query, train_data, &params(below_cutoff_factor)).unwrap();
+        };
+        let above_cutoff: Vec<&NameData> = train_data
+            .iter()
+            .copied()
+            .filter(|train_datum| get_score_impl(query, train_datum, &params(0.0)).unwrap() > 20.0)
+            .collect();
+        assert!(above_cutoff.len() < train_data.len());
+        assert_eq!(probs(&train_data, 0.0), probs(&above_cutoff, 0.0));
+        assert_ne!(probs(&train_data, 0.5), probs(&above_cutoff, 0.5));
+    }
+
+    #[test]
+    fn evaluate_detailed_records_every_test_name() {
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(1), 1, Some(1900), &[1], 1, 2),
+            name(Some(2), 2, Some(1900), &[2], 2, 3),
+        ];
+        let test_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 10),
+            name(Some(2), 1, Some(1900), &[1], 1, 11),
+            name(None, 1, Some(1900), &[1], 1, 12),
+        ];
+        let records = |params: &Params| {
+            return with_names(&test_data, |py, test_data| {
+                return evaluate_detailed(bound(py, &train_data), test_data, params)
+                    .unwrap()
+                    .into_iter()
+                    .map(|record| {
+                        assert!(record.top_prob > 0.5);
+                        return (
+                            record.name_id,
+                            record.predicted_collection,
+                            record.true_collection,
+                            record.is_correct,
+                        );
+                    })
+                    .collect::<Vec<_>>();
+            });
+        };
+        assert_eq!(
+            records(&params()),
+            [
+                (10, Some(1), Some(1), true),
+                (11, Some(1), Some(2), false),
+                (12, Some(1), None, false),
+            ]
+        );
+        let cautious = Params {
+            probability_cutoff: 0.99,
+            ..params()
+        };
+        assert_eq!(
+            records(&cautious),
+            [
+                (10, None, Some(1), false),
+                (11, None, Some(2), false),
+                (12, None, None, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_bucket_gets_highest_score_or_constant() {
+        let query = name(None, 1, None, &[1], 1, 0);
+        let train_data = [
+            name(Some(1), 1, None, &[1], 1, 1),
+            name(Some(1), 2, None, &[2], 2, 2),
+            name(Some(2), 2, None, &[1], 2, 3),
+        ];
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        let probs = |unknown_bucket_score: Option<f64>| {
+            let params = Params {
+                unknown_bucket_score,
+                ..params()
+            };
+            return get_probs_impl(&query, &train_data, &params).unwrap();
+        };
+        // Scores are 12 and 1 for collection 1 and 3 for collection 2
+        let highest_score = probs(None);
+        assert!((highest_score[&0] - 12.0 / 28.0).abs() < 1e-12);
+        assert!((highest_score[&1] - 13.0 / 28.0).abs() < 1e-12);
+        let constant = probs(Some(4.0));
+        assert!((constant[&0] - 4.0 / 20.0).abs() < 1e-12);
+        assert!((constant[&2] - 3.0 / 20.0).abs() < 1e-12);
+        // With a constant of 0 and nothing above the cutoff, everything is unknown
+        let params = Params {
+            unknown_bucket_score: Some(0.0),
+            score_cutoff: 100.0,
+            ..params()
+        };
+        let probs = get_probs_impl(&query, &train_data, &params).unwrap();
+        assert_eq!(probs, std::collections::HashMap::from([(0, 1.0)]));
+    }
+
+    #[test]
+    fn most_influential_is_the_best_match_of_the_prediction() {
+        let query = name(None, 1, Some(1900), &[1], 1, 0);
+        let train_data = [
+            name(Some(1), 1, Some(1910), &[1], 1, 1),
+            name(Some(1), 1, Some(1900), &[1], 1, 2),
+            name(Some(2), 2, Some(1900), &[2], 2, 3),
+        ];
+        let influential = |params: &Params| {
+            return with_names(&train_data, |_, train_data| {
+                return most_influential(&query, train_data, params).unwrap();
+            });
+        };
+        let best_score = get_score_impl(&query, &train_data[1], &params()).unwrap();
+        assert_eq!(influential(&params()), Some((2, best_score)));
+        let cautious = Params {
+            probability_cutoff: 0.99,
+            ..params()
+        };
+        assert_eq!(influential(&cautious), None);
+    }
+
+    #[test]
+    fn probabilities_sum_to_one_under_each_normalization() {
+        let query = name(None, 1, None, &[1], 1, 0);
+        let train_data = [
+            name(Some(1), 1, None, &[1], 1, 1),
+            name(Some(1), 2, None, &[2], 2, 2),
+            name(Some(2), 2, None, &[1], 2, 3),
+        ];
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        let probs = |normalize_with_unknown: bool| {
+            let params = Params {
+                normalize_with_unknown,
+                ..params()
+            };
+            return get_probs_impl(&query, &train_data, &params).unwrap();
+        };
+        // Collection 1 scores 13, collection 2 scores 3 and the unknown bucket 12
+        let with_unknown = probs(true);
+        assert!((with_unknown.values().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!((with_unknown[&1] - 13.0 / 28.0).abs() < 1e-12);
+        let known_only = probs(false);
+        assert!((known_only[&1] + known_only[&2] - 1.0).abs() < 1e-12);
+        assert!((known_only[&1] - 13.0 / 16.0).abs() < 1e-12);
+        assert_eq!(known_only[&0], with_unknown[&0]);
+    }
+
+    #[test]
+    fn cached_optimizer_matches_naive_optimizer() {
+        let data = clustered_data(6000, 60);
+        let (train_data, test_data) = data.split_at(45);
+        let scoring_function = ScoringFunction::new(1.0, 0.2, 0.0);
+        let params = Params {
+            probability_cutoff: 0.2,
+            ..params()
+        };
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let optimize = |cached: bool, step: f64| {
+                let (train_data, test_data) = (bound(py, train_data), bound(py, test_data));
+                if cached {
+                    return optimize_params_cached(
+                        train_data,
+                        test_data,
+                        &scoring_function,
+                        &params,
+                        step,
+                        3,
+                        Vec::new(),
+                    );
+                }
+                return optimize_params(
+                    train_data,
+                    test_data,
+                    &scoring_function,
+                    &params,
+                    step,
+                    3,
+                    Vec::new(),
+                );
+            };
+            let naive = optimize(false, 0.2).unwrap();
+            let cached = optimize(true, 0.2).unwrap();
+            assert!(naive != params);
+            assert!(naive == cached);
+            let train_names: Vec<&NameData> = train_data.iter().collect();
+            let test_names: Vec<&NameData> = test_data.iter().collect();
+            let evaluate = |params: &Params| {
+                let info =
+                    evaluate_model_impl(&train_names, &test_names, &scoring_function, params);
+                return summary(&info.unwrap());
+            };
+            assert_eq!(evaluate(&naive), evaluate(&cached));
+            assert!(optimize(false, 1.0).is_err());
+            assert!(optimize(true, 1.0).is_err());
+        });
+    }
+
+    #[test]
+    fn author_overlap_is_the_shared_proportion() {
+        let cases: [(&[i32], &[i32], f64); 5] = [
+            (&[1, 2, 3], &[2, 3, 4], 0.5),
+            (&[1, 2], &[2, 1], 1.0),
+            (&[1, 2], &[1, 2], 1.0),
+            (&[], &[], 1.0),
+            (&[1], &[], 0.0),
+        ];
+        for (authors1, authors2, expected) in cases {
+            let nam1 = name(Some(1), 1, None, authors1, 1, 1);
+            let nam2 = name(Some(2), 1, None, authors2, 1, 2);
+            assert_eq!(author_overlap(&nam1, &nam2, None), expected);
+            assert_eq!(author_overlap(&nam2, &nam1, None), expected);
+        }
+    }
+
+    #[test]
+    fn per_collection_results_add_up_to_the_total() {
+        let mut data = clustered_data(9000, 60);
+        data[50].collection = None;
+        let (train_data, test_data) = data.split_at(40);
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        let params = Params {
+            probability_cutoff: 0.3,
+            ..params()
+        };
+        let (overall, by_collection) = with_names(train_data, |py, train_data| {
+            return evaluate_full(train_data, bound(py, test_data), &scoring_function, &params)
+                .unwrap();
+        });
+        assert!(overall.correct > 0 && overall.incorrect > 0 && overall.no_value > 0);
+        assert!(by_collection.len() > 1);
+        let mut sum = (0.0, 0, 0, 0);
+        for info in by_collection.values() {
+            sum.0 += info.score;
+            sum.1 += info.correct;
+            sum.2 += info.incorrect;
+            sum.3 += info.no_value;
+        }
+        assert!((sum.0 - overall.score).abs() < 1e-9);
+        assert_eq!(
+            (sum.1, sum.2, sum.3),
+            (overall.correct, overall.incorrect, overall.no_value)
+        );
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let expected = evaluate_model_impl(&train_names, &test_names, &scoring_function, &params);
+        assert_eq!(summary(&overall), summary(&expected.unwrap()));
+    }
+
+    #[test]
+    fn field_match_reports_which_fields_agree() {
+        let nam1 = name(Some(1), 1, Some(1900), &[1, 2], 1, 1);
+        let nam2 = name(Some(2), 1, Some(1950), &[2, 3], 2, 2);
+        let matches = field_match(&nam1, &nam2);
+        assert!(matches.country_match);
+        assert!(!matches.cg_match);
+        assert!(!matches.authors_equal);
+        assert_eq!(matches.shared_author_count, 1);
+        assert_eq!(matches.year_difference, Some(50));
+        let undated = name(Some(2), 2, None, &[1, 2], 1, 3);
+        let matches = field_match(&nam1, &undated);
+        assert!(!matches.country_match);
+        assert!(matches.cg_match);
+        assert!(matches.authors_equal);
+        assert_eq!(matches.year_difference, None);
+        assert_eq!(
+            matches.__repr__().unwrap(),
+            "FieldMatch(country_match=False, cg_match=True, authors_equal=True, \
+             shared_author_count=2, year_difference=None)"
+        );
+    }
+
+    #[test]
+    fn weights_scale_each_test_name() {
+        let data = clustered_data(11000, 60);
+        let (train_data, test_data) = data.split_at(40);
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        let params = Params {
+            probability_cutoff: 0.3,
+            ..params()
+        };
+        with_names(train_data, |py, train_data| {
+            let evaluate = |test_data: &[NameData], weights: Option<Vec<f64>>| {
+                return evaluate_model(
+                    train_data.clone(),
+                    bound(py, test_data),
+                    &scoring_function,
+                    &params,
+                    weights,
+                    None,
+                    false,
+                    false,
+                    false,
+                );
+            };
+            let unweighted = evaluate(test_data, None).unwrap();
+            let second_half = evaluate(&test_data[10..], None).unwrap();
+            assert!(second_half.correct > 0 && second_half.incorrect > 0);
+            let weights: Vec<f64> = (0..20)
+                .map(|index| if index < 10 { 0.0 } else { 3.0 })
+                .collect();
+            let weighted = evaluate(test_data, Some(weights)).unwrap();
+            assert_eq!(
+                (weighted.correct, weighted.incorrect, weighted.no_value),
+                (
+                    unweighted.correct,
+                    unweighted.incorrect,
+                    unweighted.no_value
+                )
+            );
+            assert!((weighted.score - 3.0 * second_half.score).abs() < 1e-9);
+            assert_eq!(weighted.weighted_correct, 3.0 * second_half.correct as f64);
+            assert_eq!(
+                weighted.weighted_incorrect,
+                3.0 * second_half.incorrect as f64
+            );
+            assert_eq!(
+                weighted.weighted_no_value,
+                3.0 * second_half.no_value as f64
+            );
+            assert!(evaluate(test_data, Some(vec![1.0; 19])).is_err());
+            assert!(evaluate(test_data, Some(vec![-1.0; 20])).is_err());
+        });
+    }
+
+    #[test]
+    fn misclassification_report_describes_each_confusion() {
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(1), 1, Some(1900), &[1], 1, 2),
+            name(Some(2), 2, Some(1900), &[2], 2, 3),
+        ];
+        // The last two are predicted as collection 1
+        let test_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 10),
+            name(Some(2), 1, Some(1900), &[1], 1, 11),
+            name(Some(2), 1, Some(1910), &[1], 1, 12),
+        ];
+        let report = with_names(&test_data, |py, test_data| {
+            return misclassification_report(bound(py, &train_data), test_data, &params()).unwrap();
+        });
+        assert_eq!(report.len(), 1);
+        let confusion = &report[0];
+        assert_eq!(
+            (
+                confusion.true_collection,
+                confusion.predicted_collection,
+                confusion.count,
+                confusion.explained
+            ),
+            (2, 1, 2, 2)
+        );
+        assert_eq!(confusion.mean_author_overlap, Some(1.0));
+        assert_eq!(confusion.mean_year_difference, Some(5.0));
+        assert_eq!(confusion.country_match_rate, Some(1.0));
+        assert_eq!(confusion.cg_match_rate, Some(1.0));
+    }
+
+    #[test]
+    fn coordinate_search_keeps_pinned_parameters() {
+        let pinned = vec!["year_factor".to_string()];
+        // Rewards raising every parameter, so anything not pinned moves
+        let result = coordinate_search(&params(), 0.1, 3, &pinned, |params| {
+            return Ok(params.year_factor + params.author_boost);
+        })
+        .unwrap();
+        assert_eq!(result.year_factor, params().year_factor);
+        assert!(result.author_boost > params().author_boost);
+    }
+
+    #[test]
+    fn allowed_collections_restrict_the_candidates() {
+        let (query, train_data) = tie_fixture();
+        let params = params();
+        with_names(&train_data, |_py, bound_train| {
+            for allowed in [1, 2] {
+                let probs = get_probs(
+                    &query,
+                    bound_train.clone(),
+                    &params,
+                    Some(vec![allowed]),
+                    None,
+                    None,
+                    false,
+                )
+                .unwrap();
+                let kept: Vec<&NameData> = train_data
+                    .iter()
+                    .filter(|nam| nam.collection == Some(allowed))
+                    .collect();
+                assert_eq!(probs, get_probs_impl(&query, &kept, &params).unwrap());
+                assert!(probs.keys().all(|key| *key == 0 || *key == allowed));
+                let top_choice =
+                    get_top_choice(&query, bound_train.clone(), &params, Some(vec![allowed]));
+                assert_eq!(top_choice.unwrap().unwrap().0, allowed);
+            }
+            let top_choice = get_top_choice(&query, bound_train.clone(), &params, Some(vec![7]));
+            // Nothing is left to compare against, so the name is unknown
+            assert_eq!(top_choice.unwrap(), Some((0, 1.0)));
+        });
+    }
+
+    #[test]
+    fn mean_with_support_needs_min_support_matches() {
+        let params = Params {
+            aggregation: Aggregation::MeanWithSupport,
+            min_support: 2,
+            below_cutoff_factor: 0.5,
+            unknown_bucket_score: Some(0.0),
+            ..params()
+        };
+        let mut raw_scores = RawScores::new(&params);
+        raw_scores.add_all(vec![(1, 4.0), (1, 2.0), (2, 6.0), (1, 0.1)], &params);
+        let totals = raw_scores.totals();
+        // The softened score below the cutoff counts towards neither the mean nor support
+        assert_eq!(totals[&1], 3.0);
+        assert!(!totals.contains_key(&2));
+        assert_eq!(raw_scores.collections[&1].best, 4.0);
+        let sum_params = Params {
+            aggregation: Aggregation::Sum,
+            ..params
+        };
+        let mut raw_scores = RawScores::new(&sum_params);
+        raw_scores.add_all(vec![(1, 4.0), (1, 2.0), (1, 0.1)], &sum_params);
+        assert_eq!(raw_scores.totals()[&1], 6.05);
+    }
+
+    #[test]
+    fn ordered_author_overlap_counts_authors_in_order() {
+        // Author overlap enters get_score through the interaction with the year
+        let unordered = Params {
+            author_year_interaction: 0.5,
+            ..params()
+        };
+        let ordered = Params {
+            ordered_author_overlap: true,
+            ..unordered.clone()
+        };
+        let cases: [(&[i32], &[i32], f64, f64); 4] = [
+            (&[1, 2, 3], &[3, 2, 1], 1.0, 1.0 / 3.0),
+            (&[1, 2, 3], &[1, 3, 4], 0.5, 0.5),
+            (&[1, 2, 3], &[2, 1, 3], 1.0, 2.0 / 3.0),
+            (&[1, 2], &[3], 0.0, 0.0),
+        ];
+        for (authors1, authors2, unordered_overlap, ordered_overlap) in cases {
+            let nam1 = name(Some(1), 1, Some(1900), authors1, 1, 1);
+            let nam2 = name(Some(2), 2, Some(1910), authors2, 2, 2);
+            assert_eq!(
+                author_overlap(&nam1, &nam2, Some(&unordered)),
+                unordered_overlap
+            );
+            assert_eq!(
+                author_overlap(&nam1, &nam2, Some(&ordered)),
+                ordered_overlap
+            );
+            let unordered_score = get_score_impl(&nam1, &nam2, &unordered).unwrap();
+            let ordered_score = get_score_impl(&nam1, &nam2, &ordered).unwrap();
+            assert_eq!(
+                ordered_score == unordered_score,
+                ordered_overlap == unordered_overlap
+            );
+        }
+    }
+
+    #[test]
+    fn best_cutoff_beats_every_other_cutoff() {
+        let mut data = clustered_data(12000, 70);
+        data[60].collection = None;
+        let (train_data, test_data) = data.split_at(40);
+        let scoring_function = ScoringFunction::new(2.0, 0.0, 0.0);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let (cutoff, info) = with_names(train_data, |py, train_data| {
+            return best_cutoff(
+                train_data,
+                bound(py, test_data),
+                &scoring_function,
+                &params(),
+            )
+            .unwrap();
+        });
+        let evaluate = |probability_cutoff: f64| {
+            let params = Params {
+                probability_cutoff,
+                ..params()
+            };
+            return evaluate_model_impl(&train_names, &test_names, &scoring_function, &params)
+                .unwrap();
+        };
+        assert!(cutoff > 0.0);
+        assert_eq!(summary(&info), summary(&evaluate(cutoff)));
+        for step in 0..=100 {
+            assert!(evaluate(step as f64 / 100.0).score <= info.score + 1e-9);
+        }
+    }
+
+    #[test]
+    fn prediction_stability_notices_a_borderline_prediction() {
+        let query = name(None, 1, Some(1900), &[1], 1, 0);
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(1), 1, Some(1900), &[1], 1, 2),
+            name(Some(2), 2, Some(1900), &[2], 2, 3),
+        ];
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let (_, probability) = get_top_choice_impl(&query, &train_names, &params())
+            .unwrap()
+            .unwrap();
+        let borderline = Params {
+            probability_cutoff: probability - 1e-6,
+            ..params()
+        };
+        let [stable, unstable, too_large] = with_names(&train_data, |_, train_data| {
+            return [
+                prediction_stability(&query, train_data.clone(), &params(), 0.1),
+                prediction_stability(&query, train_data.clone(), &borderline, 0.1),
+                prediction_stability(&query, train_data, &params(), 1.0),
+            ];
+        });
+        assert!(stable.unwrap());
+        assert!(!unstable.unwrap());
+        assert!(too_large.is_err());
+    }
+
+    #[test]
+    fn cached_author_hash_agrees_with_comparing_lists() {
+        let lists: [&[i32]; 5] = [&[1, 2, 3], &[1, 2, 3], &[1, 2, 4], &[1, 2], &[]];
+        for (index1, authors1) in lists.iter().enumerate() {
+            for (index2, authors2) in lists.iter().enumerate() {
+                let nam1 = name(Some(1), 1, None, authors1, 1, index1 as i32);
+                let nam2 = name(Some(2), 1, None, authors2, 1, index2 as i32 + 10);
+                assert_eq!(nam1.same_authors(&nam2), authors1 == authors2);
+            }
+        }
+        pyo3::prepare_freethreaded_python();
+        let timings = Python::with_gil(|py| benchmark_scoring(py, &params(), 10, 5).unwrap());
+        assert!(timings["same_length_authors"] >= 0.0);
+    }
+
+    #[test]
+    fn min_unknown_prob_scales_the_other_probabilities() {
+        let (query, train_data) = tie_fixture();
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        let probs = get_probs_impl(&query, &train_data, &params()).unwrap();
+        assert!(probs[&0] < 0.5);
+        let floored = Params {
+            min_unknown_prob: 0.5,
+            ..params()
+        };
+        let floored_probs = get_probs_impl(&query, &train_data, &floored).unwrap();
+        assert_eq!(floored_probs[&0], 0.5);
+        for collection in [1, 2] {
+            let expected = probs[&collection] * 0.5 / (1.0 - probs[&0]);
+            assert!((floored_probs[&collection] - expected).abs() < 1e-12);
+        }
+        assert!((floored_probs.values().sum::<f64>() - 1.0).abs() < 1e-12);
+        let low_floor = Params {
+            min_unknown_prob: probs[&0] / 2.0,
+            ..params()
+        };
+        assert_probs_close(
+            &get_probs_impl(&query, &train_data, &low_floor).unwrap(),
+            &probs,
+        );
+        let without_unknown = Params {
+            normalize_with_unknown: false,
+            ..params()
+        };
+        let floored_without_unknown = Params {
+            min_unknown_prob: 0.5,
+            ..without_unknown.clone()
+        };
+        assert_probs_close(
+            &get_probs_impl(&query, &train_data, &floored_without_unknown).unwrap(),
+            &get_probs_impl(&query, &train_data, &without_unknown).unwrap(),
+        );
+    }
+
+    #[test]
+    fn label_arrays_use_the_abstain_label() {
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(1), 1, Some(1900), &[1], 1, 2),
+            name(Some(2), 2, Some(1900), &[2], 2, 3),
+        ];
+        let test_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 10),
+            name(None, 1, Some(1900), &[1], 1, 11),
+            name(Some(2), 1, Some(1900), &[1], 1, 12),
+        ];
+        let cautious = Params {
+            probability_cutoff: 0.99,
+            ..params()
+        };
+        let (labels, abstained) = with_names(&test_data, |py, test_data| {
+            return (
+                to_label_arrays(bound(py, &train_data), test_data.clone(), &params(), -1),
+                to_label_arrays(bound(py, &train_data), test_data, &cautious, 0),
+            );
+        });
+        assert_eq!(labels.unwrap(), (vec![1, 2], vec![1, 1]));
+        assert_eq!(abstained.unwrap(), (vec![1, 2], vec![0, 0]));
+    }
+
+    #[test]
+    fn precomputed_evaluator_matches_evaluate_model() {
+        let mut data = clustered_data(7000, 80);
+        // Unlabelled names are skipped both as training names and as test names
+        for index in [3, 20, 50, 61, 70] {
+            data[index].collection = None;
+        }
+        let (train_data, test_data) = data.split_at(60);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let evaluator = PrecomputedEvaluator::from_names(&train_names, &test_names);
+        let scoring_function = ScoringFunction::new(1.0, 0.2, 0.0);
+        let variants = [
+            params(),
+            Params {
+                exclude_same_citation_group: true,
+                ..params()
+            },
+            Params {
+                max_contributions: 3,
+                probability_cutoff: 0.3,
+                ..params()
+            },
+            Params {
+                tie_break: TieBreak::BestMatch,
+                below_cutoff_factor: 0.5,
+                score_cutoff: 5.0,
+                ..params()
+            },
+        ];
+        for params in variants {
+            let expected =
+                evaluate_model_impl(&train_names, &test_names, &scoring_function, &params);
+            assert_eq!(
+                summary(&evaluator.evaluate(&scoring_function, &params)),
+                summary(&expected.unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn reject_collection_counts_toward_the_unknown_bucket() {
+        let (query, train_data) = tie_fixture();
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        let probs = get_probs_impl(&query, &train_data, &params()).unwrap();
+        let rejecting = Params {
+            reject_collection: Some(1),
+            ..params()
+        };
+        let rejected_probs = get_probs_impl(&query, &train_data, &rejecting).unwrap();
+        assert!(!rejected_probs.contains_key(&1));
+        assert!(rejected_probs[&0] > probs[&0]);
+        assert!((rejected_probs.values().sum::<f64>() - 1.0).abs() < 1e-12);
+        let top_choice = get_top_choice_impl(&query, &train_data, &rejecting).unwrap();
+        assert_ne!(top_choice.unwrap().0, 1);
+        let rejecting_absent = Params {
+            reject_collection: Some(7),
+            ..params()
+        };
+        assert_probs_close(
+            &get_probs_impl(&query, &train_data, &rejecting_absent).unwrap(),
+            &probs,
+        );
+    }
+
+    #[test]
+    fn score_stats_summarize_every_score() {
+        let query = name(None, 1, Some(1900), &[1], 1, 0);
+        let train_data = synthetic_data(16000, 5);
+        let mut scores: Vec<f64> = train_data
+            .iter()
+            .map(|train_datum| get_score_impl(&query, train_datum, &params()).unwrap())
+            .collect();
+        scores.sort_by(|a, b| a.total_cmp(b));
+        let (stats, empty) = with_names(&train_data, |_, train_data| {
+            return (
+                score_stats(&query, train_data, &params()).unwrap(),
+                score_stats(&query, Vec::new(), &params()).is_err(),
+            );
+        });
+        assert_eq!(stats.count, 5);
+        assert_eq!(
+            (stats.min, stats.max, stats.median),
+            (scores[0], scores[4], scores[2])
+        );
+        assert!((stats.mean - scores.iter().sum::<f64>() / 5.0).abs() < 1e-12);
+        assert!((stats.p90 - (scores[3] + 0.6 * (scores[4] - scores[3]))).abs() < 1e-12);
+        assert!(empty);
+    }
+
+    #[test]
+    fn frequencies_count_each_name_once() {
+        let train_data = [
+            name(Some(1), 1, None, &[1, 2, 1], 1, 1),
+            name(Some(1), 1, None, &[2], 1, 2),
+            name(Some(2), 1, None, &[], 3, 3),
+        ];
+        let (authors, citation_groups) = with_names(&train_data, |_py, train_data| {
+            return (
+                compute_author_frequencies(train_data.clone()),
+                compute_citation_group_sizes(train_data),
+            );
+        });
+        assert_eq!(authors, std::collections::HashMap::from([(1, 1), (2, 2)]));
+        assert_eq!(
+            citation_groups,
+            std::collections::HashMap::from([(1, 2), (3, 1)])
+        );
+    }
+
+    #[test]
+    fn grid_search_keeps_pinned_parameters() {
+        let (query, mut train_data) = tie_fixture();
+        train_data.push(query);
+        let grid: std::collections::HashMap<String, Vec<f64>> = [
+            ("author_boost".to_string(), vec![1.0, 5.0]),
+            ("year_factor".to_string(), vec![1.5, 3.0]),
+        ]
+        .into_iter()
+        .collect();
+        let scoring_function = ScoringFunction::new(1.0, 0.2, 0.5);
+        let result = with_names(&train_data, |py, names| {
+            return grid_search(
+                py,
+                names.clone(),
+                names,
+                &scoring_function,
+                &params(),
+                grid,
+                false,
+                vec!["year_factor".to_string()],
+            )
+            .unwrap();
+        });
+        assert_eq!(result.num_combinations, 2);
+        assert_eq!(
+            result.best_params.unwrap().year_factor,
+            params().year_factor
+        );
+    }
+
+    #[test]
+    fn cross_agreement_averages_both_directions() {
+        let data = clustered_data(13000, 60);
+        let (subset_a, subset_b) = data.split_at(25);
+        let names_a: Vec<&NameData> = subset_a.iter().collect();
+        let names_b: Vec<&NameData> = subset_b.iter().collect();
+        let scoring_function = ScoringFunction::new(0.0, 0.0, 0.0);
+        let rate = |train_data: &[&NameData], test_data: &[&NameData]| {
+            let info =
+                evaluate_model_impl(train_data, test_data, &scoring_function, &params()).unwrap();
+            return info.correct as f64 / test_data.len() as f64;
+        };
+        let expected = (rate(&names_a, &names_b) + rate(&names_b, &names_a)) / 2.0;
+        let unlabelled: Vec<NameData> = subset_a
+            .iter()
+            .map(|nam| NameData {
+                collection: None,
+                ..nam.clone()
+            })
+            .collect();
+        with_names(subset_a, |py, bound_a| {
+            let agreement = cross_agreement(bound_a, bound(py, subset_b), &params());
+            assert!((agreement.unwrap() - expected).abs() < 1e-12);
+            let agreement = cross_agreement(bound(py, &unlabelled), bound(py, &[]), &params());
+            assert!(agreement.is_err());
+        });
+    }
+
+    #[test]
+    fn rounded_probabilities_keep_their_sum() {
+        let thirds =
+            std::collections::HashMap::from([(1, 1.0 / 3.0), (2, 1.0 / 3.0), (3, 1.0 / 3.0)]);
+        assert_eq!(
+            round_probs(&thirds, 2),
+            std::collections::HashMap::from([(1, 0.34), (2, 0.33), (3, 0.33)])
+        );
+        let uneven = std::collections::HashMap::from([(1, 0.125), (2, 0.875)]);
+        assert_eq!(
+            round_probs(&uneven, 1),
+            std::collections::HashMap::from([(1, 0.1), (2, 0.9)])
+        );
+        let (query, train_data) = tie_fixture();
+        with_names(&train_data, |_py, train_data| {
+            let round = |round_to: i32| {
+                return get_probs(
+                    &query,
+                    train_data.clone(),
+                    &params(),
+                    None,
+                    Some(round_to),
+                    None,
+                    false,
+                );
+            };
+            let probs = round(3).unwrap();
+            assert!((probs.values().sum::<f64>() - 1.0).abs() < 1e-12);
+            assert!(probs
+                .values()
+                .all(|prob| (prob * 1000.0 - (prob * 1000.0).round()).abs() < 1e-9));
+            assert!(round(-1).is_err());
+            assert!(round(16).is_err());
+        });
+    }
+
+    #[test]
+    fn score_matrix_rows_normalize_to_the_probabilities() {
+        let train_data = synthetic_data(17000, 40);
+        let queries = synthetic_data(17100, 5);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let matrix = with_names(&queries, |py, queries| {
+            return score_matrix_by_collection(py, queries, bound(py, &train_data), &params())
+                .unwrap();
+        });
+        assert_eq!(matrix.len(), queries.len());
+        for (query, scores) in queries.iter().zip(matrix.iter()) {
+            let total: f64 = scores.values().sum();
+            let normalized = scores
+                .iter()
+                .map(|(collection, score)| (*collection, score / total))
+                .collect();
+            assert_probs_close(
+                &normalized,
+                &get_probs_impl(query, &train_names, &params()).unwrap(),
+            );
+        }
+    }
+
+    #[test]
+    fn near_duplicates_are_the_pairs_above_the_threshold() {
+        let train_data = clustered_data(14000, 30);
+        let mut expected: Vec<(i32, i32, f64)> = Vec::new();
+        for (index, nam1) in train_data.iter().enumerate() {
+            for nam2 in &train_data[index + 1..] {
+                let score = get_score_impl(nam1, nam2, &params()).unwrap();
+                if score > 5.0 {
+                    expected.push((nam1.name_id, nam2.name_id, score));
+                }
+            }
+        }
+        expected.sort_by(|a, b| b.2.total_cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+        assert!(expected.len() > 1 && expected.len() < 100);
+        let pairs = with_names(&train_data, |py, train_data| {
+            return find_near_duplicates(py, train_data, &params(), 5.0).unwrap();
+        });
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn combiners_combine_the_same_factors() {
+        let with_combiner = |combiner: Combiner| {
+            return Params {
+                combiner,
+                ..params()
+            };
+        };
+        let nam1 = name(Some(1), 1, Some(1900), &[1, 2], 1, 1);
+        let matching = name(Some(2), 1, Some(1900), &[1, 2], 1, 2);
+        // Only the year differs, by enough that its log is below -1
+        let distant = name(Some(2), 2, Some(1920), &[3], 2, 3);
+        let score = |other: &NameData, combiner: Combiner| {
+            return get_score_impl(&nam1, other, &with_combiner(combiner)).unwrap();
+        };
+        assert_eq!(score(&matching, Combiner::Multiplicative), 12.0);
+        let log_additive = 1.0 + 2f64.ln() + 2f64.ln() + 3f64.ln();
+        assert!((score(&matching, Combiner::LogAdditive) - log_additive).abs() < 1e-12);
+        assert_eq!(score(&matching, Combiner::MaxFactor), 3.0);
+        assert_eq!(
+            score(&distant, Combiner::Multiplicative),
+            1.1f64.powf(-20.0)
+        );
+        assert_eq!(score(&distant, Combiner::LogAdditive), 0.0);
+        assert_eq!(score(&distant, Combiner::MaxFactor), 1.0);
+    }
+
+    #[test]
+    fn forward_only_trains_on_earlier_names() {
+        let data = clustered_data(15000, 60);
+        assert!(data.iter().any(|nam| nam.year.is_none()));
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        let mut expected = (0.0, 0, 0, 0);
+        for nam in data.iter().filter(|nam| nam.year.is_some()) {
+            let train_data: Vec<&NameData> = data
+                .iter()
+                .filter(|other| other.year.is_some() && other.year <= nam.year)
+                .filter(|other| other.name_id != nam.name_id)
+                .collect();
+            let info =
+                evaluate_model_impl(&train_data, &[nam], &scoring_function, &params()).unwrap();
+            expected.0 += info.score;
+            expected.1 += info.correct;
+            expected.2 += info.incorrect;
+            expected.3 += info.no_value;
+        }
+        let info = with_names(&data, |_py, data| {
+            return evaluate_forward_only(data, &scoring_function, &params()).unwrap();
+        });
+        assert!((info.score - expected.0).abs() < 1e-9);
+        assert_eq!(
+            (info.correct, info.incorrect, info.no_value),
+            (expected.1, expected.2, expected.3)
+        );
+        assert!(info.correct > 0 && info.incorrect > 0);
+    }
+
+    #[test]
+    fn year_curve_can_reproduce_year_factor() {
+        let data = synthetic_data(15000, 40);
+        let parametric = Params {
+            author_year_interaction: 0.5,
+            ..params()
+        };
+        let curve: Vec<f64> = (0..=200)
+            .map(|difference| 1.1f64.powf(-(difference as f64)))
+            .collect();
+        let with_curve = Params {
+            year_curve: Some(curve),
+            ..parametric.clone()
+        };
+        for nam1 in data.iter() {
+            for nam2 in data.iter() {
+                assert_eq!(
+                    get_score_impl(nam1, nam2, &with_curve).unwrap(),
+                    get_score_impl(nam1, nam2, &parametric).unwrap()
+                );
+            }
+        }
+        // Differences past the end of the curve use its last entry
+        let short_curve = Params {
+            year_curve: Some(vec![1.0, 0.5]),
+            ..params()
+        };
+        let query = name(None, 2, Some(1900), &[1], 2, 0);
+        let score = |year: i32| {
+            let train_datum = name(Some(1), 1, Some(year), &[2], 1, 1);
+            return get_score_impl(&query, &train_datum, &short_curve).unwrap();
+        };
+        assert_eq!((score(1900), score(1901), score(1950)), (1.0, 0.5, 0.5));
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            use pyo3::types::IntoPyDict;
+            let make_params = py.get_type_bound::<Params>();
+            let kwargs = [("year_curve", Vec::<f64>::new())].into_py_dict_bound(py);
+            let empty = make_params.call((2.0, 2.0, 3.0, 1.1, 1.0, 0.5, 0.0), Some(&kwargs));
+            assert!(empty.is_err());
+        });
+    }
+
+    #[test]
+    fn min_margin_abstains_on_close_calls() {
+        let query = name(None, 1, None, &[1], 1, 0);
+        let train_data = [
+            name(Some(1), 1, None, &[1], 1, 1),
+            name(Some(1), 2, None, &[2], 2, 2),
+            name(Some(2), 2, None, &[1], 2, 3),
+        ];
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        let probs = get_probs_impl(&query, &train_data, &params()).unwrap();
+        let (best, probability) = best_choice(&probs).unwrap();
+        let margin = probability - runner_up_probability(&probs, best);
+        assert!(margin > 0.0);
+        let with_margin = |min_margin: f64| {
+            return Params {
+                min_margin,
+                ..params()
+            };
+        };
+        let top_choice = get_top_choice_impl(&query, &train_data, &with_margin(margin * 0.9));
+        assert!(same_choice(top_choice.unwrap(), Some((best, probability))));
+        let close = with_margin(margin * 1.1);
+        assert_eq!(
+            get_top_choice_impl(&query, &train_data, &close).unwrap(),
+            None
+        );
+        match top_choice_explained_impl(&query, &train_data, &close).unwrap() {
+            PredictionOutcome::Ambiguous {
+                collection,
+                margin: explained_margin,
+                ..
+            } => {
+                assert_eq!(collection, best);
+                assert!((explained_margin - margin).abs() < 1e-12);
+            }
+            _ => panic!("expected an ambiguous prediction"),
+        }
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn predictions_parquet_holds_every_test_name() {
+        let mut data = clustered_data(39000, 40);
+        data[35].collection = None;
+        let (train_data, test_data) = data.split_at(30);
+        let params = Params {
+            probability_cutoff: 0.4,
+            ..params()
+        };
+        let path = std::env::temp_dir().join(format!(
+            "repoguess-predictions-{}.parquet",
+            std::process::id()
+        ));
+        with_names(train_data, |py, train_data| {
+            let test_data = bound(py, test_data);
+            write_predictions_parquet(train_data.clone(), test_data.clone(), &params, path.clone())
+                .unwrap();
+            let missing_dir = path.join("missing").join("predictions.parquet");
+            let err =
+                write_predictions_parquet(train_data, test_data, &params, missing_dir).unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyOSError>(py));
+        });
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(contents.starts_with(b"PAR1") && contents.ends_with(b"PAR1"));
+        let footer_start = contents.len() - 8;
+        let footer_len =
+            u32::from_le_bytes(contents[footer_start..footer_start + 4].try_into().unwrap());
+        let metadata = &contents[footer_start - footer_len as usize..footer_start];
+        let contains = |haystack: &[u8], needle: &[u8]| {
+            return haystack
+                .windows(needle.len())
+                .any(|window| window == needle);
+        };
+        for column in [
+            "name_id",
+            "predicted_collection",
+            "probability",
+            "true_collection",
+        ] {
+            assert!(contains(metadata, column.as_bytes()));
+        }
+        // The name_id column comes first and has no definition levels
+        let name_ids: Vec<u8> = test_data
+            .iter()
+            .flat_map(|nam| nam.name_id.to_le_bytes())
+            .collect();
+        assert!(contains(&contents[4..64], &name_ids));
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let predictions: Vec<(i32, f64)> = test_data
+            .iter()
+            .filter_map(|nam| get_top_choice_impl(nam, &train_names, &params).unwrap())
+            .collect();
+        assert!(!predictions.is_empty() && predictions.len() < test_data.len());
+        let predicted_collections: Vec<u8> = predictions
+            .iter()
+            .flat_map(|(collection, _)| collection.to_le_bytes())
+            .collect();
+        assert!(contains(&contents, &predicted_collections));
+        let true_collections: Vec<u8> = test_data
+            .iter()
+            .filter_map(|nam| nam.collection)
+            .flat_map(|collection| collection.to_le_bytes())
+            .collect();
+        assert_eq!(true_collections.len(), 4 * 9);
+        assert!(contains(&contents, &true_collections));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_file_writes_plain_pages_and_a_footer() {
+        let columns = [
+            ParquetColumn {
+                name: "a",
+                required: true,
+                values: ParquetValues::Int32(vec![Some(1), Some(-2)]),
+            },
+            ParquetColumn {
+                name: "b",
+                required: false,
+                values: ParquetValues::Double(vec![None, Some(0.5)]),
+            },
+        ];
+        // A data page header in Thrift's compact protocol: type DATA_PAGE, twice the page
+        // size, then a DataPageHeader with 2 values, PLAIN encoding and RLE levels
+        let page_header = |size: u8| {
+            [
+                0x15,
+                0x00,
+                0x15,
+                size * 2,
+                0x15,
+                size * 2,
+                0x2c,
+                0x15,
+                0x04,
+                0x15,
+                0x00,
+                0x15,
+                0x06,
+                0x15,
+                0x06,
+                0x00,
+                0x00,
+            ]
+        };
+        let mut expected_pages = b"PAR1".to_vec();
+        expected_pages.extend(page_header(8));
+        expected_pages.extend([0x01, 0x00, 0x00, 0x00, 0xfe, 0xff, 0xff, 0xff]);
+        expected_pages.extend(page_header(16));
+        // The definition levels are a run of one 0 and a run of one 1
+        expected_pages.extend([0x04, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x01]);
+        expected_pages.extend(0.5f64.to_le_bytes());
+        let contents = parquet_file(&columns);
+        assert!(contents.starts_with(&expected_pages));
+        let footer_start = contents.len() - 8;
+        let footer_len =
+            u32::from_le_bytes(contents[footer_start..footer_start + 4].try_into().unwrap());
+        assert_eq!(expected_pages.len() + footer_len as usize, footer_start);
+        assert_eq!(&contents[footer_start + 4..], b"PAR1");
+    }
+
+    #[test]
+    fn nearest_neighbor_choice_follows_the_best_single_match() {
+        let (query, train_data) = tie_fixture();
+        let best_score = get_score_impl(&query, &train_data[2], &params()).unwrap();
+        let strict = Params {
+            score_cutoff: best_score,
+            ..params()
+        };
+        let (choice, no_choice) = with_names(&train_data, |_, train_data| {
+            return (
+                nearest_neighbor_choice(&query, train_data.clone(), &params()).unwrap(),
+                nearest_neighbor_choice(&query, train_data, &strict).unwrap(),
+            );
+        });
+        assert_eq!(choice, Some((2, best_score)));
+        assert_eq!(no_choice, None);
+    }
+
+    #[test]
+    fn query_denominator_ignores_extra_training_authors() {
+        let with_denominator = |author_overlap_denominator, ordered_author_overlap| {
+            return Params {
+                author_overlap_denominator,
+                ordered_author_overlap,
+                ..params()
+            };
+        };
+        let variants = [
+            with_denominator(AuthorOverlapDenominator::Union, false),
+            with_denominator(AuthorOverlapDenominator::Query, false),
+            with_denominator(AuthorOverlapDenominator::Query, true),
+        ];
+        // The overlap under each variant
+        let cases: [(&[i32], &[i32], [f64; 3]); 4] = [
+            (&[1, 2], &[1, 2, 3, 4], [0.5, 1.0, 1.0]),
+            (&[1, 2, 3, 4], &[1, 2], [0.5, 0.5, 0.5]),
+            (&[1, 2], &[2, 1, 5], [2.0 / 3.0, 1.0, 0.5]),
+            (&[], &[1], [0.0, 0.0, 0.0]),
+        ];
+        for (authors1, authors2, overlaps) in cases {
+            let nam1 = name(Some(1), 1, None, authors1, 1, 1);
+            let nam2 = name(Some(2), 1, None, authors2, 1, 2);
+            for (params, overlap) in variants.iter().zip(overlaps) {
+                assert_eq!(author_overlap(&nam1, &nam2, Some(params)), overlap);
+            }
+        }
+    }
+
+    #[test]
+    fn expected_abstentions_are_the_unpredicted_names() {
+        let data = clustered_data(18000, 60);
+        let (train_data, test_data) = data.split_at(40);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let scoring_function = ScoringFunction::new(1.0, 0.0, 0.0);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let by_cutoff = abstentions_by_cutoff(
+                bound(py, train_data),
+                bound(py, test_data),
+                &params(),
+                vec![0.0, 0.4, 0.6, 1.0],
+            )
+            .unwrap();
+            assert_eq!(by_cutoff[3], (1.0, 20));
+            for (cutoff, abstentions) in by_cutoff {
+                let params = Params {
+                    probability_cutoff: cutoff,
+                    ..params()
+                };
+                let info =
+                    evaluate_model_impl(&train_names, &test_names, &scoring_function, &params)
+                        .unwrap();
+                assert_eq!(abstentions, info.no_value);
+                assert_eq!(
+                    expected_abstentions(bound(py, train_data), bound(py, test_data), &params)
+                        .unwrap(),
+                    abstentions
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn breakdown_factors_multiply_to_the_score() {
+        let data = synthetic_data(13000, 30);
+        let params = Params {
+            author_year_interaction: 0.5,
+            length_mismatch_penalty: 0.1,
+            ..params()
+        };
+        for nam1 in data.iter() {
+            for nam2 in data.iter().take(10) {
+                let (score, breakdown) = get_score_verbose(nam1, nam2, &params).unwrap();
+                assert_eq!(score, get_score_impl(nam1, nam2, &params).unwrap());
+                if nam1.name_id == nam2.name_id {
+                    continue;
+                }
+                let product = breakdown.country
+                    * breakdown.citation_group
+                    * breakdown.author
+                    * breakdown.year
+                    * breakdown.year_boost;
+                assert!((product - score).abs() <= 1e-12 * score);
+            }
+        }
+        let distance = Params {
+            score_mode: ScoreMode::Distance,
+            ..params
+        };
+        assert!(get_score_verbose(&data[0], &data[1], &distance).is_err());
+    }
+
+    #[test]
+    fn validate_warns_about_degenerate_params() {
+        assert!(params().validate().is_empty());
+        let degenerate = Params {
+            country_boost: 0.0,
+            probability_cutoff: 1.0,
+            year_factor: 0.5,
+            ..params()
+        };
+        let warnings = degenerate.validate();
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].starts_with("country_boost is 0"));
+        assert!(warnings[1].starts_with("year_factor is 0.5"));
+        assert!(warnings[2].starts_with("probability_cutoff is 1"));
+    }
+
+    #[test]
+    fn most_influential_train_item_matches_removing_each_name() {
+        let data = clustered_data(19000, 30);
+        let (train_data, test_data) = data.split_at(20);
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let correct = |train_names: &[&NameData]| {
+            let scoring_function = ScoringFunction::new(0.0, 0.0, 0.0);
+            return evaluate_model_impl(train_names, &test_names, &scoring_function, &params())
+                .unwrap()
+                .correct;
+        };
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let baseline = correct(&train_names);
+        let mut expected: Option<(i32, i32)> = None;
+        for index in 0..train_names.len() {
+            let mut candidate = train_names.clone();
+            candidate.remove(index);
+            let reduction = baseline - correct(&candidate);
+            if expected.is_none_or(|(_, best)| reduction > best) {
+                expected = Some((train_names[index].name_id, reduction));
+            }
+        }
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let influential = |train_data: &[NameData]| {
+                return most_influential_train_item(
+                    py,
+                    bound(py, train_data),
+                    bound(py, test_data),
+                    &params(),
+                );
+            };
+            assert_eq!(influential(train_data).unwrap(), expected.unwrap());
+            assert!(influential(&[]).is_err());
+        });
+    }
+
+    #[test]
+    fn best_over_params_takes_the_most_confident_params() {
+        let (query, train_data) = tie_fixture();
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let unpredicted = Params {
+            probability_cutoff: 1.0,
+            ..params()
+        };
+        let best_match = Params {
+            tie_break: TieBreak::BestMatch,
+            ..params()
+        };
+        let boosted = Params {
+            author_boost: 10.0,
+            ..params()
+        };
+        let boosted_choice = get_top_choice_impl(&query, &train_names, &boosted).unwrap();
+        let (boosted_collection, boosted_probability) = boosted_choice.unwrap();
+        let default_probability = get_top_choice_impl(&query, &train_names, &params())
+            .unwrap()
+            .unwrap()
+            .1;
+        assert!(boosted_probability > default_probability);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let best = |params_list: &[&Params]| {
+                let params_list: Vec<PyRef<'_, Params>> = params_list
+                    .iter()
+                    .map(|params| Bound::new(py, (*params).clone()).unwrap().borrow())
+                    .collect();
+                return best_over_params(&query, bound(py, &train_data), params_list).unwrap();
+            };
+            assert_eq!(best(&[&unpredicted]), None);
+            let (collection, probability, index) = best(&[&unpredicted, &best_match]).unwrap();
+            assert!(same_choice(
+                Some((collection, probability)),
+                Some((2, default_probability))
+            ));
+            assert_eq!(index, 1);
+            let (collection, probability, index) =
+                best(&[&params(), &boosted, &best_match]).unwrap();
+            let boosted_choice = Some((boosted_collection, boosted_probability));
+            assert!(same_choice(Some((collection, probability)), boosted_choice));
+            assert_eq!(index, 1);
+        });
+    }
+
+    #[test]
+    fn feature_correlation_counts_matching_pairs() {
+        let train_data = [
+            name(Some(1), 1, None, &[1], 1, 1),
+            name(Some(1), 1, None, &[2], 1, 2),
+            name(Some(2), 1, None, &[3], 2, 3),
+            name(Some(2), 2, None, &[4], 3, 4),
+        ];
+        let (correlation, unmatched, empty) = with_names(&train_data, |py, data| {
+            return (
+                feature_correlation(py, data.clone()),
+                feature_correlation(py, vec![data[2].clone(), data[3].clone()]),
+                feature_correlation(py, Vec::new()),
+            );
+        });
+        let expected = [
+            ("country", 0.5),
+            ("citation_group", 1.0 / 6.0),
+            ("both", 1.0 / 6.0),
+            ("citation_group_given_country", 1.0 / 3.0),
+            ("country_given_citation_group", 1.0),
+        ];
+        assert_eq!(correlation.len(), expected.len());
+        for (key, value) in expected {
+            assert!((correlation[key] - value).abs() < 1e-12);
+        }
+        let mut unmatched_keys: Vec<&String> = unmatched.keys().collect();
+        unmatched_keys.sort();
+        assert_eq!(unmatched_keys, ["both", "citation_group", "country"]);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn top_k_agrees_with_a_full_sort() {
+        let mut rng = SeededRng::new(3);
+        for count in [0, 1, 5, 40] {
+            // Few distinct values, so there are many ties
+            let entries: Vec<(i32, f64)> = (0..count)
+                .map(|key| (key, rng.below(4) as f64 / 4.0))
+                .collect();
+            let mut sorted = entries.clone();
+            sorted.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+            for k in 0..count as usize + 2 {
+                let expected: Vec<(i32, f64)> = sorted.iter().copied().take(k).collect();
+                assert_eq!(top_k(entries.iter().copied(), k), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn break_even_cost_zeroes_the_score() {
+        let data = clustered_data(20000, 60);
+        let (train_data, test_data) = data.split_at(40);
+        let break_even = |train_data: &[NameData], test_data: &[NameData]| {
+            return with_names(test_data, |py, test_data| {
+                return break_even_cost(bound(py, train_data), test_data, &params()).unwrap();
+            });
+        };
+        let cost = break_even(train_data, test_data);
+        let info = evaluate_model_impl(
+            &train_data.iter().collect::<Vec<_>>(),
+            &test_data.iter().collect::<Vec<_>>(),
+            &ScoringFunction::new(cost, 0.0, 0.0),
+            &params(),
+        )
+        .unwrap();
+        assert!(info.correct > 0 && info.incorrect > 0);
+        assert!(info.score.abs() < 1e-9);
+        // Without a wrong prediction no cost makes the predictions worthless
+        assert_eq!(break_even(train_data, &test_data[..0]), f64::INFINITY);
+    }
+
+    #[test]
+    fn year_bins_evaluate_their_own_names() {
+        let mut data = clustered_data(16000, 70);
+        data[65].year = Some(-5);
+        let (train_data, test_data) = data.split_at(40);
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let bins = with_names(train_data, |py, train_data| {
+            let by_year = |bin_size: i32| {
+                return accuracy_by_year(
+                    train_data.clone(),
+                    bound(py, test_data),
+                    &scoring_function,
+                    &params(),
+                    bin_size,
+                );
+            };
+            assert!(by_year(0).is_err());
+            return by_year(50).unwrap();
+        });
+        let starts: Vec<i32> = bins.iter().map(|(start, _)| *start).collect();
+        let mut expected_starts: Vec<i32> = test_data
+            .iter()
+            .filter_map(|nam| nam.year)
+            .map(|year| year.div_euclid(50) * 50)
+            .collect();
+        expected_starts.sort();
+        expected_starts.dedup();
+        assert_eq!(starts, expected_starts);
+        assert_eq!(starts[0], -50);
+        for (start, info) in bins {
+            let in_bin: Vec<&NameData> = test_data
+                .iter()
+                .filter(|nam| {
+                    nam.year
+                        .is_some_and(|year| year >= start && year < start + 50)
+                })
+                .collect();
+            let expected =
+                evaluate_model_impl(&train_names, &in_bin, &scoring_function, &params()).unwrap();
+            assert_eq!(summary(&info), summary(&expected));
+        }
+    }
+
+    #[test]
+    fn counterfactual_country_changes_only_the_country() {
+        let query = name(None, 1, None, &[7], 3, 0);
+        let train_data = [
+            name(Some(1), 1, None, &[9], 1, 1),
+            name(Some(1), 1, None, &[9], 1, 2),
+            name(Some(2), 2, None, &[8], 2, 3),
+            name(Some(2), 2, None, &[8], 2, 4),
+        ];
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let choices = with_names(&train_data, |_py, train_data| {
+            return counterfactual_country(&query, train_data, &params(), vec![1, 2, 3]).unwrap();
+        });
+        assert_eq!(choices.len(), 3);
+        assert_eq!(choices[&1].unwrap().0, 1);
+        assert_eq!(choices[&2].unwrap().0, 2);
+        for (tl_country, choice) in choices {
+            let moved = name(None, tl_country, None, &[7], 3, 0);
+            let expected = get_top_choice_impl(&moved, &train_names, &params()).unwrap();
+            assert!(same_choice(choice, expected));
+        }
+    }
+
+    #[test]
+    fn interned_names_share_equal_author_lists() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let data = [
+                name(Some(1), 1, None, &[1, 2], 1, 1),
+                name(Some(2), 2, None, &[1, 2], 2, 2),
+                name(Some(1), 1, None, &[3], 1, 3),
+                name(Some(2), 1, None, &[1, 2], 1, 4),
+            ];
+            let interned = intern_authors(bound(py, &data));
+            assert!(std::sync::Arc::ptr_eq(
+                &interned[0].authors,
+                &interned[1].authors
+            ));
+            assert!(std::sync::Arc::ptr_eq(
+                &interned[0].authors,
+                &interned[3].authors
+            ));
+            assert!(!std::sync::Arc::ptr_eq(
+                &interned[0].authors,
+                &interned[2].authors
+            ));
+            assert!(!std::sync::Arc::ptr_eq(&data[0].authors, &data[1].authors));
+            for (index1, nam1) in interned.iter().enumerate() {
+                for (index2, nam2) in interned.iter().enumerate() {
+                    assert_eq!(
+                        get_score_impl(nam1, nam2, &params()).unwrap(),
+                        get_score_impl(&data[index1], &data[index2], &params()).unwrap()
+                    );
+                }
+            }
+            let timings = benchmark_scoring(py, &params(), 10, 5).unwrap();
+            assert!(timings["interned_authors_matching"] >= 0.0);
+        });
+    }
+
+    #[test]
+    fn evaluate_confident_defers_unlikely_predictions() {
+        let data = clustered_data(21000, 60);
+        let (train_data, test_data) = data.split_at(40);
+        let scoring_function = ScoringFunction::new(1.0, 0.5, 0.0);
+        let full = evaluate_model_impl(
+            &train_data.iter().collect::<Vec<_>>(),
+            &test_data.iter().collect::<Vec<_>>(),
+            &scoring_function,
+            &params(),
+        )
+        .unwrap();
+        let confident = |min_confidence: f64| {
+            return with_names(test_data, |py, test_data| {
+                return evaluate_confident(
+                    bound(py, train_data),
+                    test_data,
+                    &scoring_function,
+                    &params(),
+                    min_confidence,
+                )
+                .unwrap();
+            });
+        };
+        let (info, deferred) = confident(0.0);
+        assert_eq!((summary(&info), deferred), (summary(&full), 0));
+        let (info, deferred) = confident(0.5);
+        assert!(deferred > 0 && info.correct + info.incorrect > 0);
+        assert_eq!(info.correct + info.incorrect + info.no_value + deferred, 20);
+        let (info, deferred) = confident(1.1);
+        assert_eq!(
+            (info.correct + info.incorrect + info.no_value, deferred),
+            (0, 20)
+        );
+    }
+
+    #[test]
+    fn sentinels_mark_fields_as_unknown() {
+        let with_sentinels = Params {
+            country_sentinel: Some(0),
+            citation_group_sentinel: Some(0),
+            ..params()
+        };
+        let factors = |nam1: &NameData, nam2: &NameData, params: &Params| {
+            let factors = score_factors(&pair_features(nam1, nam2), params);
+            return (factors[0], factors[1]);
+        };
+        let unknown = name(Some(1), 0, None, &[1], 0, 1);
+        let also_unknown = name(Some(2), 0, None, &[2], 0, 2);
+        let known = name(Some(2), 1, None, &[2], 1, 3);
+        let also_known = name(Some(1), 1, None, &[3], 1, 6);
+        assert_eq!(factors(&unknown, &also_unknown, &params()), (2.0, 2.0));
+        assert_eq!(
+            factors(&unknown, &also_unknown, &with_sentinels),
+            (1.0, 1.0)
+        );
+        assert_eq!(factors(&known, &also_known, &with_sentinels), (2.0, 2.0));
+        // The sentinel only marks the citation group unknown when it is the only one
+        let several = NameData::new(
+            Some(1),
+            1,
+            None,
+            vec![1],
+            CitationGroups::Multiple(vec![0, 1]),
+            4,
+        );
+        let overlapping = NameData::new(
+            Some(2),
+            1,
+            None,
+            vec![2],
+            CitationGroups::Multiple(vec![0, 1]),
+            5,
+        );
+        assert_eq!(factors(&several, &overlapping, &with_sentinels), (2.0, 2.0));
+    }
+
+    #[test]
+    fn plausible_collections_exceed_min_prob() {
+        let (query, train_data) = tie_fixture();
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let probs = get_probs_impl(&query, &train_names, &params()).unwrap();
+        let tied = probs[&1];
+        assert!(probs[&0] > 0.0);
+        with_names(&train_data, |_py, train_data| {
+            let count = |min_prob: f64| {
+                return plausible_collection_count(&query, train_data.clone(), &params(), min_prob)
+                    .unwrap();
+            };
+            // The unknown bucket is never counted
+            assert_eq!(count(0.0), 2);
+            assert_eq!(count(tied * 0.9), 2);
+            assert_eq!(count(tied), 0);
+        });
+    }
+
+    #[test]
+    fn bagged_top_choice_counts_the_votes_of_each_resample() {
+        let query = name(None, 1, Some(1900), &[1], 1, 0);
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(1), 1, Some(1910), &[1], 1, 2),
+            name(Some(1), 2, Some(1900), &[2], 2, 3),
+        ];
+        let cautious = Params {
+            probability_cutoff: 0.99,
+            ..params()
+        };
+        let (unanimous, unpredicted, no_bags) = with_names(&train_data, |py, train_data| {
+            let bagged = |params: &Params, n_bags: usize| {
+                return bagged_top_choice(py, &query, train_data.clone(), params, n_bags, 3);
+            };
+            return (
+                bagged(&params(), 10).unwrap(),
+                bagged(&cautious, 10).unwrap(),
+                bagged(&params(), 0).is_err(),
+            );
+        });
+        // Every resample only has names of collection 1
+        assert_eq!(unanimous, Some((1, 1.0)));
+        assert_eq!(unpredicted, None);
+        assert!(no_bags);
+    }
+
+    #[test]
+    fn zero_error_cutoff_excludes_every_wrong_prediction() {
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(1), 1, Some(1900), &[1], 1, 2),
+            name(Some(2), 2, Some(1900), &[2], 2, 3),
+        ];
+        // Both are predicted as collection 1, the wrong one less confidently
+        let test_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 10),
+            name(Some(2), 2, Some(1900), &[1], 2, 11),
+        ];
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let incorrect = get_top_choice_impl(&test_data[1], &train_names, &params())
+            .unwrap()
+            .unwrap()
+            .1;
+        let cutoff = |test_data: &[NameData]| {
+            return with_names(test_data, |py, test_data| {
+                return zero_error_cutoff(bound(py, &train_data), test_data, &params()).unwrap();
+            });
+        };
+        assert!((cutoff(&test_data).unwrap() - incorrect).abs() < 1e-12);
+        assert_eq!(cutoff(&test_data[1..]), None);
+        let info = evaluate_model_impl(
+            &train_names,
+            &test_data.iter().collect::<Vec<_>>(),
+            &ScoringFunction::new(1.0, 0.0, 0.0),
+            // Allowing for probabilities that differ in the last bits when recomputed
+            &Params {
+                probability_cutoff: cutoff(&test_data).unwrap() + 1e-12,
+                ..params()
+            },
+        )
+        .unwrap();
+        assert_eq!((info.correct, info.incorrect), (1, 0));
+    }
+
+    #[test]
+    fn scoring_table_reproduces_get_score() {
+        let data = synthetic_data(14000, 40);
+        let variants = [
+            Params {
+                author_year_interaction: 0.5,
+                length_mismatch_penalty: 0.1,
+                ..params()
+            },
+            Params {
+                combiner: Combiner::LogAdditive,
+                country_sentinel: Some(3),
+                citation_group_sentinel: Some(7),
+                ..params()
+            },
+        ];
+        for params in variants {
+            let table = export_scoring_table(&params, 200, 5).unwrap();
+            for nam1 in data.iter() {
+                for nam2 in data.iter() {
+                    let score = get_score_impl(nam1, nam2, &params).unwrap();
+                    assert!((table.score(nam1, nam2) - score).abs() <= 1e-12 * score);
+                }
+            }
+        }
+        // Applied by hand: same country, different citation groups, one shared author of
+        // lists one name apart, and 10 years apart
+        let table = export_scoring_table(&params(), 100, 20).unwrap();
+        let nam1 = name(Some(1), 1, Some(1900), &[1], 1, 1);
+        let nam2 = name(Some(1), 1, Some(1910), &[1, 2], 2, 2);
+        let product = table.country_factor
+            * table.author_factors[1]
+            * table.year_decays[10]
+            * table.year_boost;
+        assert_eq!(product, get_score_impl(&nam1, &nam2, &params()).unwrap());
+        let distance = Params {
+            score_mode: ScoreMode::Distance,
+            ..params()
+        };
+        assert!(export_scoring_table(&distance, 100, 20).is_err());
+    }
+
+    #[test]
+    fn label_noise_estimate_finds_a_mislabelled_name() {
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[1], 1, 1),
+            name(Some(1), 1, Some(1900), &[1], 1, 2),
+            name(Some(1), 1, Some(1900), &[1], 1, 3),
+            // Looks like collection 1
+            name(Some(2), 1, Some(1900), &[1], 1, 4),
+            name(Some(2), 2, Some(1900), &[2], 2, 5),
+            name(Some(2), 2, Some(1900), &[2], 2, 6),
+            name(None, 1, Some(1900), &[1], 1, 7),
+        ];
+        let (noise, suspects) = with_names(&train_data, |py, train_data| {
+            return label_noise_estimate(py, train_data, &params()).unwrap();
+        });
+        assert_eq!(suspects, [4]);
+        assert_eq!(noise, 1.0 / 6.0);
+    }
+
+    #[test]
+    fn predict_batch_matches_top_choice_explained() {
+        let train_data = clustered_data(22000, 40);
+        let queries = clustered_data(22100, 10);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let params = Params {
+            probability_cutoff: 0.3,
+            ..params()
+        };
+        let outcomes = with_names(&queries, |py, queries| {
+            return predict_batch(py, queries, bound(py, &train_data), &params).unwrap();
+        });
+        assert_eq!(outcomes.len(), queries.len());
+        assert!(outcomes
+            .iter()
+            .any(|outcome| matches!(outcome, PredictionOutcome::Predicted { .. })));
+        assert!(outcomes
+            .iter()
+            .any(|outcome| matches!(outcome, PredictionOutcome::BelowCutoff { .. })));
+        for (query, outcome) in queries.iter().zip(outcomes) {
+            let expected = top_choice_explained_impl(query, &train_names, &params).unwrap();
+            // The probabilities can differ in the last bits
+            assert_eq!(
+                std::mem::discriminant(&outcome),
+                std::mem::discriminant(&expected)
+            );
+            if let (
+                PredictionOutcome::Predicted { collection, .. },
+                PredictionOutcome::Predicted {
+                    collection: expected,
+                    ..
+                },
+            ) = (outcome, expected)
+            {
+                assert_eq!(collection, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_probs_match_sequential_probs() {
+        let mut data = clustered_data(10000, 200);
+        data[7].collection = None;
+        let (queries, train_data) = data.split_at(10);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let variants = [
+            params(),
+            Params {
+                max_contributions: 5,
+                exclude_same_citation_group: true,
+                ..params()
+            },
+        ];
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            for params in variants.iter() {
+                for query in queries {
+                    let parallel = get_probs_parallel(py, query, bound(py, train_data), params);
+                    let parallel = parallel.unwrap();
+                    let sequential = get_probs_impl(query, &train_names, params).unwrap();
+                    assert_eq!(parallel.len(), sequential.len());
+                    // The totals are summed over a HashMap, so the last bit can differ
+                    for (collection, prob) in sequential.iter() {
+                        assert!((parallel[collection] - prob).abs() < 1e-12);
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn max_contributions_keeps_the_best_matches() {
+        let data = clustered_data(17000, 41);
+        let (query, train_data) = (&data[0], &data[1..]);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let mut ranked: Vec<(usize, f64)> = train_names
+            .iter()
+            .map(|nam| get_score_impl(query, nam, &params()).unwrap())
+            .enumerate()
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        let capped = Params {
+            max_contributions: 5,
+            ..params()
+        };
+        let mut kept: Vec<usize> = ranked[..5].iter().map(|(index, _)| *index).collect();
+        kept.sort_unstable();
+        let best_names: Vec<&NameData> = kept.iter().map(|index| train_names[*index]).collect();
+        let probs = get_probs_impl(query, &train_names, &capped).unwrap();
+        let expected = get_probs_impl(query, &best_names, &params()).unwrap();
+        assert_ne!(
+            probs,
+            get_probs_impl(query, &train_names, &params()).unwrap()
+        );
+        assert_eq!(probs.len(), expected.len());
+        for (collection, prob) in expected {
+            assert!((probs[&collection] - prob).abs() < 1e-12);
+        }
+        let raw_scores = get_raw_scores_impl(query, &train_names[..20], &capped).unwrap();
+        with_names(&train_data[20..], |_py, new_names| {
+            assert!(raw_scores.update(query, new_names, &capped).is_err());
+        });
+    }
+
+    #[test]
+    fn sibling_predictions_earn_partial_credit() {
+        let data = clustered_data(18000, 80);
+        let (train_data, test_data) = data.split_at(40);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let parents = std::collections::HashMap::from([(1, 10), (2, 10), (3, 20), (4, 20)]);
+        let mut siblings = 0;
+        for nam in test_data {
+            let prediction = get_top_choice_impl(nam, &train_names, &params()).unwrap();
+            if let Some((collection, _)) = prediction {
+                let true_collection = nam.collection.unwrap();
+                if collection != true_collection
+                    && parents[&collection] == parents[&true_collection]
+                {
+                    siblings += 1;
+                }
+            }
+        }
+        assert!(siblings > 0);
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.5);
+        let (plain, with_parents) = with_names(train_data, |py, train_data| {
+            let evaluate = |parents: Option<std::collections::HashMap<i32, i32>>| {
+                return evaluate_model(
+                    train_data.clone(),
+                    bound(py, test_data),
+                    &scoring_function,
+                    &params(),
+                    None,
+                    parents,
+                    false,
+                    false,
+                    false,
+                )
+                .unwrap();
+            };
+            return (evaluate(None), evaluate(Some(parents)));
+        });
+        assert_eq!(plain.partial_correct, 0);
+        assert_eq!(with_parents.partial_correct, siblings);
+        assert_eq!(with_parents.correct, plain.correct);
+        assert_eq!(with_parents.incorrect, plain.incorrect - siblings);
+        let gained = siblings as f64 * (1.0 + 0.5);
+        assert!((with_parents.score - plain.score - gained).abs() < 1e-9);
+    }
+
+    #[test]
+    fn learning_curve_ends_at_the_full_training_set() {
+        let data = clustered_data(23000, 60);
+        let (train_data, test_data) = data.split_at(45);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let scoring_function = ScoringFunction::new(1.0, 0.2, 0.0);
+        let params = Params {
+            probability_cutoff: 0.3,
+            ..params()
+        };
+        let full =
+            evaluate_model_impl(&train_names, &test_names, &scoring_function, &params).unwrap();
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let curve = |fractions: Vec<f64>| {
+                return learning_curve(
+                    py,
+                    bound(py, train_data),
+                    bound(py, test_data),
+                    &scoring_function,
+                    &params,
+                    fractions,
+                    1,
+                );
+            };
+            let points = curve(vec![0.5, 1.0]).unwrap();
+            let fractions: Vec<f64> = points.iter().map(|(fraction, _)| *fraction).collect();
+            assert_eq!(fractions, vec![0.5, 1.0]);
+            assert_eq!(summary(&points[1].1), summary(&full));
+            assert!(curve(vec![0.5, 1.5]).is_err());
+            assert!(curve(vec![-0.1]).is_err());
+        });
+    }
+
+    #[test]
+    fn merge_candidates_follow_the_confusion_matrix() {
+        let mut data = clustered_data(19000, 80);
+        data[70].collection = None;
+        let (train_data, test_data) = data.split_at(40);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let mut expected_matrix: std::collections::HashMap<(i32, i32), i32> =
+            std::collections::HashMap::new();
+        for nam in test_data.iter().filter(|nam| nam.collection.is_some()) {
+            if let Some((predicted, _)) = get_top_choice_impl(nam, &train_names, &params()).unwrap()
+            {
+                *expected_matrix
+                    .entry((nam.collection.unwrap(), predicted))
+                    .or_insert(0) += 1;
+            }
+        }
+        let (matrix, candidates) = with_names(train_data, |py, train_data| {
+            return (
+                confusion_matrix(train_data.clone(), bound(py, test_data), &params()).unwrap(),
+                merge_candidates(train_data, bound(py, test_data), &params(), 0.0).unwrap(),
+            );
+        });
+        assert_eq!(matrix, expected_matrix);
+        let predicted = |collection: i32| {
+            return matrix
+                .iter()
+                .filter(|((true_collection, _), _)| *true_collection == collection)
+                .map(|(_, count)| count)
+                .sum::<i32>();
+        };
+        assert!(!candidates.is_empty());
+        for (index, (collection1, collection2, rate)) in candidates.iter().enumerate() {
+            assert!(collection1 < collection2 && *collection1 != 0);
+            let count = matrix.get(&(*collection1, *collection2)).unwrap_or(&0)
+                + matrix.get(&(*collection2, *collection1)).unwrap_or(&0);
+            let total = predicted(*collection1) + predicted(*collection2);
+            assert_eq!(*rate, count as f64 / total as f64);
+            if index > 0 {
+                assert!(candidates[index - 1].2 >= *rate);
+            }
+        }
+        let highest = candidates[0].2;
+        let above = with_names(train_data, |py, train_data| {
+            return merge_candidates(train_data, bound(py, test_data), &params(), highest).unwrap();
+        });
+        assert!(above.is_empty());
+    }
+
+    #[test]
+    fn ovr_scores_give_every_collection_for_each_labelled_name() {
+        let train_data = clustered_data(24000, 40);
+        let mut test_data = clustered_data(24100, 5);
+        test_data.push(name(None, 1, Some(1900), &[1], 1, 24200));
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let scores = with_names(&train_data, |py, train_data| {
+            return ovr_scores(train_data, bound(py, &test_data), &params()).unwrap();
+        });
+        // The unlabelled name is left out
+        assert_eq!(scores.len(), 5 * 4);
+        for (nam, rows) in test_data.iter().zip(scores.chunks(4)) {
+            let probs = get_probs_impl(nam, &train_names, &params()).unwrap();
+            let collections: Vec<i32> = rows.iter().map(|(collection, _, _)| *collection).collect();
+            assert_eq!(collections, vec![1, 2, 3, 4]);
+            for (collection, prob, is_true) in rows.iter() {
+                let expected = probs.get(collection).copied().unwrap_or(0.0);
+                assert!((prob - expected).abs() <= 1e-12);
+                assert_eq!(*is_true, nam.collection == Some(*collection));
+            }
+        }
+    }
+
+    #[test]
+    fn balanced_collections_count_equally() {
+        let names = [
+            name(Some(1), 1, None, &[1], 1, 1),
+            name(Some(1), 1, None, &[1], 1, 2),
+            name(Some(1), 1, None, &[1], 1, 3),
+            name(Some(2), 1, None, &[1], 1, 4),
+            name(None, 1, None, &[1], 1, 5),
+        ];
+        let names: Vec<&NameData> = names.iter().collect();
+        assert_eq!(
+            collection_balance_weights(&names),
+            [2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 2.0, 1.0]
+        );
+        let data = clustered_data(20000, 60);
+        let (train_data, test_data) = data[..41].split_at(30);
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let balance = collection_balance_weights(&test_names);
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        with_names(train_data, |py, train_data| {
+            let evaluate = |weights: Option<Vec<f64>>, balance_collections: bool| {
+                return evaluate_model(
+                    train_data.clone(),
+                    bound(py, test_data),
+                    &scoring_function,
+                    &params(),
+                    weights,
+                    None,
+                    balance_collections,
+                    false,
+                    false,
+                )
+                .unwrap();
+            };
+            let balanced = evaluate(None, true);
+            assert!((balanced.score - evaluate(Some(balance.clone()), false).score).abs() < 1e-9);
+            assert_ne!(balanced.score, evaluate(None, false).score);
+            let weights: Vec<f64> = (0..11).map(|index| index as f64).collect();
+            let product: Vec<f64> = weights.iter().zip(&balance).map(|(a, b)| a * b).collect();
+            let weighted_balanced = evaluate(Some(weights), true);
+            assert!((weighted_balanced.score - evaluate(Some(product), false).score).abs() < 1e-9);
+        });
+    }
+
+    #[test]
+    fn benchmark_queries_times_every_query() {
+        let train_data = synthetic_data(25000, 200);
+        let queries = synthetic_data(25200, 20);
+        let (stats, empty) = with_names(&train_data, |py, train_data| {
+            return (
+                benchmark_queries(py, bound(py, &queries), train_data.clone(), &params()).unwrap(),
+                benchmark_queries(py, Vec::new(), train_data, &params()),
+            );
+        });
+        assert_eq!(stats.count, queries.len());
+        assert!(stats.min >= 0.0);
+        assert!(stats.min <= stats.p50 && stats.p50 <= stats.p95);
+        assert!(stats.p95 <= stats.p99 && stats.p99 <= stats.max);
+        assert!(stats.min <= stats.mean && stats.mean <= stats.max);
+        assert!(empty.is_err());
+    }
+
+    #[test]
+    fn validate_dataset_reports_each_problem_once() {
+        let data = [
+            name(Some(1), 1, None, &[1], 1, 1),
+            name(Some(2), 1, None, &[1], 1, 1),
+            name(Some(1), 1, None, &[1], 1, 1),
+            name(Some(0), 1, None, &[], 1, -2),
+            NameData::new(
+                Some(-3),
+                1,
+                None,
+                vec![1],
+                CitationGroups::Multiple(vec![]),
+                3,
+            ),
+        ];
+        let problems = with_names(&data, |_py, data| validate_dataset(data));
+        assert_eq!(
+            problems,
+            [
+                "name_id 1 is used more than once",
+                "name -2 has a negative name_id",
+                "name -2 is in collection 0, which is reserved for the unknown bucket",
+                "name -2 has no authors",
+                "name 3 is in collection -3, which is negative",
+                "name 3 has no citation groups",
+            ]
+        );
+        let clean = with_names(&clustered_data(21000, 20), |_py, data| {
+            validate_dataset(data)
+        });
+        assert!(clean.is_empty());
+    }
+
+    #[test]
+    fn cached_predictions_match_evaluate_model_at_every_cutoff() {
+        let mut data = clustered_data(22000, 70);
+        data[60].collection = None;
+        let (train_data, test_data) = data.split_at(40);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.1);
+        let cached = with_names(train_data, |py, train_data| {
+            return CachedPredictions::new(train_data, bound(py, test_data), &params()).unwrap();
+        });
+        for step in 0..=20 {
+            let probability_cutoff = step as f64 / 20.0;
+            let params = Params {
+                probability_cutoff,
+                ..params()
+            };
+            let expected =
+                evaluate_model_impl(&train_names, &test_names, &scoring_function, &params).unwrap();
+            let info = cached.at_cutoff(&scoring_function, probability_cutoff);
+            assert_eq!(summary(&info), summary(&expected));
+        }
+    }
+
+    #[test]
+    fn model_vs_nn_agreement_lists_the_disagreements() {
+        let train_data = clustered_data(26000, 60);
+        let test_data = clustered_data(26100, 30);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let expected: Vec<i32> = test_data
+            .iter()
+            .filter(|nam| {
+                let model = get_top_choice_impl(nam, &train_names, &params()).unwrap();
+                let nearest = nearest_neighbor_choice_impl(nam, &train_names, &params()).unwrap();
+                return model.map(|(collection, _)| collection)
+                    != nearest.map(|(collection, _)| collection);
+            })
+            .map(|nam| nam.name_id)
+            .collect();
+        assert!(!expected.is_empty() && expected.len() < test_data.len());
+        let (agreement, empty) = with_names(&train_data, |py, train_data| {
+            return (
+                model_vs_nn_agreement(train_data.clone(), bound(py, &test_data), &params())
+                    .unwrap(),
+                model_vs_nn_agreement(train_data, Vec::new(), &params()).unwrap(),
+            );
+        });
+        let fraction = 1.0 - expected.len() as f64 / test_data.len() as f64;
+        assert_eq!(agreement, (fraction, expected));
+        assert_eq!(empty, (1.0, Vec::new()));
+    }
+
+    #[test]
+    fn author_overlap_exponent_must_be_positive() {
+        assert!(params().with_value("author_overlap_exponent", 0.0).is_err());
+        assert!(params()
+            .with_value("author_overlap_exponent", -1.0)
+            .is_err());
+        assert!(params().with_value("author_overlap_exponent", 2.0).is_ok());
+    }
+
+    #[test]
+    fn author_overlap_exponent_reduces_partial_overlap_credit() {
+        let query = name(None, 1, Some(1900), &[1, 2], 1, 0);
+        let full = name(Some(1), 1, Some(1950), &[1, 2], 1, 1);
+        let partial = name(Some(1), 1, Some(1950), &[1, 3], 1, 2);
+        let score = |train_datum: &NameData, exponent: f64| {
+            let params = Params {
+                author_year_interaction: 1.0,
+                ..params()
+                    .with_value("author_overlap_exponent", exponent)
+                    .unwrap()
+            };
+            return get_score_impl(&query, train_datum, &params).unwrap();
+        };
+        assert_eq!(score(&full, 1.0), score(&full, 2.0));
+        assert!(score(&partial, 2.0) < score(&partial, 1.0));
+    }
+
+    #[test]
+    fn early_top_choice_matches_exact_with_interaction() {
+        let query = name(None, 1, Some(1900), &[1], 1, 0);
+        let train_data = [
+            name(Some(1), 1, Some(1900), &[2], 1, 1),
+            name(Some(2), 2, Some(1950), &[1], 2, 2),
+        ];
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        let params = Params {
+            author_year_interaction: 0.5,
+            ..params()
+        };
+        assert_eq!(
+            get_top_choice_early_impl(&query, &train_data, &params)
+                .unwrap()
+                .map(|choice| choice.0),
+            get_top_choice_impl(&query, &train_data, &params)
+                .unwrap()
+                .map(|choice| choice.0)
+        );
+    }
+
+    #[test]
+    fn fit_boosts_lowers_the_loss() {
+        let data = clustered_data(11000, 60);
+        let (train_data, test_data) = data.split_at(40);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let fit = |test_data: &[NameData], initial: &Params, learning_rate: f64| {
+                return fit_boosts(
+                    bound(py, train_data),
+                    bound(py, test_data),
+                    initial,
+                    learning_rate,
+                    30,
+                );
+            };
+            let (fitted, losses) = fit(test_data, &params(), 0.05).unwrap();
+            assert_eq!(losses.len(), 30);
+            assert!(losses.windows(2).all(|pair| pair[1] <= pair[0]));
+            assert!(losses[29] < losses[0]);
+            // Matching authors separate the collections, so their boost goes up
+            assert!(fitted.author_boost > params().author_boost);
+            for initial in [
+                Params {
+                    combiner: Combiner::MaxFactor,
+                    ..params()
+                },
+                Params {
+                    score_mode: ScoreMode::Distance,
+                    ..params()
+                },
+                Params {
+                    cg_boost: 0.0,
+                    ..params()
+                },
+            ] {
+                assert!(fit(test_data, &initial, 0.05).is_err());
+            }
+            assert!(fit(test_data, &params(), 0.0).is_err());
+            let unlabelled = [name(None, 1, None, &[1], 1, 99999)];
+            assert!(fit(&unlabelled, &params(), 0.05).is_err());
+        });
+    }
+
+    #[test]
+    fn excluding_the_citation_group_drops_its_names() {
+        let data = clustered_data(23000, 60);
+        let (queries, train_data) = data.split_at(10);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let excluding = Params {
+            exclude_same_citation_group: true,
+            ..params()
+        };
+        let mut changed = 0;
+        for query in queries {
+            let kept: Vec<&NameData> = train_names
+                .iter()
+                .copied()
+                .filter(|nam| !shares_citation_group(query, nam))
+                .collect();
+            let probs = get_probs_impl(query, &train_names, &excluding).unwrap();
+            let expected = get_probs_impl(query, &kept, &params()).unwrap();
+            assert_eq!(probs.len(), expected.len());
+            for (collection, prob) in expected {
+                assert!((probs[&collection] - prob).abs() < 1e-12);
+            }
+            let collection = |train_data: &[&NameData], params: &Params| {
+                let top_choice = get_top_choice_impl(query, train_data, params).unwrap();
+                return top_choice.map(|(collection, _)| collection);
+            };
+            let top_choice = collection(&train_names, &excluding);
+            assert_eq!(top_choice, collection(&kept, &params()));
+            if top_choice != collection(&train_names, &params()) {
+                changed += 1;
+            }
+        }
+        assert!(changed > 0);
+    }
+
+    #[test]
+    fn prediction_diversity_is_normalized_entropy() {
+        let train_data = [
+            name(Some(1), 1, None, &[9], 1, 1),
+            name(Some(1), 1, None, &[9], 1, 2),
+            name(Some(2), 2, None, &[8], 2, 3),
+            name(Some(2), 2, None, &[8], 2, 4),
+        ];
+        let in_country = |tl_country: i32, name_id: i32| {
+            return name(None, tl_country, None, &[7], 3, name_id);
+        };
+        let uneven = [
+            in_country(1, 5),
+            in_country(1, 6),
+            in_country(1, 7),
+            in_country(2, 8),
+        ];
+        let even = [in_country(1, 5), in_country(2, 6)];
+        let (uneven, even, single) = with_names(&train_data, |py, train_data| {
+            let diversity = |test_data: &[NameData]| {
+                return prediction_diversity(train_data.clone(), bound(py, test_data), &params())
+                    .unwrap();
+            };
+            return (diversity(&uneven), diversity(&even), diversity(&even[..1]));
+        });
+        let entropy = -(0.75 * 0.75f64.ln() + 0.25 * 0.25f64.ln()) / 2f64.ln();
+        assert_eq!(uneven.0, 2);
+        assert!((uneven.1 - entropy).abs() < 1e-12);
+        assert_eq!(even, (2, 1.0));
+        assert_eq!(single, (1, 0.0));
+    }
+
+    #[test]
+    fn tie_break_applies_to_every_prediction_path() {
+        let (query, train_data) = tie_fixture();
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        for (tie_break, expected) in [(TieBreak::LowestId, 1), (TieBreak::BestMatch, 2)] {
+            let params = Params {
+                tie_break,
+                ..params()
+            };
+            let probs = get_probs_impl(&query, &train_data, &params).unwrap();
+            assert_eq!(probs[&1], probs[&2]);
+            let top_choice = get_top_choice_impl(&query, &train_data, &params).unwrap();
+            assert_eq!(top_choice.map(|(collection, _)| collection), Some(expected));
+            match top_choice_explained_impl(&query, &train_data, &params).unwrap() {
+                PredictionOutcome::Predicted { collection, .. } => {
+                    assert_eq!(collection, expected)
+                }
+                _ => panic!("expected a prediction"),
+            }
+            let choices = cutoff_independent_choices(&train_data, &[&query], &params).unwrap();
+            assert_eq!(choices[0].map(|(_, correct)| correct), Some(expected == 2));
+        }
+    }
+
+    #[test]
+    fn collection_accuracy_intervals_contain_the_accuracy() {
+        let (lower, upper) = wilson_interval(8, 10, 1.96);
+        assert!((lower - 0.490157).abs() < 1e-6 && (upper - 0.943319).abs() < 1e-6);
+        assert_eq!(wilson_interval(0, 10, 1.96).0, 0.0);
+        assert_eq!(wilson_interval(10, 10, 1.96).1, 1.0);
+        assert_eq!(wilson_interval(0, 0, 1.96), (0.0, 1.0));
+        let data = clustered_data(24000, 80);
+        let (train_data, test_data) = data.split_at(40);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        let accuracies = with_names(train_data, |py, train_data| {
+            return accuracy_by_collection(
+                train_data,
+                bound(py, test_data),
+                &scoring_function,
+                &params(),
+                1.96,
+            )
+            .unwrap();
+        });
+        let collections: Vec<i32> = accuracies.iter().map(|entry| entry.collection).collect();
+        assert_eq!(collections, [1, 2, 3, 4]);
+        for entry in accuracies {
+            let in_collection: Vec<&NameData> = test_data
+                .iter()
+                .filter(|nam| nam.collection == Some(entry.collection))
+                .collect();
+            let expected =
+                evaluate_model_impl(&train_names, &in_collection, &scoring_function, &params());
+            assert_eq!(summary(&entry.info), summary(&expected.unwrap()));
+            let accuracy = entry.info.correct as f64 / in_collection.len() as f64;
+            assert_eq!(entry.accuracy, accuracy);
+            assert!(entry.lower <= accuracy && accuracy <= entry.upper);
+        }
+    }
+
+    #[test]
+    fn sensitive_predictions_rest_on_one_name() {
+        let train_data = [
+            name(Some(1), 1, None, &[1], 1, 1),
+            name(Some(2), 2, None, &[5], 2, 2),
+            name(Some(2), 2, None, &[5], 2, 3),
+            name(Some(2), 2, None, &[5], 2, 4),
+        ];
+        // Collection 1 wins on its single strong match, collection 2 on several
+        let test_data = [
+            name(None, 1, None, &[1], 1, 10),
+            name(None, 2, None, &[5], 2, 11),
+        ];
+        let params = Params {
+            unknown_bucket_score: Some(1.0),
+            ..params()
+        };
+        let changes = with_names(&train_data, |py, train_data| {
+            return top_contributor_sensitivity(py, train_data, bound(py, &test_data), &params)
+                .unwrap();
+        });
+        assert_eq!(changes, [(10, true), (11, false)]);
+    }
+
+    #[test]
+    fn leave_one_out_skips_the_name_and_its_duplicates() {
+        let mut data = clustered_data(25000, 40);
+        // Exact copies under other ids, some of them in another collection
+        for index in 0..10 {
+            let copy = NameData {
+                name_id: 25100 + index,
+                collection: Some(index % 4 + 1),
+                ..data[index as usize].clone()
+            };
+            data.push(copy);
+        }
+        let names: Vec<&NameData> = data.iter().collect();
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        let expected = |leave_out: LeaveOut| {
+            let mut total = (0.0, 0, 0, 0);
+            for nam in names.iter() {
+                let remaining: Vec<&NameData> = names
+                    .iter()
+                    .copied()
+                    .filter(|train_datum| !leave_out.excludes(nam, train_datum))
+                    .collect();
+                let info =
+                    evaluate_model_impl(&remaining, &[nam], &scoring_function, &params()).unwrap();
+                total.0 += info.score;
+                total.1 += info.correct;
+                total.2 += info.incorrect;
+                total.3 += info.no_value;
+            }
+            return total;
+        };
+        with_names(&data, |_py, data| {
+            let evaluate = |leave_one_out: bool, exclude_duplicates: bool| {
+                return evaluate_model(
+                    data.clone(),
+                    data.clone(),
+                    &scoring_function,
+                    &params(),
+                    None,
+                    None,
+                    false,
+                    leave_one_out,
+                    exclude_duplicates,
+                );
+            };
+            let close = |info: ScoreInfo, expected: (f64, i32, i32, i32)| {
+                let counts = (info.correct, info.incorrect, info.no_value);
+                return (info.score - expected.0).abs() < 1e-9
+                    && counts == (expected.1, expected.2, expected.3);
+            };
+            let without_duplicates = evaluate(true, true).unwrap();
+            assert!(close(
+                evaluate(true, false).unwrap(),
+                expected(LeaveOut::SameId)
+            ));
+            assert!(close(
+                without_duplicates.clone(),
+                expected(LeaveOut::SameIdOrDuplicate)
+            ));
+            assert_ne!(
+                summary(&without_duplicates),
+                summary(&evaluate(true, false).unwrap())
+            );
+            assert!(evaluate(false, true).is_err());
+        });
+    }
+
+    #[test]
+    fn mutual_information_measures_how_fields_predict_collections() {
+        let train_data = [
+            name(Some(1), 1, None, &[1], 5, 1),
+            name(Some(1), 1, None, &[], 5, 2),
+            name(Some(2), 2, None, &[1], 5, 3),
+            name(Some(2), 2, None, &[], 5, 4),
+            name(None, 3, None, &[2], 6, 5),
+        ];
+        let information = with_names(&train_data, |_py, data| feature_mutual_information(data));
+        assert_eq!(
+            information,
+            std::collections::HashMap::from([
+                ("tl_country".to_string(), 1.0),
+                ("citation_group".to_string(), 0.0),
+                ("first_author".to_string(), 0.0),
+            ])
+        );
+        // The entropy of the collection less its entropy once the value is known, which only
+        // remains uncertain for the value 1
+        let entropy = |probs: &[f64]| -probs.iter().map(|p| p * p.log2()).sum::<f64>();
+        let expected = entropy(&[0.25, 0.75]) - 0.5 * entropy(&[0.5, 0.5]);
+        let pairs = [(Some(1), 1), (Some(1), 2), (Some(2), 2), (Some(2), 2)];
+        assert!((mutual_information(pairs.into_iter()) - expected).abs() < 1e-12);
+        assert_eq!(mutual_information(std::iter::empty()), 0.0);
+    }
+
+    #[test]
+    fn gated_top_choice_blends_the_two_models() {
+        let data = clustered_data(26000, 41);
+        let (query, train_data) = (&data[0], &data[1..]);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let params_author = Params {
+            author_boost: 10.0,
+            ..params()
+        };
+        let params_year = Params {
+            year_factor: 3.0,
+            probability_cutoff: 0.9,
+            ..params()
+        };
+        let author_probs = get_probs_impl(query, &train_names, &params_author).unwrap();
+        let year_probs = get_probs_impl(query, &train_names, &params_year).unwrap();
+        let mut blend = author_probs.clone();
+        for (collection, prob) in year_probs.iter() {
+            *blend.entry(*collection).or_insert(0.0) += prob;
+        }
+        for prob in blend.values_mut() {
+            *prob /= 2.0;
+        }
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let query = Bound::new(py, query.clone()).unwrap();
+            let gated = |gate: &str| {
+                let gate_fn = py.eval_bound(gate, None, None).unwrap();
+                return gated_top_choice(
+                    py,
+                    &query,
+                    bound(py, train_data),
+                    &params_author,
+                    &params_year,
+                    &gate_fn,
+                );
+            };
+            let author_only = gated("lambda query: (2.0, 0.0)").unwrap();
+            let expected = get_top_choice_impl(query.get(), &train_names, &params_author);
+            assert!(same_choice(author_only, expected.unwrap()));
+            let year_only = gated("lambda query: (0.0, 1.0)").unwrap();
+            let expected = get_top_choice_impl(query.get(), &train_names, &params_year);
+            assert!(same_choice(year_only, expected.unwrap()));
+            assert_ne!(author_only, year_only);
+            // On a tie the cutoff is params_author's
+            let blended = gated("lambda query: (1.0, 1.0)").unwrap();
+            let author_scores = get_raw_scores_impl(query.get(), &train_names, &params_author);
+            let expected = author_scores.unwrap().choose(&blend, &params_author);
+            assert!(same_choice(blended, expected));
+            assert!(gated("lambda query: (-1.0, 2.0)").is_err());
+            assert!(gated("lambda query: (0.0, 0.0)").is_err());
+            assert!(gated("lambda query: 1.0").is_err());
+        });
+    }
+
+    #[test]
+    fn novelty_threshold_maximizes_balanced_accuracy() {
+        let data = clustered_data(27000, 90);
+        let (novel, known): (Vec<NameData>, Vec<NameData>) =
+            data.into_iter().partition(|nam| nam.collection == Some(4));
+        let (train_data, test_data) = known.split_at(40);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        // A constant unknown bucket stands out against the low scores of novel names
+        let params = Params {
+            unknown_bucket_score: Some(1.0),
+            ..params()
+        };
+        let unknown_prob = |nam: &NameData| {
+            return get_probs_impl(nam, &train_names, &params).unwrap()[&0];
+        };
+        let known_probs: Vec<f64> = test_data.iter().map(unknown_prob).collect();
+        let novel_probs: Vec<f64> = novel.iter().map(unknown_prob).collect();
+        let balanced_accuracy = |threshold: f64| {
+            let novel_share = novel_probs
+                .iter()
+                .filter(|prob| **prob >= threshold)
+                .count();
+            let known_share = known_probs.iter().filter(|prob| **prob < threshold).count();
+            return (novel_share as f64 / novel_probs.len() as f64
+                + known_share as f64 / known_probs.len() as f64)
+                / 2.0;
+        };
+        let threshold = with_names(train_data, |py, train_data| {
+            let fit = |test_data: &[NameData], novel_test_data: &[NameData]| {
+                return fit_novelty_threshold(
+                    train_data.clone(),
+                    bound(py, test_data),
+                    bound(py, novel_test_data),
+                    &params,
+                );
+            };
+            assert!(fit(&[], &novel).is_err());
+            assert!(fit(test_data, &[]).is_err());
+            return fit(test_data, &novel).unwrap();
+        });
+        let best = balanced_accuracy(threshold);
+        assert!(best > 0.5);
+        for candidate in known_probs.iter().chain(&novel_probs) {
+            let accuracy = balanced_accuracy(*candidate);
+            assert!(accuracy <= best + 1e-12);
+            // Ties go to the lowest threshold
+            if (accuracy - best).abs() < 1e-12 {
+                assert!(threshold <= candidate + 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn params_grid_covers_every_combination_in_order() {
+        let grid = |extra: &[(&str, &[f64])]| {
+            let mut grids: std::collections::HashMap<String, Vec<f64>> = [
+                ("country_boost", 2.0),
+                ("cg_boost", 2.0),
+                ("year_factor", 1.1),
+                ("year_boost", 1.0),
+                ("score_cutoff", 0.5),
+                ("probability_cutoff", 0.0),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), vec![value]))
+            .collect();
+            for (name, values) in extra {
+                grids.insert(name.to_string(), values.to_vec());
+            }
+            pyo3::prepare_freethreaded_python();
+            return Python::with_gil(|py| build_params_grid(py, grids));
+        };
+        let combinations = grid(&[
+            ("author_boost", &[1.0, 3.0, 5.0]),
+            ("min_margin", &[0.0, 0.1]),
+        ]);
+        let values: Vec<(f64, f64)> = combinations
+            .unwrap()
+            .iter()
+            .map(|params| {
+                // The parameters left out keep their defaults
+                assert_eq!(params.author_overlap_exponent, 1.0);
+                assert!(params.normalize_with_unknown);
+                return (params.author_boost, params.min_margin);
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (1.0, 0.0),
+                (1.0, 0.1),
+                (3.0, 0.0),
+                (3.0, 0.1),
+                (5.0, 0.0),
+                (5.0, 0.1)
+            ]
+        );
+        assert!(grid(&[]).is_err());
+        assert!(grid(&[("author_boost", &[])]).is_err());
+        assert!(grid(&[("author_boost", &[1.0]), ("no_such_param", &[1.0])]).is_err());
+    }
+
+    #[test]
+    fn explanation_agrees_with_the_separate_functions() {
+        let data = clustered_data(28000, 41);
+        let (query, train_data) = (&data[0], &data[1..]);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let (explanation, influential, unpredicted) = with_names(train_data, |_py, bound_train| {
+            let unpredicted = Params {
+                probability_cutoff: 1.0,
+                ..params()
+            };
+            return (
+                explain_prediction(query, bound_train.clone(), &params()).unwrap(),
+                most_influential(query, bound_train.clone(), &params()).unwrap(),
+                explain_prediction(query, bound_train, &unpredicted).unwrap(),
+            );
+        });
+        let top_choice = get_top_choice_impl(query, &train_names, &params()).unwrap();
+        let (collection, probability) = top_choice.unwrap();
+        let choice = Some((
+            explanation.collection.unwrap(),
+            explanation.probability.unwrap(),
+        ));
+        assert!(same_choice(choice, top_choice));
+        let probs = get_probs_impl(query, &train_names, &params()).unwrap();
+        let margin = probability - runner_up_probability(&probs, collection);
+        assert!((explanation.margin.unwrap() - margin).abs() < 1e-12);
+        let (name_id, contribution) = influential.unwrap();
+        assert_eq!(explanation.influential_name_id, Some(name_id));
+        assert_eq!(explanation.contribution, Some(contribution));
+        let influential_name = train_data
+            .iter()
+            .find(|nam| nam.name_id == name_id)
+            .unwrap();
+        let expected = field_match(query, influential_name);
+        let explained = explanation.field_match.unwrap();
+        assert_eq!(
+            (
+                explained.country_match,
+                explained.cg_match,
+                explained.authors_equal,
+                explained.shared_author_count,
+                explained.year_difference
+            ),
+            (
+                expected.country_match,
+                expected.cg_match,
+                expected.authors_equal,
+                expected.shared_author_count,
+                expected.year_difference
+            )
+        );
+        assert!(unpredicted.collection.is_none() && unpredicted.probability.is_none());
+        assert!(unpredicted.margin.is_none() && unpredicted.influential_name_id.is_none());
+        assert!(unpredicted.contribution.is_none() && unpredicted.field_match.is_none());
+    }
+
+    #[test]
+    fn calibration_fits_a_loss_minimizing_temperature() {
+        let probs = std::collections::HashMap::from([(0, 0.2), (1, 0.5), (2, 0.3)]);
+        assert_probs_close(&apply_temperature(&probs, 1.0), &probs);
+        let sharpened = apply_temperature(&probs, 0.5);
+        let expected_total = 0.04 + 0.25 + 0.09;
+        assert!((sharpened[&1] - 0.25 / expected_total).abs() < 1e-12);
+        assert!((sharpened.values().sum::<f64>() - 1.0).abs() < 1e-12);
+        let data = clustered_data(29000, 100);
+        let (train_data, test_data) = data.split_at(40);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let items: Vec<(std::collections::HashMap<i32, f64>, i32)> = test_data
+            .iter()
+            .map(|nam| {
+                let probs = get_probs_impl(nam, &train_names, &params()).unwrap();
+                return (probs, nam.collection.unwrap());
+            })
+            .collect();
+        let temperature = fit_temperature(&items);
+        assert_ne!(temperature, 1.0);
+        let loss = mean_log_loss(&items, temperature);
+        for other in [1.0, temperature * 1.05, temperature / 1.05] {
+            assert!(loss <= mean_log_loss(&items, other));
+        }
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        let (fitted, info, log_loss) = with_names(train_data, |py, train_data| {
+            let test_data = bound(py, test_data);
+            return evaluate_calibrated(
+                train_data,
+                test_data.clone(),
+                test_data,
+                &scoring_function,
+                &params(),
+            )
+            .unwrap();
+        });
+        assert!((fitted - temperature).abs() < 1e-9);
+        assert!((log_loss - loss).abs() < 1e-9);
+        // Without a cutoff the temperature cannot change which collection is most probable
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let expected =
+            evaluate_model_impl(&train_names, &test_names, &scoring_function, &params()).unwrap();
+        assert_eq!(summary(&info), summary(&expected));
+    }
+
+    #[test]
+    fn ties_are_top_choices_within_epsilon() {
+        let (query, train_data) = tie_fixture();
+        let data = clustered_data(30000, 60);
+        let train_names: Vec<&NameData> = data[..30].iter().collect();
+        let close_calls = data[30..]
+            .iter()
+            .filter(|nam| {
+                let probs = get_probs_impl(nam, &train_names, &params()).unwrap();
+                let (collection, probability) = best_choice(&probs).unwrap();
+                return probs.len() >= 2
+                    && probability - runner_up_probability(&probs, collection) <= 0.1;
+            })
+            .count();
+        assert!(close_calls > 0 && close_calls < 30);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let train = bound(py, &train_data);
+            let test = bound(py, &[query]);
+            assert_eq!(
+                count_ties(train.clone(), test.clone(), &params(), 1e-12).unwrap(),
+                1
+            );
+            assert_eq!(count_ties(train, test, &params(), -1.0).unwrap(), 0);
+            let train = bound(py, &data[..30]);
+            let test = bound(py, &data[30..]);
+            let counted = count_ties(train, test, &params(), 0.1).unwrap();
+            assert_eq!(counted as usize, close_calls);
+        });
+    }
+
+    #[test]
+    fn min_prob_drops_unlikely_collections() {
+        let data = clustered_data(31000, 40);
+        let query = &data[0];
+        let train_names: Vec<&NameData> = data[1..].iter().collect();
+        let probs = get_probs_impl(query, &train_names, &params()).unwrap();
+        let min_prob = 0.2;
+        let expected: std::collections::HashMap<i32, f64> = probs
+            .iter()
+            .filter(|(_, prob)| **prob >= min_prob)
+            .map(|(collection, prob)| (*collection, *prob))
+            .collect();
+        assert!(!expected.is_empty() && expected.len() < probs.len());
+        let total: f64 = expected.values().sum();
+        with_names(&data[1..], |_py, train_data| {
+            let call = |min_prob, renormalize| {
+                return get_probs(
+                    query,
+                    train_data.clone(),
+                    &params(),
+                    None,
+                    None,
+                    min_prob,
+                    renormalize,
+                )
+                .unwrap();
+            };
+            assert_probs_close(&call(None, false), &probs);
+            assert_probs_close(&call(None, true), &probs);
+            assert_probs_close(&call(Some(min_prob), false), &expected);
+            let renormalized = call(Some(min_prob), true);
+            assert_eq!(renormalized.len(), expected.len());
+            for (collection, prob) in &expected {
+                assert!((renormalized[collection] - prob / total).abs() < 1e-12);
+            }
+            // Nothing is left to renormalize, which is not an error
+            assert!(call(Some(1.5), true).is_empty());
+        });
+    }
+
+    #[test]
+    fn extra_boost_multiplies_the_score() {
+        let (query, train_data) = tie_fixture();
+        let mut names = vec![query];
+        names.extend(train_data);
+        with_names(&names, |py, bound_names| {
+            let (query, others) = bound_names.split_first().unwrap();
+            let boost_by_id = py
+                .eval_bound("lambda nam1, nam2: nam2.name_id + nam1.name_id", None, None)
+                .unwrap();
+            for other in others {
+                let score = get_score(query, other, &params(), None).unwrap();
+                assert_eq!(
+                    score,
+                    get_score_impl(query.get(), other.get(), &params()).unwrap()
+                );
+                let boosted = get_score(query, other, &params(), Some(&boost_by_id)).unwrap();
+                assert_eq!(boosted, score * f64::from(other.get().name_id));
+            }
+            let overflow = py
+                .eval_bound("lambda nam1, nam2: float('inf')", None, None)
+                .unwrap();
+            let boosted = get_score(query, &others[2], &params(), Some(&overflow)).unwrap();
+            assert_eq!(boosted, f64::MAX);
+            let not_a_number = py
+                .eval_bound("lambda nam1, nam2: 'boost'", None, None)
+                .unwrap();
+            assert!(get_score(query, &others[2], &params(), Some(&not_a_number)).is_err());
+            let raises = py
+                .eval_bound("lambda nam1, nam2: int('boost')", None, None)
+                .unwrap();
+            let err = get_score(query, &others[2], &params(), Some(&raises)).unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyValueError>(py));
+        });
+    }
+
+    #[test]
+    fn top_prob_distribution_bins_every_top_probability() {
+        let data = clustered_data(32000, 60);
+        let train_names: Vec<&NameData> = data[..30].iter().collect();
+        let mut expected = [0; 3];
+        for nam in &data[30..] {
+            let probs = get_probs_impl(nam, &train_names, &params()).unwrap();
+            let (_, probability) = best_choice(&probs).unwrap();
+            expected[((probability * 3.0) as usize).min(2)] += 1;
+        }
+        assert!(expected.iter().filter(|count| **count > 0).count() > 1);
+        with_names(&data[..30], |py, train_data| {
+            let test_data = bound(py, &data[30..]);
+            let bins =
+                top_prob_distribution(train_data.clone(), test_data.clone(), &params(), 3).unwrap();
+            let edges: Vec<(f64, f64)> = bins.iter().map(|(low, high, _)| (*low, *high)).collect();
+            assert_eq!(
+                edges,
+                [(0.0, 1.0 / 3.0), (1.0 / 3.0, 2.0 / 3.0), (2.0 / 3.0, 1.0)]
+            );
+            let counts: Vec<i32> = bins.iter().map(|(_, _, count)| *count).collect();
+            assert_eq!(counts, expected);
+            assert!(top_prob_distribution(train_data, test_data, &params(), 0).is_err());
+        });
+    }
+
+    #[test]
+    fn author_counts_evaluate_their_own_names() {
+        let mut data = clustered_data(33000, 70);
+        data[69].collection = None;
+        let (train_data, test_data) = data.split_at(40);
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let by_count = with_names(train_data, |py, train_data| {
+            return accuracy_by_author_count(
+                train_data,
+                bound(py, test_data),
+                &scoring_function,
+                &params(),
+            )
+            .unwrap();
+        });
+        let labelled: Vec<&NameData> = test_data
+            .iter()
+            .filter(|nam| nam.collection.is_some())
+            .collect();
+        let mut expected_counts: Vec<i32> = labelled
+            .iter()
+            .map(|nam| nam.authors.len() as i32)
+            .collect();
+        expected_counts.sort();
+        expected_counts.dedup();
+        assert!(expected_counts.len() > 1);
+        let mut counts: Vec<i32> = by_count.keys().copied().collect();
+        counts.sort();
+        assert_eq!(counts, expected_counts);
+        for (count, info) in &by_count {
+            let with_count: Vec<&NameData> = labelled
+                .iter()
+                .copied()
+                .filter(|nam| nam.authors.len() as i32 == *count)
+                .collect();
+            let expected =
+                evaluate_model_impl(&train_names, &with_count, &scoring_function, &params())
+                    .unwrap();
+            assert_eq!(summary(info), summary(&expected));
+        }
+    }
+
+    #[test]
+    fn cached_predictions_reload_only_for_the_same_inputs() {
+        let data = clustered_data(8000, 40);
+        let (train_data, test_data) = data.split_at(30);
+        let path = std::env::temp_dir().join(format!(
+            "repoguess-cached-predictions-{}.txt",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let changed = Params {
+            author_boost: 5.0,
+            ..params()
+        };
+        let scoring_function = ScoringFunction::new(1.0, 0.2, 0.0);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let load = |params: &Params| {
+                let (train_data, test_data) = (bound(py, train_data), bound(py, test_data));
+                return CachedPredictions::load_or_compute(
+                    path.clone(),
+                    train_data,
+                    test_data,
+                    params,
+                )
+                .unwrap();
+            };
+            let computed = load(&params());
+            assert!(!computed.loaded);
+            let reloaded = load(&params());
+            assert!(reloaded.loaded);
+            assert_eq!(reloaded.key, computed.key);
+            assert_eq!(reloaded.choices, computed.choices);
+            // Other params give another key, so the saved file is recomputed and replaced
+            let recomputed = load(&changed);
+            assert!(!recomputed.loaded);
+            assert_ne!(recomputed.key, computed.key);
+            let fresh =
+                CachedPredictions::new(bound(py, train_data), bound(py, test_data), &changed);
+            // Summing in another order can change the last bit of a probability
+            let fresh = fresh.unwrap();
+            assert_eq!(recomputed.choices.len(), fresh.choices.len());
+            for (choice, fresh_choice) in recomputed.choices.iter().zip(fresh.choices.iter()) {
+                let (probability, correct) = choice.unwrap();
+                let (fresh_probability, fresh_correct) = fresh_choice.unwrap();
+                assert!((probability - fresh_probability).abs() < 1e-12);
+                assert_eq!(correct, fresh_correct);
+            }
+            assert!(load(&changed).loaded);
+            assert!(!load(&params()).loaded);
+            assert_eq!(
+                summary(&reloaded.at_cutoff(&scoring_function, 0.3)),
+                summary(&computed.at_cutoff(&scoring_function, 0.3))
+            );
+        });
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn distance_mode_scores_are_similarities() {
+        let query = name(None, 1, Some(1900), &[1, 2], 1, 0);
+        let train_datum = name(Some(1), 2, Some(1910), &[1], 2, 1);
+        let params = Params {
+            score_mode: ScoreMode::Distance,
+            ..params()
+        };
+        let distance = get_distance(&query, &train_datum, &params);
+        let score = get_score_impl(&query, &train_datum, &params).unwrap();
+        assert!(distance > 0.0);
+        assert_eq!(score, (-distance).exp());
+        assert_eq!(get_score_impl(&query, &query, &params).unwrap(), 0.0);
+        assert_eq!(get_distance(&query, &query, &params), f64::INFINITY);
+    }
+
+    #[test]
+    fn bottleneck_param_takes_the_best_single_change() {
+        let data = clustered_data(34000, 70);
+        let (train_data, test_data) = data.split_at(40);
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let candidates = [
+            ("score_cutoff", [0.0, 0.5, 50.0]),
+            ("author_boost", [1.0, 3.0, 30.0]),
+        ];
+        // Alphabetical order, then the order of the values, which decides ties
+        let mut expected: Option<(&str, f64, f64)> = None;
+        for (name, values) in [candidates[1], candidates[0]] {
+            for value in values {
+                let trial = params().with_value(name, value).unwrap();
+                let score =
+                    evaluate_model_impl(&train_names, &test_names, &scoring_function, &trial)
+                        .unwrap()
+                        .score;
+                if expected.is_none_or(|(_, _, best)| score > best) {
+                    expected = Some((name, value, score));
+                }
+            }
+        }
+        let (expected_name, expected_value, expected_score) = expected.unwrap();
+        with_names(train_data, |py, train_data| {
+            let bottleneck = |candidates: std::collections::HashMap<String, Vec<f64>>| {
+                return bottleneck_param(
+                    py,
+                    train_data.clone(),
+                    bound(py, test_data),
+                    &scoring_function,
+                    &params(),
+                    candidates,
+                );
+            };
+            let grid = candidates
+                .iter()
+                .map(|(name, values)| (name.to_string(), values.to_vec()))
+                .collect();
+            let (name, value, info) = bottleneck(grid).unwrap();
+            assert_eq!((name.as_str(), value), (expected_name, expected_value));
+            assert_eq!(info.score, expected_score);
+            assert!(bottleneck(std::collections::HashMap::new()).is_err());
+            let no_values = std::collections::HashMap::from([("author_boost".to_string(), vec![])]);
+            assert!(bottleneck(no_values).is_err());
+            let unknown = std::collections::HashMap::from([("boost".to_string(), vec![1.0])]);
+            assert!(bottleneck(unknown).is_err());
+        });
+    }
+
+    #[test]
+    fn simulated_collections_move_names_in_both_sets() {
+        let data = clustered_data(35000, 70);
+        let (train_data, test_data) = data.split_at(40);
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        let reassign: std::collections::HashMap<i32, i32> = data
+            .iter()
+            .filter(|nam| nam.collection == Some(4) && nam.name_id % 2 == 0)
+            .map(|nam| (nam.name_id, 5))
+            .collect();
+        let moved_data: Vec<NameData> = data
+            .iter()
+            .map(|nam| match reassign.get(&nam.name_id) {
+                Some(collection) => NameData {
+                    collection: Some(*collection),
+                    ..nam.clone()
+                },
+                None => nam.clone(),
+            })
+            .collect();
+        let evaluate = |data: &[NameData]| {
+            let train_names: Vec<&NameData> = data[..40].iter().collect();
+            let test_names: Vec<&NameData> = data[40..].iter().collect();
+            return evaluate_model_impl(&train_names, &test_names, &scoring_function, &params())
+                .unwrap();
+        };
+        assert!(summary(&evaluate(&data)) != summary(&evaluate(&moved_data)));
+        with_names(train_data, |py, train_data| {
+            let simulate = |reassign| {
+                return simulate_new_collection(
+                    train_data.clone(),
+                    bound(py, test_data),
+                    &scoring_function,
+                    &params(),
+                    reassign,
+                );
+            };
+            let (before, after) = simulate(reassign.clone()).unwrap();
+            assert_eq!(summary(&before), summary(&evaluate(&data)));
+            assert_eq!(summary(&after), summary(&evaluate(&moved_data)));
+            let (before, after) = simulate(std::collections::HashMap::new()).unwrap();
+            assert_eq!(summary(&before), summary(&after));
+            let mut to_unknown = reassign;
+            to_unknown.insert(data[0].name_id, 0);
+            assert!(simulate(to_unknown).is_err());
+        });
+    }
+
+    #[test]
+    fn winning_contributions_add_up_to_the_collection_score() {
+        let data = clustered_data(36000, 40);
+        let query = &data[0];
+        let train_names: Vec<&NameData> = data[1..].iter().collect();
+        let (predicted, _) = get_top_choice_impl(query, &train_names, &params())
+            .unwrap()
+            .unwrap();
+        assert_ne!(predicted, 0);
+        let raw_scores = get_raw_scores_impl(query, &train_names, &params()).unwrap();
+        let entry = &raw_scores.collections[&predicted];
+        with_names(&data[1..], |_py, train_data| {
+            let contributions = winning_contributions(query, train_data, &params()).unwrap();
+            assert!(contributions.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+            for (name_id, contribution) in &contributions {
+                let train_datum = data.iter().find(|nam| nam.name_id == *name_id).unwrap();
+                assert_eq!(train_datum.collection, Some(predicted));
+                assert!(*contribution > 0.0);
+            }
+            let in_collection = train_names
+                .iter()
+                .filter(|nam| nam.collection == Some(predicted))
+                .count();
+            assert!(contributions.len() <= in_collection);
+            let total: f64 = contributions
+                .iter()
+                .map(|(_, contribution)| contribution)
+                .sum();
+            assert!((total - entry.total - entry.below_cutoff_total).abs() < 1e-9);
+        });
+        // With no training names the unknown bucket wins, and it has no contributors
+        assert!(winning_contributions(query, Vec::new(), &params())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn expected_score_with_full_confidence_is_the_score() {
+        let (query, mut train_data) = tie_fixture();
+        // The highest collection id must not overflow
+        train_data.push(name(Some(i32::MAX), 3, None, &[9], 3, 11));
+        train_data.push(name(Some(i32::MAX), 3, None, &[9], 3, 12));
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        let other = name(Some(i32::MAX), 3, None, &[9], 3, 10);
+        let test_data = [&query, &other];
+        let scoring_function = ScoringFunction::new(1.0, 0.2, 0.5);
+        let params = params();
+        assert_eq!(
+            get_top_choice_impl(&other, &train_data, &params)
+                .unwrap()
+                .map(|(collection, _)| collection),
+            Some(i32::MAX)
+        );
+        let score = evaluate_model_impl(&train_data, &test_data, &scoring_function, &params)
+            .unwrap()
+            .score;
+        let expected = |confidences: &[f64]| {
+            return expected_score_impl(
+                &train_data,
+                &test_data,
+                confidences,
+                &scoring_function,
+                &params,
+            )
+            .unwrap();
+        };
+        assert_eq!(expected(&[1.0, 1.0]), score);
+        assert!(expected(&[0.5, 0.5]) < score);
+    }
+
+    #[test]
+    fn weighted_miss_counts_against_the_score() {
+        let mut counter = ScoreCounter::default();
+        counter.record_weighted(Some(1), 1, 0.75, None);
+        counter.record_weighted_miss(0.25);
+        let info = counter.finish(&ScoringFunction::new(2.0, 0.0, 0.5));
+        assert_eq!(info.weighted_incorrect, 0.25);
+        assert_eq!(info.score, 0.25);
+    }
+
+    #[test]
+    fn pair_scores_do_not_depend_on_order() {
+        let short = name(Some(1), 1, Some(1900), &[1], 1, 1);
+        let long = name(Some(1), 1, Some(1950), &[1, 2, 3], 1, 2);
+        let params = Params {
+            author_overlap_denominator: AuthorOverlapDenominator::Query,
+            author_year_interaction: 1.0,
+            ..params()
+        };
+        assert!(
+            get_score_impl(&short, &long, &params).unwrap()
+                > get_score_impl(&long, &short, &params).unwrap()
+        );
+        let forward = symmetric_score(&short, &long, &params).unwrap();
+        assert_eq!(forward, symmetric_score(&long, &short, &params).unwrap());
+        assert_eq!(forward, get_score_impl(&short, &long, &params).unwrap());
+    }
+
+    #[test]
+    fn similar_names_cluster_together() {
+        let data = [
+            name(Some(1), 1, Some(1900), &[1, 2], 1, 1),
+            name(Some(2), 2, Some(1900), &[5, 6], 2, 2),
+            name(Some(1), 1, Some(1900), &[1, 2], 1, 3),
+            name(Some(3), 3, Some(1900), &[9], 3, 4),
+            name(Some(2), 2, Some(1900), &[5, 6], 2, 5),
+        ];
+        // Matching names score 2 × 2 × 3 = 12 and unrelated ones 1
+        let clusters = with_names(&data, |py, data| {
+            return cluster_by_similarity(py, data, &params(), 5.0).unwrap();
+        });
+        assert_eq!(clusters, vec![vec![1, 3], vec![2, 5], vec![4]]);
+        let everything = with_names(&data, |py, data| {
+            return cluster_by_similarity(py, data, &params(), 0.5).unwrap();
+        });
+        assert_eq!(everything, vec![vec![1, 2, 3, 4, 5]]);
+    }
+
+    #[test]
+    fn scanned_parameter_matches_evaluate_model() {
+        let data = clustered_data(37000, 70);
+        let (train_data, test_data) = data.split_at(40);
+        let scoring_function = ScoringFunction::new(1.0, 0.25, 0.0);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let test_names: Vec<&NameData> = test_data.iter().collect();
+        let values = [50.0, 0.0, 2.0, 0.5];
+        with_names(train_data, |py, train_data| {
+            let scan = |param_name, values: &[f64]| {
+                return scan_parameter(
+                    py,
+                    train_data.clone(),
+                    bound(py, test_data),
+                    &scoring_function,
+                    &params(),
+                    param_name,
+                    values.to_vec(),
+                );
+            };
+            let scanned = scan("score_cutoff", &values).unwrap();
+            assert_eq!(scanned.len(), values.len());
+            for ((value, info), expected_value) in scanned.iter().zip(values) {
+                assert_eq!(*value, expected_value);
+                let params = params().with_value("score_cutoff", expected_value).unwrap();
+                let expected =
+                    evaluate_model_impl(&train_names, &test_names, &scoring_function, &params)
+                        .unwrap();
+                assert_eq!(summary(info), summary(&expected));
+            }
+            assert!(scan("score_cutoff", &[]).unwrap().is_empty());
+            assert!(scan("cutoff", &values).is_err());
+        });
+    }
+
+    #[test]
+    fn nn_blend_moves_from_sums_to_best_matches() {
+        let query = name(None, 1, None, &[1], 1, 0);
+        // Collection 1 has matches scoring 3 and 3, collection 2 a single match scoring 4
+        let train_data = [
+            name(Some(1), 2, None, &[1], 2, 1),
+            name(Some(1), 2, None, &[1], 3, 2),
+            name(Some(2), 1, None, &[2], 1, 3),
+        ];
+        let train_data: Vec<&NameData> = train_data.iter().collect();
+        let totals = |nn_blend: f64| {
+            // A constant unknown bucket, so that it does not tie with the best match
+            let params = Params {
+                nn_blend,
+                unknown_bucket_score: Some(1.0),
+                ..params()
+            };
+            let raw_scores = get_raw_scores_impl(&query, &train_data, &params).unwrap();
+            return (raw_scores.totals(), raw_scores.top_choice(&params));
+        };
+        let (summed, summed_choice) = totals(0.0);
+        assert_eq!((summed[&1], summed[&2]), (6.0, 4.0));
+        assert_eq!(summed_choice.unwrap().0, 1);
+        let (nearest, nearest_choice) = totals(1.0);
+        assert_eq!((nearest[&1], nearest[&2]), (3.0, 4.0));
+        let params = Params {
+            nn_blend: 1.0,
+            ..params()
+        };
+        let nearest_neighbor = nearest_neighbor_choice_impl(&query, &train_data, &params);
+        assert_eq!(
+            nearest_choice.unwrap().0,
+            nearest_neighbor.unwrap().unwrap().0
+        );
+        let (blended, _) = totals(0.25);
+        assert_eq!((blended[&1], blended[&2]), (5.25, 4.0));
+    }
+
+    #[test]
+    fn balanced_cutoff_beats_every_other_cutoff() {
+        let mut data = clustered_data(38000, 70);
+        data[60].collection = None;
+        let (train_data, test_data) = data.split_at(40);
+        let train_names: Vec<&NameData> = train_data.iter().collect();
+        let choices: Vec<(i32, f64, i32)> = test_data
+            .iter()
+            .filter_map(|nam| {
+                let true_collection = nam.collection?;
+                let (collection, probability) = get_top_choice_impl(nam, &train_names, &params())
+                    .unwrap()
+                    .unwrap();
+                return Some((collection, probability, true_collection));
+            })
+            .collect();
+        let balanced_accuracy = |cutoff: f64| {
+            let mut collections: Vec<i32> = choices.iter().map(|choice| choice.2).collect();
+            collections.sort();
+            collections.dedup();
+            let total: f64 = collections
+                .iter()
+                .map(|true_collection| {
+                    let predicted: Vec<bool> = choices
+                        .iter()
+                        .filter(|choice| choice.2 == *true_collection && choice.1 > cutoff)
+                        .map(|choice| choice.0 == choice.2)
+                        .collect();
+                    if predicted.is_empty() {
+                        return 0.0;
+                    }
+                    let correct = predicted.iter().filter(|correct| **correct).count();
+                    return correct as f64 / predicted.len() as f64;
+                })
+                .sum();
+            return total / collections.len() as f64;
+        };
+        let (cutoff, accuracy) = with_names(train_data, |py, train_data| {
+            assert!(
+                best_cutoff_balanced(train_data.clone(), bound(py, &data[60..61]), &params())
+                    .is_err()
+            );
+            return best_cutoff_balanced(train_data, bound(py, test_data), &params()).unwrap();
+        });
+        assert!(cutoff > 0.0);
+        // The cutoff excludes the choices with exactly its probability
+        assert!((accuracy - balanced_accuracy(cutoff + 1e-12)).abs() < 1e-12);
+        assert!(balanced_accuracy(cutoff - 1e-9) < accuracy - 1e-12);
+        for step in 0..=100 {
+            assert!(balanced_accuracy(step as f64 / 100.0) <= accuracy + 1e-12);
+        }
+        for (_, probability, _) in &choices {
+            assert!(balanced_accuracy(*probability + 1e-12) <= accuracy + 1e-12);
+        }
+    }
 }